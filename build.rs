@@ -2,11 +2,64 @@
 extern crate cbindgen;
 
 use std::env;
+use std::fs;
+use std::path::Path;
 
 fn main() {
     let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out_dir = env::var("OUT_DIR").unwrap();
 
-    cbindgen::generate(&crate_dir)
+    // cbindgen does not expand macros, so the functions defined by
+    // `sample_type_api!` are written out for it.
+    let bindings = fs::read_to_string(Path::new(&crate_dir).join("src/cbindings.rs")).unwrap();
+    let api_path = Path::new(&out_dir).join("cbindings_api.rs");
+    fs::write(&api_path, expand_sample_type_api(&bindings)).unwrap();
+
+    cbindgen::Builder::new()
+        .with_config(cbindgen::Config::from_root_or_default(&crate_dir))
+        .with_crate(&crate_dir)
+        .with_src(&api_path)
+        .generate()
         .unwrap()
         .write_to_file("include/hao/fx-collection-rs/cbindings.h");
 }
+
+/// Writes out the functions of every `sample_type_api!` invocation, first
+/// the `f32` and then the `f64` ones. cbindgen only reads the signatures.
+fn expand_sample_type_api(bindings: &str) -> String {
+    const INVOCATION: &str = "sample_type_api! {";
+
+    let mut expanded = String::new();
+    let mut lines = bindings.lines();
+    while let Some(line) = lines.next() {
+        if line != INVOCATION {
+            continue;
+        }
+
+        // Skips the module names.
+        lines.next();
+        let body: Vec<&str> = lines.by_ref().take_while(|line| *line != "}").collect();
+        for (variant, sample) in ["f32", "f64"].iter().enumerate() {
+            for line in &body {
+                expanded += &expand_line(line, variant, sample);
+                expanded.push('\n');
+            }
+        }
+    }
+
+    expanded
+}
+
+/// Picks the name of `variant` from `fn name, name_f64(` and replaces the
+/// sample type `T`.
+fn expand_line(line: &str, variant: usize, sample: &str) -> String {
+    let line = line.replace("<T>", &format!("<{}>", sample));
+    match line.trim_start().strip_prefix("fn ") {
+        Some(rest) => {
+            let (names, args) = rest.split_once('(').unwrap();
+            let name = names.split(", ").nth(variant).unwrap();
+            format!("#[no_mangle]\npub unsafe extern \"C\" fn {}({}", name, args)
+        }
+        None => line,
+    }
+}
@@ -0,0 +1,77 @@
+// Copyright(c) 2022 Hansen Audio.
+
+//! Offline renderer which applies the effects of this library to a WAV file.
+//!
+//! ```text
+//! fx-render <input.wav> <output.wav> [--preset <file>] [--<key> <value>]...
+//! ```
+//!
+//! Presets contain `key = value` lines using the same keys as the flags.
+
+mod options;
+mod render;
+mod wav;
+
+use std::process::ExitCode;
+use wav::Wav;
+
+const USAGE: &str = "\
+usage: fx-render <input.wav> <output.wav> [--preset <file>] [--<key> <value>]...
+
+host:   effects (gate,delay,multi-tap), tempo, project-time (quarters),
+        block-size, tail (seconds), bits (16, 24, 32f)
+gate:   gate-steps, gate-steps-right, gate-pattern-<n>[-right], gate-chain
+        (pattern:length,...), gate-chain-unit, gate-step-count, gate-step-len,
+        gate-step-timing (note, ms, hz), gate-step-time-ms, gate-step-rate-hz,
+        gate-mix, gate-width, gate-shuffle, gate-contour, gate-contour-attack,
+        gate-contour-release, gate-declick, gate-edge-mode, gate-stereo-mode,
+        gate-stereo-offset, gate-fade-curve, gate-release-target,
+        gate-humanize-seed, gate-humanize-level, gate-humanize-timing,
+        gate-humanize-contour, gate-delay, gate-fade-in, gate-release-at
+        (quarters), gate-release-len
+delay:  delay-buffer-size, delay-max-time-ms, delay-left, delay-right,
+        delay-timing (normalized, note, ms, samples), delay-note-len-left,
+        delay-note-len-right, delay-time-ms-left, delay-time-ms-right,
+        delay-samples-left, delay-samples-right, delay-feedback,
+        delay-feedback-left, delay-feedback-right, delay-feedback-balance,
+        delay-feedback-link (true, false), delay-feedback-matrix
+        (ll,lr,rl,rr), delay-feedback-mode (matrix, ping-pong),
+        delay-input-mode (mono-sum, stereo), delay-interpolation (linear,
+        hermite, lagrange, allpass, sinc), delay-time-change (glide,
+        crossfade, jump), delay-glide-rate, delay-wow-rate,
+        delay-wow-depth-ms, delay-flutter-rate, delay-flutter-depth-ms,
+        delay-wow-flutter-stereo-phase, delay-wow-flutter-noise,
+        delay-saturation (off, soft-clip, tape, tube),
+        delay-saturation-drive-db, delay-saturation-compensation (true,
+        false), delay-reverse (true, false), delay-freeze (true, false),
+        delay-mix, delay-mix-law (linear, equal-power), delay-dry-level,
+        delay-wet-level, delay-output-gain-db, delay-wet-only (true, false),
+        delay-lp, delay-hp
+taps:   multi-tap-taps (1-8), multi-tap-max-time-ms,
+        multi-tap-interpolation (as delay-interpolation),
+        multi-tap-time-change (jump, glide, crossfade),
+        multi-tap-glide-rate, and per tap <n> from 0: multi-tap-<n>-timing (ms, note), multi-tap-<n>-time-ms,
+        multi-tap-<n>-note-len, multi-tap-<n>-level, multi-tap-<n>-pan,
+        multi-tap-<n>-feedback, multi-tap-<n>-filter (off, lp, hp),
+        multi-tap-<n>-filter-freq";
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("fx-render: {}\n\n{}", err, USAGE);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<(), String> {
+    let options = options::parse_args(std::env::args().skip(1))?;
+
+    let input = Wav::read(&options.input)
+        .map_err(|err| format!("cannot read '{}': {}", options.input.display(), err))?;
+    let output = render::render(&input, &options.params)?;
+    output
+        .write(&options.output)
+        .map_err(|err| format!("cannot write '{}': {}", options.output.display(), err))
+}
@@ -0,0 +1,99 @@
+// Copyright(c) 2022 Hansen Audio.
+
+use std::fs;
+use std::path::PathBuf;
+
+pub type Param = (String, String);
+
+#[derive(Debug)]
+pub struct Options {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    /// Parameters in the order they were given. Later entries override
+    /// earlier ones, so CLI flags after `--preset` win over the preset.
+    pub params: Vec<Param>,
+}
+
+pub fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut positional = Vec::new();
+    let mut params = Vec::new();
+
+    while let Some(arg) = args.next() {
+        let Some(key) = arg.strip_prefix("--") else {
+            positional.push(PathBuf::from(arg));
+            continue;
+        };
+
+        let value = args
+            .next()
+            .ok_or_else(|| format!("missing value for --{}", key))?;
+
+        if key == "preset" {
+            let text = fs::read_to_string(&value)
+                .map_err(|err| format!("cannot read preset '{}': {}", value, err))?;
+            params.extend(parse_preset(&text)?);
+        } else {
+            params.push((key.to_string(), value));
+        }
+    }
+
+    let [input, output]: [PathBuf; 2] = positional
+        .try_into()
+        .map_err(|_| "expected an input and an output file".to_string())?;
+
+    Ok(Options {
+        input,
+        output,
+        params,
+    })
+}
+
+/// Parses `key = value` lines. Empty lines and lines starting with `#` are
+/// ignored. The keys are the CLI flags without the leading dashes.
+pub fn parse_preset(text: &str) -> Result<Vec<Param>, String> {
+    let mut params = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("preset line {}: expected 'key = value'", index + 1))?;
+        params.push((key.trim().to_string(), value.trim().to_string()));
+    }
+
+    Ok(params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_args_and_preset() {
+        let preset = parse_preset("# comment\n\ntempo = 128\ngate-steps = 1, 0\n").unwrap();
+        assert_eq!(
+            preset,
+            vec![
+                ("tempo".to_string(), "128".to_string()),
+                ("gate-steps".to_string(), "1, 0".to_string()),
+            ]
+        );
+        assert!(parse_preset("tempo 128").is_err());
+
+        let args = ["in.wav", "--tempo", "90", "out.wav"].map(String::from);
+        let options = parse_args(args.into_iter()).unwrap();
+        assert_eq!(options.input, PathBuf::from("in.wav"));
+        assert_eq!(options.output, PathBuf::from("out.wav"));
+        assert_eq!(
+            options.params,
+            vec![("tempo".to_string(), "90".to_string())]
+        );
+
+        let args = ["in.wav", "--tempo"].map(String::from);
+        assert!(parse_args(args.into_iter()).is_err());
+    }
+}
@@ -0,0 +1,627 @@
+// Copyright(c) 2022 Hansen Audio.
+
+use crate::options::Param;
+use crate::wav::{SampleFormat, Wav};
+use fx_collection_rs::multi_tap_delay::{MultiTapDelay, TapFilter, TapTiming, MAX_NUM_TAPS};
+use fx_collection_rs::stereo_delay::{
+    DelayTiming, FeedbackMode, InputMode, Interpolation, MixLaw, Saturation, StereoDelay,
+    TimeChangeMode,
+};
+use fx_collection_rs::trance_gate::{
+    ChainLengthUnit, EdgeMode, FadeCurve, ReleaseTarget, StepTiming, StereoMode, TranceGate,
+};
+use fx_collection_rs::{AudioFrame, NUM_CHANNELS};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Effect {
+    Gate,
+    Delay,
+    MultiTap,
+}
+
+/// Host side settings which are not parameters of an effect.
+#[derive(Debug)]
+struct Settings {
+    effects: Vec<Effect>,
+    tempo_bpm: f32,
+    project_time: f64,
+    block_size: usize,
+    tail_secs: f32,
+    format: Option<SampleFormat>,
+    gate_delay: f32,
+    gate_fade_in: f32,
+    gate_release_at: Option<f64>,
+    gate_release_len: f32,
+}
+
+impl Settings {
+    fn new() -> Self {
+        Self {
+            effects: Vec::new(),
+            tempo_bpm: 120.,
+            project_time: 0.,
+            block_size: 64,
+            tail_secs: 0.,
+            format: None,
+            gate_delay: 0.,
+            gate_fade_in: 0.,
+            gate_release_at: None,
+            gate_release_len: 0.,
+        }
+    }
+
+    /// Returns false if the key is not a host setting.
+    fn apply(&mut self, key: &str, value: &str) -> Result<bool, String> {
+        match key {
+            "effects" => {
+                self.effects = list(key, value, |name| match name {
+                    "gate" => Some(Effect::Gate),
+                    "delay" => Some(Effect::Delay),
+                    "multi-tap" => Some(Effect::MultiTap),
+                    _ => None,
+                })?
+            }
+            "tempo" => self.tempo_bpm = parse(key, value)?,
+            "project-time" => self.project_time = parse(key, value)?,
+            "block-size" => self.block_size = parse::<usize>(key, value)?.max(1),
+            "tail" => self.tail_secs = parse(key, value)?,
+            "bits" => {
+                self.format = Some(choice(key, value, |name| match name {
+                    "16" => Some(SampleFormat::Int16),
+                    "24" => Some(SampleFormat::Int24),
+                    "32f" => Some(SampleFormat::Float32),
+                    _ => None,
+                })?)
+            }
+            "gate-delay" => self.gate_delay = parse(key, value)?,
+            "gate-fade-in" => self.gate_fade_in = parse(key, value)?,
+            "gate-release-at" => self.gate_release_at = Some(parse(key, value)?),
+            "gate-release-len" => self.gate_release_len = parse(key, value)?,
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+}
+
+/// Renders the input through the selected effects. Project time is given in
+/// quarter notes and advances with the tempo, once per block like in a host.
+pub fn render(input: &Wav, params: &[Param]) -> Result<Wav, String> {
+    const SECS_PER_MINUTE: f64 = 60.;
+
+    let sample_rate = input.sample_rate as f32;
+    let mut settings = Settings::new();
+    let mut gate = TranceGate::new();
+    let mut delay = StereoDelay::new();
+    let mut multi_tap = MultiTapDelay::new();
+    gate.set_sample_rate(sample_rate);
+    delay.set_sample_rate(sample_rate);
+    multi_tap.set_sample_rate(sample_rate);
+
+    for (key, value) in params {
+        if settings.apply(key, value)? {
+            continue;
+        }
+
+        if let Some(gate_key) = key.strip_prefix("gate-") {
+            apply_gate_param(&mut gate, gate_key, value)?;
+        } else if let Some(delay_key) = key.strip_prefix("delay-") {
+            apply_delay_param(&mut delay, delay_key, value)?;
+        } else if let Some(multi_tap_key) = key.strip_prefix("multi-tap-") {
+            apply_multi_tap_param(&mut multi_tap, multi_tap_key, value)?;
+        } else {
+            return Err(format!("unknown parameter '{}'", key));
+        }
+    }
+
+    if settings.effects.is_empty() {
+        return Err("no effects selected, use --effects gate,delay".to_string());
+    }
+
+    gate.set_tempo(settings.tempo_bpm);
+    delay.set_tempo(settings.tempo_bpm);
+    multi_tap.set_tempo(settings.tempo_bpm);
+    gate.update_project_time_music(settings.project_time);
+    gate.trigger(settings.gate_delay, settings.gate_fade_in);
+    delay.reset_heads();
+    delay.clear_buffer();
+    multi_tap.clear_buffer();
+
+    let num_channels = input.num_channels;
+    let tail_frames = (settings.tail_secs.max(0.) * sample_rate) as usize;
+    let num_frames = input.num_frames() + tail_frames;
+    let quarters_per_sample = settings.tempo_bpm as f64 / SECS_PER_MINUTE / sample_rate as f64;
+
+    let mut samples = input.samples.clone();
+    samples.resize(num_frames * num_channels, 0.);
+
+    let mut project_time = settings.project_time;
+    let mut is_released = false;
+    for block in samples.chunks_mut(settings.block_size * num_channels) {
+        gate.update_project_time_music(project_time);
+        if let Some(release_at) = settings.gate_release_at {
+            if !is_released && project_time >= release_at {
+                gate.release(settings.gate_release_len);
+                is_released = true;
+            }
+        }
+
+        for frame in block.chunks_exact_mut(num_channels) {
+            process_frame(
+                frame,
+                &settings.effects,
+                &mut gate,
+                &mut delay,
+                &mut multi_tap,
+            );
+        }
+
+        project_time += (block.len() / num_channels) as f64 * quarters_per_sample;
+    }
+
+    Ok(Wav {
+        sample_rate: input.sample_rate,
+        num_channels,
+        format: settings.format.unwrap_or(input.format),
+        samples,
+    })
+}
+
+/// Processes the first two channels as a stereo pair. Mono files feed the
+/// same signal to both sides, further channels pass through unchanged.
+fn process_frame(
+    frame: &mut [f32],
+    effects: &[Effect],
+    gate: &mut TranceGate,
+    delay: &mut StereoDelay,
+    multi_tap: &mut MultiTapDelay,
+) {
+    let right = frame.len().min(2) - 1;
+    let mut audio: AudioFrame = [0.; NUM_CHANNELS];
+    audio[0] = frame[0];
+    audio[1] = frame[right];
+
+    for effect in effects {
+        match effect {
+            Effect::Gate => {
+                let inputs = audio;
+                gate.process(&inputs, &mut audio);
+            }
+            Effect::Delay => delay.process_stereo(&mut audio),
+            Effect::MultiTap => multi_tap.process_stereo(&mut audio),
+        }
+    }
+
+    frame[0] = audio[0];
+    if frame.len() > 1 {
+        frame[1] = audio[1];
+    }
+}
+
+fn apply_gate_param(gate: &mut TranceGate, key: &str, value: &str) -> Result<(), String> {
+    const LEFT: usize = 0;
+    const RIGHT: usize = 1;
+
+    let full_key = || format!("gate-{}", key);
+    match key {
+        "steps" => set_pattern(gate, 0, LEFT, &full_key(), value)?,
+        "steps-right" => set_pattern(gate, 0, RIGHT, &full_key(), value)?,
+        "step-count" => gate.set_step_count(parse(&full_key(), value)?),
+        "step-len" => gate.set_step_len(parse(&full_key(), value)?),
+        "step-time-ms" => gate.set_step_time_ms(parse(&full_key(), value)?),
+        "step-rate-hz" => gate.set_step_rate_hz(parse(&full_key(), value)?),
+        "step-timing" => gate.set_step_timing(choice(&full_key(), value, |name| match name {
+            "note" => Some(StepTiming::NoteLength),
+            "ms" => Some(StepTiming::Milliseconds),
+            "hz" => Some(StepTiming::Hertz),
+            _ => None,
+        })?),
+        "mix" => gate.set_mix(parse(&full_key(), value)?),
+        "width" => gate.set_width(parse(&full_key(), value)?),
+        "shuffle" => gate.set_shuffle_amount(parse(&full_key(), value)?),
+        "contour" => gate.set_contour(parse(&full_key(), value)?),
+        "contour-attack" => gate.set_contour_attack(parse(&full_key(), value)?),
+        "contour-release" => gate.set_contour_release(parse(&full_key(), value)?),
+        "declick" => gate.set_declick(parse(&full_key(), value)?),
+        "edge-mode" => gate.set_edge_mode(choice(&full_key(), value, |name| match name {
+            "hard" => Some(EdgeMode::Hard),
+            "band-limited" => Some(EdgeMode::BandLimited),
+            _ => None,
+        })?),
+        "stereo-mode" => gate.set_stereo_mode_ex(choice(&full_key(), value, |name| match name {
+            "mono" => Some(StereoMode::Mono),
+            "stereo" => Some(StereoMode::Stereo),
+            "mirrored" => Some(StereoMode::Mirrored),
+            "offset" => Some(StereoMode::Offset),
+            "reverse" => Some(StereoMode::Reverse),
+            "mid-side" => Some(StereoMode::MidSide),
+            _ => None,
+        })?),
+        "stereo-offset" => gate.set_stereo_offset(parse(&full_key(), value)?),
+        "fade-curve" => gate.set_fade_curve(choice(&full_key(), value, |name| match name {
+            "linear" => Some(FadeCurve::Linear),
+            "exponential" => Some(FadeCurve::Exponential),
+            "s-curve" => Some(FadeCurve::SCurve),
+            _ => None,
+        })?),
+        "release-target" => {
+            gate.set_release_target(choice(&full_key(), value, |name| match name {
+                "dry" => Some(ReleaseTarget::Dry),
+                "silence" => Some(ReleaseTarget::Silence),
+                _ => None,
+            })?)
+        }
+        "chain" => {
+            gate.clear_pattern_chain();
+            let entries = list(&full_key(), value, |entry| {
+                let (pattern, length) = entry.split_once(':')?;
+                Some((pattern.parse().ok()?, length.parse().ok()?))
+            })?;
+            for (pattern, length) in entries {
+                gate.add_pattern_chain_entry(pattern, length);
+            }
+        }
+        "chain-unit" => {
+            gate.set_pattern_chain_unit(choice(&full_key(), value, |name| match name {
+                "repeats" => Some(ChainLengthUnit::Repeats),
+                "bars" => Some(ChainLengthUnit::Bars),
+                _ => None,
+            })?)
+        }
+        "humanize-seed" => gate.set_humanize_seed(parse(&full_key(), value)?),
+        "humanize-level" => gate.set_humanize_level(parse(&full_key(), value)?),
+        "humanize-timing" => gate.set_humanize_timing(parse(&full_key(), value)?),
+        "humanize-contour" => gate.set_humanize_contour(parse(&full_key(), value)?),
+        _ => return set_pattern_by_key(gate, key, value),
+    }
+
+    Ok(())
+}
+
+/// Handles `gate-pattern-<n>` and `gate-pattern-<n>-right`.
+fn set_pattern_by_key(gate: &mut TranceGate, key: &str, value: &str) -> Result<(), String> {
+    const NUM_PATTERNS: usize = 8;
+
+    let full_key = format!("gate-{}", key);
+    let unknown = || format!("unknown parameter '{}'", full_key);
+
+    let index = key.strip_prefix("pattern-").ok_or_else(unknown)?;
+    let (index, channel) = match index.strip_suffix("-right") {
+        Some(index) => (index, 1),
+        None => (index, 0),
+    };
+    let pattern: usize = index.parse().map_err(|_| unknown())?;
+    if pattern >= NUM_PATTERNS {
+        return Err(format!("{}: pattern index out of range", full_key));
+    }
+
+    set_pattern(gate, pattern, channel, &full_key, value)
+}
+
+fn set_pattern(
+    gate: &mut TranceGate,
+    pattern: usize,
+    channel: usize,
+    key: &str,
+    value: &str,
+) -> Result<(), String> {
+    let steps: Vec<f32> = list(key, value, |step| step.parse().ok())?;
+    if steps.len() > gate.max_num_steps() {
+        return Err(format!(
+            "{}: at most {} steps are supported",
+            key,
+            gate.max_num_steps()
+        ));
+    }
+
+    for (step, value) in steps.into_iter().enumerate() {
+        gate.set_pattern_step(pattern, channel, step, value);
+    }
+
+    Ok(())
+}
+
+fn apply_delay_param(delay: &mut StereoDelay, key: &str, value: &str) -> Result<(), String> {
+    let full_key = format!("delay-{}", key);
+    match key {
+        "buffer-size" => delay.set_buffer_size(parse::<usize>(&full_key, value)?.max(2)),
+        "left" => delay.set_normalized_delay_left(parse(&full_key, value)?),
+        "right" => delay.set_normalized_delay_right(parse(&full_key, value)?),
+        "timing" => delay.set_delay_timing(choice(&full_key, value, |name| match name {
+            "normalized" => Some(DelayTiming::Normalized),
+            "note" => Some(DelayTiming::NoteLength),
+            "ms" => Some(DelayTiming::Milliseconds),
+            "samples" => Some(DelayTiming::Samples),
+            _ => None,
+        })?),
+        "note-len-left" => delay.set_note_len_left(parse(&full_key, value)?),
+        "note-len-right" => delay.set_note_len_right(parse(&full_key, value)?),
+        "time-ms-left" => delay.set_delay_time_ms_left(parse(&full_key, value)?),
+        "time-ms-right" => delay.set_delay_time_ms_right(parse(&full_key, value)?),
+        "samples-left" => delay.set_delay_samples_left(parse(&full_key, value)?),
+        "samples-right" => delay.set_delay_samples_right(parse(&full_key, value)?),
+        "max-time-ms" => delay.set_max_delay_time_ms(parse(&full_key, value)?),
+        "feedback" => delay.set_feedback(parse(&full_key, value)?),
+        "feedback-left" => delay.set_feedback_left(parse(&full_key, value)?),
+        "feedback-right" => delay.set_feedback_right(parse(&full_key, value)?),
+        "feedback-balance" => delay.set_feedback_balance(parse(&full_key, value)?),
+        "feedback-link" => delay.set_feedback_link(parse(&full_key, value)?),
+        "feedback-matrix" => {
+            let gains: Vec<f32> = list(&full_key, value, |gain| gain.parse().ok())?;
+            match gains[..] {
+                [l_to_l, l_to_r, r_to_l, r_to_r] => {
+                    delay.set_feedback_matrix(l_to_l, l_to_r, r_to_l, r_to_r)
+                }
+                _ => return Err(format!("{}: expected four gains", full_key)),
+            }
+        }
+        "feedback-mode" => delay.set_feedback_mode(choice(&full_key, value, |name| match name {
+            "matrix" => Some(FeedbackMode::Matrix),
+            "ping-pong" => Some(FeedbackMode::PingPong),
+            _ => None,
+        })?),
+        "input-mode" => delay.set_input_mode(choice(&full_key, value, |name| match name {
+            "mono-sum" => Some(InputMode::MonoSum),
+            "stereo" => Some(InputMode::Stereo),
+            _ => None,
+        })?),
+        "interpolation" => delay.set_interpolation(choice(&full_key, value, interpolation)?),
+        "time-change" => delay.set_time_change_mode(choice(&full_key, value, time_change_mode)?),
+        "glide-rate" => delay.set_glide_rate(parse(&full_key, value)?),
+        "wow-rate" => delay.set_wow_rate_hz(parse(&full_key, value)?),
+        "wow-depth-ms" => delay.set_wow_depth_ms(parse(&full_key, value)?),
+        "flutter-rate" => delay.set_flutter_rate_hz(parse(&full_key, value)?),
+        "flutter-depth-ms" => delay.set_flutter_depth_ms(parse(&full_key, value)?),
+        "wow-flutter-stereo-phase" => delay.set_wow_flutter_stereo_phase(parse(&full_key, value)?),
+        "wow-flutter-noise" => delay.set_wow_flutter_noise(parse(&full_key, value)?),
+        "saturation" => delay.set_saturation(choice(&full_key, value, |name| match name {
+            "off" => Some(Saturation::Off),
+            "soft-clip" => Some(Saturation::SoftClip),
+            "tape" => Some(Saturation::Tape),
+            "tube" => Some(Saturation::Tube),
+            _ => None,
+        })?),
+        "saturation-drive-db" => delay.set_saturation_drive_db(parse(&full_key, value)?),
+        "saturation-compensation" => delay.set_saturation_compensation(parse(&full_key, value)?),
+        "reverse" => delay.set_reverse(parse(&full_key, value)?),
+        "freeze" => delay.set_freeze(parse(&full_key, value)?),
+        "mix" => delay.set_mix(parse(&full_key, value)?),
+        "mix-law" => delay.set_mix_law(choice(&full_key, value, |name| match name {
+            "linear" => Some(MixLaw::Linear),
+            "equal-power" => Some(MixLaw::EqualPower),
+            _ => None,
+        })?),
+        "dry-level" => delay.set_dry_level(parse(&full_key, value)?),
+        "wet-level" => delay.set_wet_level(parse(&full_key, value)?),
+        "output-gain-db" => delay.set_output_gain_db(parse(&full_key, value)?),
+        "wet-only" => delay.set_wet_only(parse(&full_key, value)?),
+        "lp" => delay.set_lp_freq(parse(&full_key, value)?),
+        "hp" => delay.set_hp_freq(parse(&full_key, value)?),
+        _ => return Err(format!("unknown parameter '{}'", full_key)),
+    }
+
+    Ok(())
+}
+
+/// Handles the parameters shared by all taps, e.g. `multi-tap-taps`, and the
+/// tap parameters `multi-tap-<n>-<key>`.
+fn apply_multi_tap_param(
+    multi_tap: &mut MultiTapDelay,
+    key: &str,
+    value: &str,
+) -> Result<(), String> {
+    let full_key = format!("multi-tap-{}", key);
+    let unknown = || format!("unknown parameter '{}'", full_key);
+
+    match key {
+        "taps" => multi_tap.set_num_taps(parse(&full_key, value)?),
+        "max-time-ms" => multi_tap.set_max_delay_time_ms(parse(&full_key, value)?),
+        "interpolation" => multi_tap.set_interpolation(choice(&full_key, value, interpolation)?),
+        "time-change" => {
+            multi_tap.set_time_change_mode(choice(&full_key, value, time_change_mode)?)
+        }
+        "glide-rate" => multi_tap.set_glide_rate(parse(&full_key, value)?),
+        _ => {
+            let (tap, tap_key) = key.split_once('-').ok_or_else(unknown)?;
+            let tap: usize = tap.parse().map_err(|_| unknown())?;
+            if tap >= MAX_NUM_TAPS {
+                return Err(format!("{}: tap index out of range", full_key));
+            }
+
+            match tap_key {
+                "timing" => multi_tap.set_tap_timing(
+                    tap,
+                    choice(&full_key, value, |name| match name {
+                        "ms" => Some(TapTiming::Milliseconds),
+                        "note" => Some(TapTiming::NoteLength),
+                        _ => None,
+                    })?,
+                ),
+                "time-ms" => multi_tap.set_tap_time_ms(tap, parse(&full_key, value)?),
+                "note-len" => multi_tap.set_tap_note_len(tap, parse(&full_key, value)?),
+                "level" => multi_tap.set_tap_level(tap, parse(&full_key, value)?),
+                "pan" => multi_tap.set_tap_pan(tap, parse(&full_key, value)?),
+                "feedback" => multi_tap.set_tap_feedback(tap, parse(&full_key, value)?),
+                "filter" => multi_tap.set_tap_filter(
+                    tap,
+                    choice(&full_key, value, |name| match name {
+                        "off" => Some(TapFilter::Off),
+                        "lp" => Some(TapFilter::LowPass),
+                        "hp" => Some(TapFilter::HighPass),
+                        _ => None,
+                    })?,
+                ),
+                "filter-freq" => multi_tap.set_tap_filter_freq(tap, parse(&full_key, value)?),
+                _ => return Err(unknown()),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn interpolation(name: &str) -> Option<Interpolation> {
+    match name {
+        "linear" => Some(Interpolation::Linear),
+        "hermite" => Some(Interpolation::CubicHermite),
+        "lagrange" => Some(Interpolation::Lagrange),
+        "allpass" => Some(Interpolation::Allpass),
+        "sinc" => Some(Interpolation::Sinc),
+        _ => None,
+    }
+}
+
+fn time_change_mode(name: &str) -> Option<TimeChangeMode> {
+    match name {
+        "glide" => Some(TimeChangeMode::Glide),
+        "crossfade" => Some(TimeChangeMode::Crossfade),
+        "jump" => Some(TimeChangeMode::Jump),
+        _ => None,
+    }
+}
+
+fn parse<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("{}: invalid value '{}'", key, value))
+}
+
+fn choice<T>(key: &str, value: &str, f: impl Fn(&str) -> Option<T>) -> Result<T, String> {
+    f(value.trim()).ok_or_else(|| format!("{}: invalid value '{}'", key, value))
+}
+
+fn list<T>(key: &str, value: &str, f: impl Fn(&str) -> Option<T>) -> Result<Vec<T>, String> {
+    value.split(',').map(|item| choice(key, item, &f)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(list: &[(&str, &str)]) -> Vec<Param> {
+        list.iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_render_gate_and_delay() {
+        let input = Wav {
+            sample_rate: 44100,
+            num_channels: 2,
+            format: SampleFormat::Int16,
+            samples: vec![0.5; 2 * 1000],
+        };
+
+        let output = render(
+            &input,
+            &params(&[
+                ("effects", "gate,delay"),
+                ("tempo", "128"),
+                ("bits", "32f"),
+                ("tail", "0.01"),
+                ("gate-steps", "1,0"),
+                ("gate-step-count", "2"),
+                ("gate-mix", "1"),
+                ("delay-left", "0.01"),
+                ("delay-right", "0.02"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(output.format, SampleFormat::Float32);
+        assert_eq!(output.num_frames(), 1000 + 441);
+        assert!(output.samples.iter().all(|value| value.is_finite()));
+
+        // The same parameters render the same result.
+        let again = render(
+            &input,
+            &params(&[
+                ("effects", "gate,delay"),
+                ("tempo", "128"),
+                ("bits", "32f"),
+                ("tail", "0.01"),
+                ("gate-steps", "1,0"),
+                ("gate-step-count", "2"),
+                ("gate-mix", "1"),
+                ("delay-left", "0.01"),
+                ("delay-right", "0.02"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(output, again);
+    }
+
+    #[test]
+    fn test_render_mono_keeps_left_channel() {
+        const LEFT_DELAY: usize = 10;
+        const RIGHT_DELAY: usize = 20;
+
+        let mut samples = vec![0.; 100];
+        samples[0] = 1.;
+        let input = Wav {
+            sample_rate: 44100,
+            num_channels: 1,
+            format: SampleFormat::Float32,
+            samples,
+        };
+
+        let output = render(
+            &input,
+            &params(&[
+                ("effects", "delay"),
+                ("bits", "32f"),
+                ("delay-timing", "samples"),
+                ("delay-samples-left", &LEFT_DELAY.to_string()),
+                ("delay-samples-right", &RIGHT_DELAY.to_string()),
+                ("delay-feedback", "0"),
+                ("delay-mix", "1"),
+            ]),
+        )
+        .unwrap();
+
+        // Only the left channel ends up in a mono file.
+        assert_eq!(output.num_channels, 1);
+        assert_eq!(output.num_frames(), 100);
+        assert!(output.samples[LEFT_DELAY].abs() > 0.5);
+        assert!(output.samples[RIGHT_DELAY].abs() < 0.1);
+    }
+
+    #[test]
+    fn test_render_rejects_bad_params() {
+        let input = Wav {
+            sample_rate: 44100,
+            num_channels: 1,
+            format: SampleFormat::Int16,
+            samples: vec![0.; 10],
+        };
+
+        assert!(render(&input, &params(&[])).is_err());
+        assert!(render(&input, &params(&[("effects", "gate"), ("gate-foo", "1")])).is_err());
+        assert!(render(&input, &params(&[("effects", "gate"), ("tempo", "fast")])).is_err());
+        assert!(render(&input, &params(&[("effects", "reverb")])).is_err());
+        assert!(render(
+            &input,
+            &params(&[("effects", "gate"), ("gate-pattern-3", "1,0.5")])
+        )
+        .is_ok());
+        assert!(render(
+            &input,
+            &params(&[("effects", "multi-tap"), ("multi-tap-8-level", "1")])
+        )
+        .is_err());
+        assert!(render(
+            &input,
+            &params(&[
+                ("effects", "multi-tap"),
+                ("multi-tap-taps", "2"),
+                ("multi-tap-1-timing", "note"),
+                ("multi-tap-1-filter", "lp"),
+                ("multi-tap-time-change", "crossfade"),
+            ])
+        )
+        .is_ok());
+    }
+}
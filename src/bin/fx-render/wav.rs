@@ -0,0 +1,286 @@
+// Copyright(c) 2022 Hansen Audio.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+#[derive(Debug)]
+pub enum WavError {
+    Io(std::io::Error),
+    InvalidFile,
+    UnsupportedFormat { format: u16, bits: u16 },
+}
+
+impl fmt::Display for WavError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WavError::Io(err) => write!(f, "{}", err),
+            WavError::InvalidFile => write!(f, "not a valid WAV file"),
+            WavError::UnsupportedFormat { format, bits } => {
+                write!(f, "unsupported WAV format {} with {} bits", format, bits)
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for WavError {
+    fn from(err: std::io::Error) -> Self {
+        WavError::Io(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    Int16,
+    Int24,
+    Float32,
+}
+
+impl SampleFormat {
+    fn from_header(format: u16, bits: u16) -> Result<Self, WavError> {
+        match (format, bits) {
+            (FORMAT_PCM, 16) => Ok(SampleFormat::Int16),
+            (FORMAT_PCM, 24) => Ok(SampleFormat::Int24),
+            (FORMAT_FLOAT, 32) => Ok(SampleFormat::Float32),
+            _ => Err(WavError::UnsupportedFormat { format, bits }),
+        }
+    }
+
+    fn format_tag(&self) -> u16 {
+        match self {
+            SampleFormat::Int16 | SampleFormat::Int24 => FORMAT_PCM,
+            SampleFormat::Float32 => FORMAT_FLOAT,
+        }
+    }
+
+    fn bytes_per_sample(&self) -> usize {
+        match self {
+            SampleFormat::Int16 => 2,
+            SampleFormat::Int24 => 3,
+            SampleFormat::Float32 => 4,
+        }
+    }
+}
+
+/// Interleaved audio with samples normalized to [-1, 1].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Wav {
+    pub sample_rate: u32,
+    pub num_channels: usize,
+    pub format: SampleFormat,
+    pub samples: Vec<f32>,
+}
+
+impl Wav {
+    pub fn num_frames(&self) -> usize {
+        self.samples.len() / self.num_channels
+    }
+
+    pub fn read(path: &Path) -> Result<Self, WavError> {
+        Self::decode(&fs::read(path)?)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), WavError> {
+        fs::write(path, self.encode())?;
+        Ok(())
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, WavError> {
+        const RIFF_HEADER_LEN: usize = 12;
+        const CHUNK_HEADER_LEN: usize = 8;
+        const MIN_FMT_LEN: usize = 16;
+        const SUB_FORMAT_OFFSET: usize = 24;
+
+        if data.len() < RIFF_HEADER_LEN || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+            return Err(WavError::InvalidFile);
+        }
+
+        let mut fmt = None;
+        let mut pos = RIFF_HEADER_LEN;
+        while pos + CHUNK_HEADER_LEN <= data.len() {
+            let id = &data[pos..pos + 4];
+            let len = read_u32(data, pos + 4) as usize;
+            let body_start = pos + CHUNK_HEADER_LEN;
+            let body = &data[body_start..(body_start + len).min(data.len())];
+
+            match id {
+                b"fmt " => {
+                    if body.len() < MIN_FMT_LEN {
+                        return Err(WavError::InvalidFile);
+                    }
+
+                    let mut format = read_u16(body, 0);
+                    let num_channels = read_u16(body, 2) as usize;
+                    let sample_rate = read_u32(body, 4);
+                    let bits = read_u16(body, 14);
+                    if format == FORMAT_EXTENSIBLE {
+                        if body.len() < SUB_FORMAT_OFFSET + 2 {
+                            return Err(WavError::InvalidFile);
+                        }
+                        format = read_u16(body, SUB_FORMAT_OFFSET);
+                    }
+
+                    if num_channels == 0 {
+                        return Err(WavError::InvalidFile);
+                    }
+                    fmt = Some((
+                        SampleFormat::from_header(format, bits)?,
+                        num_channels,
+                        sample_rate,
+                    ));
+                }
+                b"data" => {
+                    let (format, num_channels, sample_rate) = fmt.ok_or(WavError::InvalidFile)?;
+                    let frame_len = format.bytes_per_sample() * num_channels;
+                    let body = &body[..body.len() - body.len() % frame_len];
+
+                    return Ok(Self {
+                        sample_rate,
+                        num_channels,
+                        format,
+                        samples: body
+                            .chunks_exact(format.bytes_per_sample())
+                            .map(|bytes| decode_sample(bytes, format))
+                            .collect(),
+                    });
+                }
+                _ => {}
+            }
+
+            // Chunks are padded to an even number of bytes.
+            pos = body_start + len + len % 2;
+        }
+
+        Err(WavError::InvalidFile)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        const FMT_LEN: u32 = 16;
+        const BITS_PER_BYTE: usize = 8;
+
+        let bytes_per_sample = self.format.bytes_per_sample();
+        let block_align = bytes_per_sample * self.num_channels;
+        let data_len = self.samples.len() * bytes_per_sample;
+        let riff_len = 4 + 8 + FMT_LEN as usize + 8 + data_len + data_len % 2;
+
+        let mut data = Vec::with_capacity(riff_len + 8);
+        data.extend_from_slice(b"RIFF");
+        data.extend_from_slice(&(riff_len as u32).to_le_bytes());
+        data.extend_from_slice(b"WAVE");
+
+        data.extend_from_slice(b"fmt ");
+        data.extend_from_slice(&FMT_LEN.to_le_bytes());
+        data.extend_from_slice(&self.format.format_tag().to_le_bytes());
+        data.extend_from_slice(&(self.num_channels as u16).to_le_bytes());
+        data.extend_from_slice(&self.sample_rate.to_le_bytes());
+        data.extend_from_slice(&(self.sample_rate * block_align as u32).to_le_bytes());
+        data.extend_from_slice(&(block_align as u16).to_le_bytes());
+        data.extend_from_slice(&((bytes_per_sample * BITS_PER_BYTE) as u16).to_le_bytes());
+
+        data.extend_from_slice(b"data");
+        data.extend_from_slice(&(data_len as u32).to_le_bytes());
+        for &sample in &self.samples {
+            encode_sample(&mut data, sample, self.format);
+        }
+        if data_len % 2 == 1 {
+            data.push(0);
+        }
+
+        data
+    }
+}
+
+fn read_u16(data: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes([data[pos], data[pos + 1]])
+}
+
+fn read_u32(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+}
+
+fn decode_sample(bytes: &[u8], format: SampleFormat) -> f32 {
+    const INT16_SCALE: f32 = 32768.;
+    const INT24_SCALE: f32 = 8388608.;
+
+    match format {
+        SampleFormat::Int16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / INT16_SCALE,
+        SampleFormat::Int24 => {
+            // Shift into the upper bytes to sign-extend.
+            let value = i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8;
+            value as f32 / INT24_SCALE
+        }
+        SampleFormat::Float32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+    }
+}
+
+fn encode_sample(data: &mut Vec<u8>, sample: f32, format: SampleFormat) {
+    const INT16_MAX: f32 = 32767.;
+    const INT24_MAX: f32 = 8388607.;
+
+    match format {
+        SampleFormat::Int16 => {
+            let value = (sample.clamp(-1., 1.) * INT16_MAX).round() as i16;
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        SampleFormat::Int24 => {
+            let value = (sample.clamp(-1., 1.) * INT24_MAX).round() as i32;
+            data.extend_from_slice(&value.to_le_bytes()[0..3]);
+        }
+        SampleFormat::Float32 => data.extend_from_slice(&sample.to_le_bytes()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wav_round_trip() {
+        const NUM_CHANNELS: usize = 2;
+        let samples = vec![0., 0.5, -0.5, 1., -1., 0.25];
+
+        for format in [
+            SampleFormat::Int16,
+            SampleFormat::Int24,
+            SampleFormat::Float32,
+        ] {
+            let wav = Wav {
+                sample_rate: 48000,
+                num_channels: NUM_CHANNELS,
+                format,
+                samples: samples.clone(),
+            };
+
+            let decoded = Wav::decode(&wav.encode()).unwrap();
+            assert_eq!(decoded.sample_rate, 48000);
+            assert_eq!(decoded.num_channels, NUM_CHANNELS);
+            assert_eq!(decoded.format, format);
+            assert_eq!(decoded.num_frames(), 3);
+            for (a, b) in decoded.samples.iter().zip(samples.iter()) {
+                assert!((a - b).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_wav_rejects_unsupported_format() {
+        let mut data = Wav {
+            sample_rate: 44100,
+            num_channels: 1,
+            format: SampleFormat::Int16,
+            samples: vec![0.],
+        }
+        .encode();
+        // Patch the bit depth to 8.
+        data[34] = 8;
+
+        assert!(matches!(
+            Wav::decode(&data),
+            Err(WavError::UnsupportedFormat { .. })
+        ));
+    }
+}
@@ -1,202 +1,878 @@
 // Copyright(c) 2021 Hansen Audio.
 
-use crate::{stereo_delay, trance_gate, AudioFrame};
+use crate::{multi_tap_delay, stereo_delay, trance_gate, AudioFrame};
+use alloc::boxed::Box;
+
+/// Defines every function for `f32` samples under its first name and for
+/// `f64` samples under its second name, so both APIs cannot diverge. `T` is
+/// the sample type and may only appear as a generic argument, e.g.
+/// `TranceGate<T>`. cbindgen does not expand macros, `build.rs` expands this
+/// one for the header.
+macro_rules! sample_type_api {
+    (
+        mod $f32_mod:ident, $f64_mod:ident;
+        $(
+            $(#[$attr:meta])*
+            fn $name:ident, $name_f64:ident($($arg:ident: $arg_ty:ty),* $(,)?)
+                $(-> $ret:ty)? $body:block
+        )*
+    ) => {
+        sample_type_api!(@mod $f32_mod, f32; $(
+            $(#[$attr])* fn $name($($arg: $arg_ty),*) $(-> $ret)? $body
+        )*);
+        sample_type_api!(@mod $f64_mod, f64; $(
+            $(#[$attr])* fn $name_f64($($arg: $arg_ty),*) $(-> $ret)? $body
+        )*);
+    };
+    (@mod $mod:ident, $sample:ty; $(
+        $(#[$attr:meta])* fn $name:ident($($arg:ident: $arg_ty:ty),*) $(-> $ret:ty)? $body:block
+    )*) => {
+        mod $mod {
+            use super::*;
+
+            type T = $sample;
+
+            $(
+                $(#[$attr])*
+                #[doc = ""]
+                #[doc = "# Safety"]
+                #[doc = ""]
+                #[doc = "Effect pointers must come from the matching `create_` function and"]
+                #[doc = "must not be used concurrently or after `destroy_`. `data` must"]
+                #[doc = "point to `size` bytes."]
+                #[no_mangle]
+                pub unsafe extern "C" fn $name($($arg: $arg_ty),*) $(-> $ret)? $body
+            )*
+        }
+    };
+}
 
 //-----------------------------------------------------------------------------
 // https://firefox-source-docs.mozilla.org/writing-rust-code/ffi.html
-#[no_mangle]
-pub unsafe extern "C" fn create_trance_gate() -> *mut trance_gate::TranceGate {
-    let trance_gate = trance_gate::TranceGate::new();
-    Box::into_raw(Box::new(trance_gate))
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn destroy_trance_gate(trance_gate: *mut trance_gate::TranceGate) {
-    drop(Box::from_raw(trance_gate));
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn set_tempo(trance_gate: &mut trance_gate::TranceGate, tempo_bpm: f32) {
-    trance_gate.set_tempo(tempo_bpm);
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn trigger(
-    trance_gate: &mut trance_gate::TranceGate,
-    delay_len: f32,
-    fade_in_len: f32,
-) {
-    trance_gate.trigger(delay_len, fade_in_len);
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn reset(trance_gate: &mut trance_gate::TranceGate) {
-    trance_gate.reset();
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn reset_step_pos(trance_gate: &mut trance_gate::TranceGate, value: usize) {
-    trance_gate.reset_step_pos(value);
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn get_step_pos(trance_gate: &trance_gate::TranceGate) -> usize {
-    trance_gate.step_pos()
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn process(
-    trance_gate: &mut trance_gate::TranceGate,
-    inputs: &AudioFrame,
-    outputs: &mut AudioFrame,
-) {
-    trance_gate.process(inputs, outputs);
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn set_sample_rate(trance_gate: &mut trance_gate::TranceGate, value: f32) {
-    trance_gate.set_sample_rate(value);
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn set_step(
-    trance_gate: &mut trance_gate::TranceGate,
-    channel: usize,
-    step: usize,
-    value_normalized: f32,
-) {
-    trance_gate.set_step(channel, step, value_normalized);
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn set_width(trance_gate: &mut trance_gate::TranceGate, value: f32) {
-    trance_gate.set_width(value);
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn set_shuffle_amount(trance_gate: &mut trance_gate::TranceGate, value: f32) {
-    trance_gate.set_shuffle_amount(value);
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn set_stereo_mode(trance_gate: &mut trance_gate::TranceGate, value: bool) {
-    trance_gate.set_stereo_mode(value);
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn set_step_len(trance_gate: &mut trance_gate::TranceGate, value: f32) {
-    trance_gate.set_step_len(value);
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn update_project_time_music(
-    trance_gate: &mut trance_gate::TranceGate,
-    value: f64,
-) {
-    trance_gate.update_project_time_music(value);
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn set_step_count(trance_gate: &mut trance_gate::TranceGate, value: usize) {
-    trance_gate.set_step_count(value);
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn set_contour(trance_gate: &mut trance_gate::TranceGate, value_secs: f32) {
-    trance_gate.set_contour(value_secs);
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn set_fade_in(trance_gate: &mut trance_gate::TranceGate, value: f32) {
-    trance_gate.set_fade_in(value);
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn set_delay(trance_gate: &mut trance_gate::TranceGate, value: f32) {
-    trance_gate.set_delay(value);
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn set_mix(trance_gate: &mut trance_gate::TranceGate, value: f32) {
-    trance_gate.set_mix(value);
+sample_type_api! {
+    mod trance_gate_f32, trance_gate_f64;
+
+    fn create_trance_gate, create_trance_gate_f64() -> *mut trance_gate::TranceGate<T> {
+        let trance_gate = trance_gate::TranceGate::<T>::with_sample_type();
+        Box::into_raw(Box::new(trance_gate))
+    }
+
+    fn create_trance_gate_with_max_steps, create_trance_gate_with_max_steps_f64(
+        max_num_steps: usize,
+    ) -> *mut trance_gate::TranceGate<T> {
+        let trance_gate =
+            trance_gate::TranceGate::<T>::with_sample_type_and_max_steps(max_num_steps);
+        Box::into_raw(Box::new(trance_gate))
+    }
+
+    fn destroy_trance_gate, destroy_trance_gate_f64(trance_gate: *mut trance_gate::TranceGate<T>) {
+        drop(Box::from_raw(trance_gate));
+    }
+
+    fn set_tempo, set_tempo_f64(trance_gate: &mut trance_gate::TranceGate<T>, tempo_bpm: f32) {
+        trance_gate.set_tempo(tempo_bpm);
+    }
+
+    fn trigger, trigger_f64(
+        trance_gate: &mut trance_gate::TranceGate<T>,
+        delay_len: f32,
+        fade_in_len: f32,
+    ) {
+        trance_gate.trigger(delay_len, fade_in_len);
+    }
+
+    fn release, release_f64(trance_gate: &mut trance_gate::TranceGate<T>, fade_out_len: f32) {
+        trance_gate.release(fade_out_len);
+    }
+
+    fn reset, reset_f64(trance_gate: &mut trance_gate::TranceGate<T>) {
+        trance_gate.reset();
+    }
+
+    fn reset_step_pos, reset_step_pos_f64(
+        trance_gate: &mut trance_gate::TranceGate<T>,
+        value: usize,
+    ) {
+        trance_gate.reset_step_pos(value);
+    }
+
+    fn get_step_pos, get_step_pos_f64(trance_gate: &trance_gate::TranceGate<T>) -> usize {
+        trance_gate.step_pos()
+    }
+
+    fn process, process_f64(
+        trance_gate: &mut trance_gate::TranceGate<T>,
+        inputs: &AudioFrame<T>,
+        outputs: &mut AudioFrame<T>,
+    ) {
+        trance_gate.process(inputs, outputs);
+    }
+
+    fn set_sample_rate, set_sample_rate_f64(
+        trance_gate: &mut trance_gate::TranceGate<T>,
+        value: f32,
+    ) {
+        trance_gate.set_sample_rate(value);
+    }
+
+    fn set_step, set_step_f64(
+        trance_gate: &mut trance_gate::TranceGate<T>,
+        channel: usize,
+        step: usize,
+        value_normalized: f32,
+    ) {
+        trance_gate.set_step(channel, step, value_normalized);
+    }
+
+    fn set_pattern_step, set_pattern_step_f64(
+        trance_gate: &mut trance_gate::TranceGate<T>,
+        pattern: usize,
+        channel: usize,
+        step: usize,
+        value_normalized: f32,
+    ) {
+        trance_gate.set_pattern_step(pattern, channel, step, value_normalized);
+    }
+
+    fn add_pattern_chain_entry, add_pattern_chain_entry_f64(
+        trance_gate: &mut trance_gate::TranceGate<T>,
+        pattern: usize,
+        length: usize,
+    ) {
+        trance_gate.add_pattern_chain_entry(pattern, length);
+    }
+
+    fn clear_pattern_chain, clear_pattern_chain_f64(trance_gate: &mut trance_gate::TranceGate<T>) {
+        trance_gate.clear_pattern_chain();
+    }
+
+    fn set_pattern_chain_unit, set_pattern_chain_unit_f64(
+        trance_gate: &mut trance_gate::TranceGate<T>,
+        value: trance_gate::ChainLengthUnit,
+    ) {
+        trance_gate.set_pattern_chain_unit(value);
+    }
+
+    fn get_pattern_index, get_pattern_index_f64(trance_gate: &trance_gate::TranceGate<T>) -> usize {
+        trance_gate.pattern_index()
+    }
+
+    fn set_width, set_width_f64(trance_gate: &mut trance_gate::TranceGate<T>, value: f32) {
+        trance_gate.set_width(value);
+    }
+
+    fn set_shuffle_amount, set_shuffle_amount_f64(
+        trance_gate: &mut trance_gate::TranceGate<T>,
+        value: f32,
+    ) {
+        trance_gate.set_shuffle_amount(value);
+    }
+
+    fn set_stereo_mode, set_stereo_mode_f64(
+        trance_gate: &mut trance_gate::TranceGate<T>,
+        value: bool,
+    ) {
+        trance_gate.set_stereo_mode(value);
+    }
+
+    fn set_stereo_mode_ex, set_stereo_mode_ex_f64(
+        trance_gate: &mut trance_gate::TranceGate<T>,
+        value: trance_gate::StereoMode,
+    ) {
+        trance_gate.set_stereo_mode_ex(value);
+    }
+
+    fn set_stereo_offset, set_stereo_offset_f64(
+        trance_gate: &mut trance_gate::TranceGate<T>,
+        value: usize,
+    ) {
+        trance_gate.set_stereo_offset(value);
+    }
+
+    fn set_step_len, set_step_len_f64(trance_gate: &mut trance_gate::TranceGate<T>, value: f32) {
+        trance_gate.set_step_len(value);
+    }
+
+    fn set_step_timing, set_step_timing_f64(
+        trance_gate: &mut trance_gate::TranceGate<T>,
+        value: trance_gate::StepTiming,
+    ) {
+        trance_gate.set_step_timing(value);
+    }
+
+    fn set_step_time_ms, set_step_time_ms_f64(
+        trance_gate: &mut trance_gate::TranceGate<T>,
+        value: f32,
+    ) {
+        trance_gate.set_step_time_ms(value);
+    }
+
+    fn set_step_rate_hz, set_step_rate_hz_f64(
+        trance_gate: &mut trance_gate::TranceGate<T>,
+        value: f32,
+    ) {
+        trance_gate.set_step_rate_hz(value);
+    }
+
+    fn update_project_time_music, update_project_time_music_f64(
+        trance_gate: &mut trance_gate::TranceGate<T>,
+        value: f64,
+    ) {
+        trance_gate.update_project_time_music(value);
+    }
+
+    fn set_step_count, set_step_count_f64(
+        trance_gate: &mut trance_gate::TranceGate<T>,
+        value: usize,
+    ) {
+        trance_gate.set_step_count(value);
+    }
+
+    fn set_contour, set_contour_f64(trance_gate: &mut trance_gate::TranceGate<T>, value_secs: f32) {
+        trance_gate.set_contour(value_secs);
+    }
+
+    fn set_contour_attack, set_contour_attack_f64(
+        trance_gate: &mut trance_gate::TranceGate<T>,
+        value_secs: f32,
+    ) {
+        trance_gate.set_contour_attack(value_secs);
+    }
+
+    fn set_contour_release, set_contour_release_f64(
+        trance_gate: &mut trance_gate::TranceGate<T>,
+        value_secs: f32,
+    ) {
+        trance_gate.set_contour_release(value_secs);
+    }
+
+    fn set_declick, set_declick_f64(trance_gate: &mut trance_gate::TranceGate<T>, value_ms: f32) {
+        trance_gate.set_declick(value_ms);
+    }
+
+    fn set_edge_mode, set_edge_mode_f64(
+        trance_gate: &mut trance_gate::TranceGate<T>,
+        value: trance_gate::EdgeMode,
+    ) {
+        trance_gate.set_edge_mode(value);
+    }
+
+    fn set_fade_in, set_fade_in_f64(trance_gate: &mut trance_gate::TranceGate<T>, value: f32) {
+        trance_gate.set_fade_in(value);
+    }
+
+    fn set_delay, set_delay_f64(trance_gate: &mut trance_gate::TranceGate<T>, value: f32) {
+        trance_gate.set_delay(value);
+    }
+
+    fn set_mix, set_mix_f64(trance_gate: &mut trance_gate::TranceGate<T>, value: f32) {
+        trance_gate.set_mix(value);
+    }
+
+    fn set_humanize_seed, set_humanize_seed_f64(
+        trance_gate: &mut trance_gate::TranceGate<T>,
+        value: u32,
+    ) {
+        trance_gate.set_humanize_seed(value);
+    }
+
+    fn set_humanize_level, set_humanize_level_f64(
+        trance_gate: &mut trance_gate::TranceGate<T>,
+        value: f32,
+    ) {
+        trance_gate.set_humanize_level(value);
+    }
+
+    fn set_humanize_timing, set_humanize_timing_f64(
+        trance_gate: &mut trance_gate::TranceGate<T>,
+        value_ms: f32,
+    ) {
+        trance_gate.set_humanize_timing(value_ms);
+    }
+
+    fn set_humanize_contour, set_humanize_contour_f64(
+        trance_gate: &mut trance_gate::TranceGate<T>,
+        value: f32,
+    ) {
+        trance_gate.set_humanize_contour(value);
+    }
+
+    fn set_fade_curve, set_fade_curve_f64(
+        trance_gate: &mut trance_gate::TranceGate<T>,
+        value: trance_gate::FadeCurve,
+    ) {
+        trance_gate.set_fade_curve(value);
+    }
+
+    fn set_release_target, set_release_target_f64(
+        trance_gate: &mut trance_gate::TranceGate<T>,
+        value: trance_gate::ReleaseTarget,
+    ) {
+        trance_gate.set_release_target(value);
+    }
+
+    /// Writes the gate state into `data` if it is large enough and returns the
+    /// required size in bytes. Pass a null pointer to query the size.
+    fn save_trance_gate_state, save_trance_gate_state_f64(
+        trance_gate: &trance_gate::TranceGate<T>,
+        data: *mut u8,
+        size: usize,
+    ) -> usize {
+        copy_state(&trance_gate.save_state_binary(), data, size)
+    }
+
+    fn restore_trance_gate_state, restore_trance_gate_state_f64(
+        trance_gate: &mut trance_gate::TranceGate<T>,
+        data: *const u8,
+        size: usize,
+    ) -> bool {
+        if data.is_null() {
+            return false;
+        }
+
+        let state = core::slice::from_raw_parts(data, size);
+        trance_gate.restore_state_binary(state).is_ok()
+    }
 }
 
 //-----------------------------------------------------------------------------
 // https://firefox-source-docs.mozilla.org/writing-rust-code/ffi.html
-#[no_mangle]
-pub unsafe extern "C" fn create_stereo_delay() -> *mut stereo_delay::StereoDelay {
-    let stereo_delay = stereo_delay::StereoDelay::new();
-    Box::into_raw(Box::new(stereo_delay))
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn destroy_stereo_delay(stereo_delay: *mut stereo_delay::StereoDelay) {
-    drop(Box::from_raw(stereo_delay));
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn process_stereo_delay(
-    stereo_delay: &mut stereo_delay::StereoDelay,
-    inputs: &AudioFrame,
-    outputs: &mut AudioFrame,
-) {
-    outputs.copy_from_slice(inputs);
-    stereo_delay.process_stereo(outputs);
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn set_normalized_delay_left(
-    stereo_delay: &mut stereo_delay::StereoDelay,
-    speed: f32,
-) {
-    stereo_delay.set_normalized_delay_left(speed);
+sample_type_api! {
+    mod stereo_delay_f32, stereo_delay_f64;
+
+    fn create_stereo_delay, create_stereo_delay_f64() -> *mut stereo_delay::StereoDelay<T> {
+        let stereo_delay = stereo_delay::StereoDelay::<T>::with_sample_type();
+        Box::into_raw(Box::new(stereo_delay))
+    }
+
+    fn destroy_stereo_delay, destroy_stereo_delay_f64(
+        stereo_delay: *mut stereo_delay::StereoDelay<T>,
+    ) {
+        drop(Box::from_raw(stereo_delay));
+    }
+
+    fn process_stereo_delay, process_stereo_delay_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        inputs: &AudioFrame<T>,
+        outputs: &mut AudioFrame<T>,
+    ) {
+        outputs.copy_from_slice(inputs);
+        stereo_delay.process_stereo(outputs);
+    }
+
+    fn set_normalized_delay_left, set_normalized_delay_left_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        speed: f32,
+    ) {
+        stereo_delay.set_normalized_delay_left(speed);
+    }
+
+    fn set_normalized_delay_right, set_normalized_delay_right_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        speed: f32,
+    ) {
+        stereo_delay.set_normalized_delay_right(speed);
+    }
+
+    fn set_delay_timing, set_delay_timing_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        value: stereo_delay::DelayTiming,
+    ) {
+        stereo_delay.set_delay_timing(value);
+    }
+
+    fn set_note_len_left, set_note_len_left_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        note_len: f32,
+    ) {
+        stereo_delay.set_note_len_left(note_len);
+    }
+
+    fn set_note_len_right, set_note_len_right_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        note_len: f32,
+    ) {
+        stereo_delay.set_note_len_right(note_len);
+    }
+
+    fn set_delay_time_ms_left, set_delay_time_ms_left_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        delay_time_ms: f32,
+    ) {
+        stereo_delay.set_delay_time_ms_left(delay_time_ms);
+    }
+
+    fn set_delay_time_ms_right, set_delay_time_ms_right_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        delay_time_ms: f32,
+    ) {
+        stereo_delay.set_delay_time_ms_right(delay_time_ms);
+    }
+
+    fn set_delay_samples_left, set_delay_samples_left_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        delay_samples: f32,
+    ) {
+        stereo_delay.set_delay_samples_left(delay_samples);
+    }
+
+    fn set_delay_samples_right, set_delay_samples_right_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        delay_samples: f32,
+    ) {
+        stereo_delay.set_delay_samples_right(delay_samples);
+    }
+
+    fn set_max_delay_time_ms, set_max_delay_time_ms_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        max_delay_time_ms: f32,
+    ) {
+        stereo_delay.set_max_delay_time_ms(max_delay_time_ms);
+    }
+
+    fn set_tempo_stereo_delay, set_tempo_stereo_delay_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        tempo_bpm: f32,
+    ) {
+        stereo_delay.set_tempo(tempo_bpm);
+    }
+
+    fn set_feedback, set_feedback_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        feedback: f32,
+    ) {
+        stereo_delay.set_feedback(feedback);
+    }
+
+    fn set_feedback_left, set_feedback_left_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        feedback: f32,
+    ) {
+        stereo_delay.set_feedback_left(feedback);
+    }
+
+    fn set_feedback_right, set_feedback_right_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        feedback: f32,
+    ) {
+        stereo_delay.set_feedback_right(feedback);
+    }
+
+    fn set_feedback_balance, set_feedback_balance_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        balance: f32,
+    ) {
+        stereo_delay.set_feedback_balance(balance);
+    }
+
+    fn set_feedback_link, set_feedback_link_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        is_linked: bool,
+    ) {
+        stereo_delay.set_feedback_link(is_linked);
+    }
+
+    /// Sets the gains from each channel into both delay lines, see
+    /// `StereoDelay::set_feedback_matrix`.
+    fn set_feedback_matrix, set_feedback_matrix_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        l_to_l: f32,
+        l_to_r: f32,
+        r_to_l: f32,
+        r_to_r: f32,
+    ) {
+        stereo_delay.set_feedback_matrix(l_to_l, l_to_r, r_to_l, r_to_r);
+    }
+
+    fn set_feedback_mode, set_feedback_mode_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        value: stereo_delay::FeedbackMode,
+    ) {
+        stereo_delay.set_feedback_mode(value);
+    }
+
+    fn set_input_mode, set_input_mode_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        value: stereo_delay::InputMode,
+    ) {
+        stereo_delay.set_input_mode(value);
+    }
+
+    fn set_interpolation, set_interpolation_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        value: stereo_delay::Interpolation,
+    ) {
+        stereo_delay.set_interpolation(value);
+    }
+
+    fn set_time_change_mode, set_time_change_mode_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        value: stereo_delay::TimeChangeMode,
+    ) {
+        stereo_delay.set_time_change_mode(value);
+    }
+
+    fn set_glide_rate, set_glide_rate_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        glide_rate: f32,
+    ) {
+        stereo_delay.set_glide_rate(glide_rate);
+    }
+
+    fn set_wow_rate_hz, set_wow_rate_hz_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        rate_hz: f32,
+    ) {
+        stereo_delay.set_wow_rate_hz(rate_hz);
+    }
+
+    fn set_wow_depth_ms, set_wow_depth_ms_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        depth_ms: f32,
+    ) {
+        stereo_delay.set_wow_depth_ms(depth_ms);
+    }
+
+    fn set_flutter_rate_hz, set_flutter_rate_hz_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        rate_hz: f32,
+    ) {
+        stereo_delay.set_flutter_rate_hz(rate_hz);
+    }
+
+    fn set_flutter_depth_ms, set_flutter_depth_ms_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        depth_ms: f32,
+    ) {
+        stereo_delay.set_flutter_depth_ms(depth_ms);
+    }
+
+    fn set_wow_flutter_stereo_phase, set_wow_flutter_stereo_phase_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        stereo_phase: f32,
+    ) {
+        stereo_delay.set_wow_flutter_stereo_phase(stereo_phase);
+    }
+
+    fn set_wow_flutter_noise, set_wow_flutter_noise_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        amount: f32,
+    ) {
+        stereo_delay.set_wow_flutter_noise(amount);
+    }
+
+    fn set_saturation, set_saturation_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        value: stereo_delay::Saturation,
+    ) {
+        stereo_delay.set_saturation(value);
+    }
+
+    fn set_saturation_drive_db, set_saturation_drive_db_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        drive_db: f32,
+    ) {
+        stereo_delay.set_saturation_drive_db(drive_db);
+    }
+
+    fn set_saturation_compensation, set_saturation_compensation_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        is_compensated: bool,
+    ) {
+        stereo_delay.set_saturation_compensation(is_compensated);
+    }
+
+    fn set_reverse, set_reverse_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        is_reverse: bool,
+    ) {
+        stereo_delay.set_reverse(is_reverse);
+    }
+
+    fn set_freeze, set_freeze_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        is_frozen: bool,
+    ) {
+        stereo_delay.set_freeze(is_frozen);
+    }
+
+    fn set_mix_stereo_delay, set_mix_stereo_delay_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        mix: f32,
+    ) {
+        stereo_delay.set_mix(mix);
+    }
+
+    fn set_mix_law, set_mix_law_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        value: stereo_delay::MixLaw,
+    ) {
+        stereo_delay.set_mix_law(value);
+    }
+
+    fn set_dry_level, set_dry_level_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        dry_level: f32,
+    ) {
+        stereo_delay.set_dry_level(dry_level);
+    }
+
+    fn set_wet_level, set_wet_level_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        wet_level: f32,
+    ) {
+        stereo_delay.set_wet_level(wet_level);
+    }
+
+    fn set_output_gain_db, set_output_gain_db_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        output_gain_db: f32,
+    ) {
+        stereo_delay.set_output_gain_db(output_gain_db);
+    }
+
+    fn set_wet_only, set_wet_only_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        is_wet_only: bool,
+    ) {
+        stereo_delay.set_wet_only(is_wet_only);
+    }
+
+    fn clear_buffer, clear_buffer_f64(stereo_delay: &mut stereo_delay::StereoDelay<T>) {
+        stereo_delay.clear_buffer();
+    }
+
+    /// Returns true if a delay time is limited to the buffer size, see
+    /// `set_max_delay_time_ms`.
+    fn is_delay_time_limited, is_delay_time_limited_f64(
+        stereo_delay: &stereo_delay::StereoDelay<T>,
+    ) -> bool {
+        stereo_delay.is_delay_time_limited()
+    }
+
+    fn set_buffer_size, set_buffer_size_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        size: usize,
+    ) {
+        stereo_delay.set_buffer_size(size);
+    }
+
+    fn reset_heads, reset_heads_f64(stereo_delay: &mut stereo_delay::StereoDelay<T>) {
+        stereo_delay.reset_heads();
+    }
+
+    fn set_sample_rate_stereo_delay, set_sample_rate_stereo_delay_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        sample_rate: f32,
+    ) {
+        stereo_delay.set_sample_rate(sample_rate);
+    }
+
+    fn set_lp_freq, set_lp_freq_f64(stereo_delay: &mut stereo_delay::StereoDelay<T>, freq: f32) {
+        stereo_delay.set_lp_freq(freq);
+    }
+
+    fn set_hp_freq, set_hp_freq_f64(stereo_delay: &mut stereo_delay::StereoDelay<T>, freq: f32) {
+        stereo_delay.set_hp_freq(freq);
+    }
+
+    /// Writes the delay state including its buffers into `data` if it is large
+    /// enough and returns the required size in bytes.
+    fn save_stereo_delay_state, save_stereo_delay_state_f64(
+        stereo_delay: &stereo_delay::StereoDelay<T>,
+        data: *mut u8,
+        size: usize,
+    ) -> usize {
+        copy_state(&stereo_delay.save_state_binary(), data, size)
+    }
+
+    fn restore_stereo_delay_state, restore_stereo_delay_state_f64(
+        stereo_delay: &mut stereo_delay::StereoDelay<T>,
+        data: *const u8,
+        size: usize,
+    ) -> bool {
+        if data.is_null() {
+            return false;
+        }
+
+        let state = core::slice::from_raw_parts(data, size);
+        stereo_delay.restore_state_binary(state).is_ok()
+    }
 }
 
-#[no_mangle]
-pub unsafe extern "C" fn set_normalized_delay_right(
-    stereo_delay: &mut stereo_delay::StereoDelay,
-    speed: f32,
-) {
-    stereo_delay.set_normalized_delay_right(speed);
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn set_feedback(stereo_delay: &mut stereo_delay::StereoDelay, feedback: f32) {
-    stereo_delay.set_feedback(feedback);
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn clear_buffer(stereo_delay: &mut stereo_delay::StereoDelay) {
-    stereo_delay.clear_buffer();
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn set_buffer_size(
-    stereo_delay: &mut stereo_delay::StereoDelay,
-    size: usize,
-) {
-    stereo_delay.set_buffer_size(size);
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn reset_heads(stereo_delay: &mut stereo_delay::StereoDelay) {
-    stereo_delay.reset_heads();
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn set_sample_rate_stereo_delay(
-    stereo_delay: &mut stereo_delay::StereoDelay,
-    sample_rate: f32,
-) {
-    stereo_delay.set_sample_rate(sample_rate);
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn set_lp_freq(stereo_delay: &mut stereo_delay::StereoDelay, freq: f32) {
-    stereo_delay.set_lp_freq(freq);
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn set_hp_freq(stereo_delay: &mut stereo_delay::StereoDelay, freq: f32) {
-    stereo_delay.set_hp_freq(freq);
+//-----------------------------------------------------------------------------
+// Multi-tap delay, the tap setters ignore indices from MAX_NUM_TAPS on.
+sample_type_api! {
+    mod multi_tap_delay_f32, multi_tap_delay_f64;
+
+    fn create_multi_tap_delay, create_multi_tap_delay_f64()
+        -> *mut multi_tap_delay::MultiTapDelay<T> {
+        let multi_tap_delay = multi_tap_delay::MultiTapDelay::<T>::with_sample_type();
+        Box::into_raw(Box::new(multi_tap_delay))
+    }
+
+    fn destroy_multi_tap_delay, destroy_multi_tap_delay_f64(
+        multi_tap_delay: *mut multi_tap_delay::MultiTapDelay<T>,
+    ) {
+        drop(Box::from_raw(multi_tap_delay));
+    }
+
+    fn process_multi_tap_delay, process_multi_tap_delay_f64(
+        multi_tap_delay: &mut multi_tap_delay::MultiTapDelay<T>,
+        inputs: &AudioFrame<T>,
+        outputs: &mut AudioFrame<T>,
+    ) {
+        outputs.copy_from_slice(inputs);
+        multi_tap_delay.process_stereo(outputs);
+    }
+
+    fn set_num_taps, set_num_taps_f64(
+        multi_tap_delay: &mut multi_tap_delay::MultiTapDelay<T>,
+        num_taps: usize,
+    ) {
+        multi_tap_delay.set_num_taps(num_taps);
+    }
+
+    fn set_tap_timing, set_tap_timing_f64(
+        multi_tap_delay: &mut multi_tap_delay::MultiTapDelay<T>,
+        tap: usize,
+        value: multi_tap_delay::TapTiming,
+    ) {
+        multi_tap_delay.set_tap_timing(tap, value);
+    }
+
+    fn set_tap_time_ms, set_tap_time_ms_f64(
+        multi_tap_delay: &mut multi_tap_delay::MultiTapDelay<T>,
+        tap: usize,
+        time_ms: f32,
+    ) {
+        multi_tap_delay.set_tap_time_ms(tap, time_ms);
+    }
+
+    fn set_tap_note_len, set_tap_note_len_f64(
+        multi_tap_delay: &mut multi_tap_delay::MultiTapDelay<T>,
+        tap: usize,
+        note_len: f32,
+    ) {
+        multi_tap_delay.set_tap_note_len(tap, note_len);
+    }
+
+    fn set_tap_level, set_tap_level_f64(
+        multi_tap_delay: &mut multi_tap_delay::MultiTapDelay<T>,
+        tap: usize,
+        level: f32,
+    ) {
+        multi_tap_delay.set_tap_level(tap, level);
+    }
+
+    fn set_tap_pan, set_tap_pan_f64(
+        multi_tap_delay: &mut multi_tap_delay::MultiTapDelay<T>,
+        tap: usize,
+        pan: f32,
+    ) {
+        multi_tap_delay.set_tap_pan(tap, pan);
+    }
+
+    fn set_tap_feedback, set_tap_feedback_f64(
+        multi_tap_delay: &mut multi_tap_delay::MultiTapDelay<T>,
+        tap: usize,
+        feedback: f32,
+    ) {
+        multi_tap_delay.set_tap_feedback(tap, feedback);
+    }
+
+    fn set_tap_filter, set_tap_filter_f64(
+        multi_tap_delay: &mut multi_tap_delay::MultiTapDelay<T>,
+        tap: usize,
+        value: multi_tap_delay::TapFilter,
+    ) {
+        multi_tap_delay.set_tap_filter(tap, value);
+    }
+
+    fn set_tap_filter_freq, set_tap_filter_freq_f64(
+        multi_tap_delay: &mut multi_tap_delay::MultiTapDelay<T>,
+        tap: usize,
+        freq: f32,
+    ) {
+        multi_tap_delay.set_tap_filter_freq(tap, freq);
+    }
+
+    fn set_interpolation_multi_tap_delay, set_interpolation_multi_tap_delay_f64(
+        multi_tap_delay: &mut multi_tap_delay::MultiTapDelay<T>,
+        value: stereo_delay::Interpolation,
+    ) {
+        multi_tap_delay.set_interpolation(value);
+    }
+
+    fn set_time_change_mode_multi_tap_delay, set_time_change_mode_multi_tap_delay_f64(
+        multi_tap_delay: &mut multi_tap_delay::MultiTapDelay<T>,
+        value: stereo_delay::TimeChangeMode,
+    ) {
+        multi_tap_delay.set_time_change_mode(value);
+    }
+
+    fn set_glide_rate_multi_tap_delay, set_glide_rate_multi_tap_delay_f64(
+        multi_tap_delay: &mut multi_tap_delay::MultiTapDelay<T>,
+        glide_rate: f32,
+    ) {
+        multi_tap_delay.set_glide_rate(glide_rate);
+    }
+
+    fn set_max_delay_time_ms_multi_tap_delay, set_max_delay_time_ms_multi_tap_delay_f64(
+        multi_tap_delay: &mut multi_tap_delay::MultiTapDelay<T>,
+        max_delay_time_ms: f32,
+    ) {
+        multi_tap_delay.set_max_delay_time_ms(max_delay_time_ms);
+    }
+
+    fn set_tempo_multi_tap_delay, set_tempo_multi_tap_delay_f64(
+        multi_tap_delay: &mut multi_tap_delay::MultiTapDelay<T>,
+        tempo_bpm: f32,
+    ) {
+        multi_tap_delay.set_tempo(tempo_bpm);
+    }
+
+    fn set_sample_rate_multi_tap_delay, set_sample_rate_multi_tap_delay_f64(
+        multi_tap_delay: &mut multi_tap_delay::MultiTapDelay<T>,
+        sample_rate: f32,
+    ) {
+        multi_tap_delay.set_sample_rate(sample_rate);
+    }
+
+    fn clear_buffer_multi_tap_delay, clear_buffer_multi_tap_delay_f64(
+        multi_tap_delay: &mut multi_tap_delay::MultiTapDelay<T>,
+    ) {
+        multi_tap_delay.clear_buffer();
+    }
+
+    /// Writes the delay state including its buffer into `data` if it is large
+    /// enough and returns the required size in bytes.
+    fn save_multi_tap_delay_state, save_multi_tap_delay_state_f64(
+        multi_tap_delay: &multi_tap_delay::MultiTapDelay<T>,
+        data: *mut u8,
+        size: usize,
+    ) -> usize {
+        copy_state(&multi_tap_delay.save_state_binary(), data, size)
+    }
+
+    fn restore_multi_tap_delay_state, restore_multi_tap_delay_state_f64(
+        multi_tap_delay: &mut multi_tap_delay::MultiTapDelay<T>,
+        data: *const u8,
+        size: usize,
+    ) -> bool {
+        if data.is_null() {
+            return false;
+        }
+
+        let state = core::slice::from_raw_parts(data, size);
+        multi_tap_delay.restore_state_binary(state).is_ok()
+    }
+}
+
+unsafe fn copy_state(state: &[u8], data: *mut u8, size: usize) -> usize {
+    if !data.is_null() && size >= state.len() {
+        core::ptr::copy_nonoverlapping(state.as_ptr(), data, state.len());
+    }
+
+    state.len()
 }
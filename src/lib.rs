@@ -1,12 +1,30 @@
 // Copyright(c) 2021 Hansen Audio.
 
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+#[cfg(not(any(feature = "std", feature = "libm")))]
+compile_error!("Either the `std` or the `libm` feature must be enabled.");
+
+extern crate alloc;
+
 pub const NUM_CHANNELS: usize = 4;
-pub type AudioFrame = [f32; NUM_CHANNELS];
+/// One frame of all channels, `f32` unless double precision is requested.
+pub type AudioFrame<T = f32> = [T; NUM_CHANNELS];
 
 const DEFAULT_TEMPO_BPM: f32 = 120.;
 const DEFAULT_SAMPLE_RATE: f32 = 44100.;
 const NUM_STEREO_CHANNELS: usize = 2;
 
 pub mod cbindings;
+mod math;
+pub mod multi_tap_delay;
+mod one_pole;
+mod phase;
+mod sample;
+mod simd;
+pub mod state;
 pub mod stereo_delay;
 pub mod trance_gate;
+
+pub use sample::Sample;
+pub use simd::SIMD_TOLERANCE;
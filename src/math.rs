@@ -0,0 +1,64 @@
+// Copyright(c) 2022 Hansen Audio.
+
+//! Float functions which are not part of `core`. They come from `std` when
+//! it is available and from `libm` on `no_std` targets.
+
+#[cfg(feature = "std")]
+mod imp {
+    pub fn floorf(x: f32) -> f32 {
+        x.floor()
+    }
+
+    pub fn floor(x: f64) -> f64 {
+        x.floor()
+    }
+
+    pub fn truncf(x: f32) -> f32 {
+        x.trunc()
+    }
+
+    pub fn trunc(x: f64) -> f64 {
+        x.trunc()
+    }
+
+    pub fn fabsf(x: f32) -> f32 {
+        x.abs()
+    }
+
+    pub fn fabs(x: f64) -> f64 {
+        x.abs()
+    }
+
+    pub fn expf(x: f32) -> f32 {
+        x.exp()
+    }
+
+    pub fn expm1f(x: f32) -> f32 {
+        x.exp_m1()
+    }
+
+    pub fn sinf(x: f32) -> f32 {
+        x.sin()
+    }
+
+    pub fn cosf(x: f32) -> f32 {
+        x.cos()
+    }
+
+    pub fn tanhf(x: f32) -> f32 {
+        x.tanh()
+    }
+
+    pub fn tanh(x: f64) -> f64 {
+        x.tanh()
+    }
+}
+
+#[cfg(all(not(feature = "std"), feature = "libm"))]
+mod imp {
+    pub use libm::{
+        cosf, expf, expm1f, fabs, fabsf, floor, floorf, sinf, tanh, tanhf, trunc, truncf,
+    };
+}
+
+pub(crate) use imp::*;
@@ -0,0 +1,656 @@
+// Copyright(c) 2022 Hansen Audio.
+
+use crate::math;
+use crate::one_pole::{OnePole, OnePoleType};
+use crate::state::{impl_state_enum, StateError, StateReader, StateWriter};
+use crate::stereo_delay::buffers::Buffers;
+use crate::stereo_delay::delay_line_heads::{DelayLineHeads, NUM_FORWARD_HEADS};
+use crate::stereo_delay::delay_time;
+use crate::stereo_delay::interpolation::Interpolator;
+use crate::stereo_delay::{Interpolation, TimeChangeMode};
+use crate::{AudioFrame, Sample, DEFAULT_SAMPLE_RATE, DEFAULT_TEMPO_BPM, NUM_STEREO_CHANNELS};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::f32::consts::FRAC_PI_4;
+
+pub const MAX_NUM_TAPS: usize = 8;
+/// Upper limit for the summed feedback of all active taps.
+pub const MAX_FEEDBACK_SUM: f32 = 0.98;
+
+/// Defines how the time of a tap is specified.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TapTiming {
+    /// Tap time in milliseconds, following the sample rate.
+    Milliseconds,
+    /// Tap time as a note length, following the tempo.
+    NoteLength,
+}
+
+impl_state_enum!(TapTiming, [TapTiming::Milliseconds, TapTiming::NoteLength]);
+
+/// Defines the filter applied to the output of a tap.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TapFilter {
+    /// The tap is not filtered.
+    Off,
+    /// One pole low pass, darkens the repeats.
+    LowPass,
+    /// One pole high pass, thins out the repeats.
+    HighPass,
+}
+
+impl_state_enum!(
+    TapFilter,
+    [TapFilter::Off, TapFilter::LowPass, TapFilter::HighPass]
+);
+
+#[derive(Clone)]
+struct Tap<T: Sample> {
+    timing: TapTiming,
+    time_ms: f32,
+    note_len: f32,
+    level: f32,
+    pan: f32,
+    feedback: f32,
+    filter_type: TapFilter,
+    filter_freq: f32,
+    filter: OnePole<T>,
+    heads: DelayLineHeads<T>,
+    gains: [T; NUM_STEREO_CHANNELS],
+}
+
+impl<T: Sample> Tap<T> {
+    const DEFAULT_FILTER_FREQ: f32 = 1000.;
+
+    fn new(time_ms: f32) -> Self {
+        const DEFAULT_NOTE_LEN: f32 = 1. / 8.;
+
+        let mut tap = Self {
+            timing: TapTiming::Milliseconds,
+            time_ms,
+            note_len: DEFAULT_NOTE_LEN,
+            level: 1.,
+            pan: 0.,
+            feedback: 0.,
+            filter_type: TapFilter::Off,
+            filter_freq: Self::DEFAULT_FILTER_FREQ,
+            filter: OnePole::new(),
+            heads: DelayLineHeads::new(),
+            gains: [T::ZERO; NUM_STEREO_CHANNELS],
+        };
+
+        tap.update_gains();
+        tap
+    }
+
+    /// Equal power panning, the level applies to both sides.
+    fn update_gains(&mut self) {
+        let angle = (self.pan + 1.) * FRAC_PI_4;
+        self.gains = [
+            T::from_f32(self.level * math::cosf(angle)),
+            T::from_f32(self.level * math::sinf(angle)),
+        ];
+    }
+
+    /// The one pole computes its coefficient in `set_frequency` only, so it
+    /// is updated whenever the type, frequency or sample rate changes.
+    fn update_filter(&mut self, sample_rate: f32) {
+        self.filter.set_filter_type(self.one_pole_type());
+        self.filter.set_sample_rate(sample_rate);
+        self.filter.set_frequency(self.filter_freq);
+    }
+
+    fn one_pole_type(&self) -> OnePoleType {
+        match self.filter_type {
+            TapFilter::HighPass => OnePoleType::HP,
+            _ => OnePoleType::LP,
+        }
+    }
+
+    fn process_filter(&mut self, input: T) -> T {
+        match self.filter_type {
+            TapFilter::Off => input,
+            _ => self.filter.process_mono(input),
+        }
+    }
+
+    fn write_state(&self, writer: &mut StateWriter) {
+        writer.enumeration("tap_timing", self.timing);
+        writer.f32("tap_time_ms", self.time_ms);
+        writer.f32("tap_note_len", self.note_len);
+        writer.f32("tap_level", self.level);
+        writer.f32("tap_pan", self.pan);
+        writer.f32("tap_feedback", self.feedback);
+        writer.enumeration("tap_filter_type", self.filter_type);
+        writer.f32("tap_filter_freq", self.filter_freq);
+        self.filter.write_state(writer);
+        self.heads.write_state(writer);
+    }
+
+    fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.timing = reader.enumeration("tap_timing")?;
+        self.time_ms = reader.f32("tap_time_ms")?;
+        self.note_len = reader.f32("tap_note_len")?;
+        self.level = reader.f32("tap_level")?;
+        self.pan = reader.f32("tap_pan")?;
+        self.feedback = reader.f32("tap_feedback")?;
+        self.filter_type = reader.enumeration("tap_filter_type")?;
+        self.filter_freq = reader.f32("tap_filter_freq")?;
+        self.filter.set_filter_type(self.one_pole_type());
+        self.filter.read_state(reader)?;
+        self.heads.read_state(reader)?;
+        self.update_gains();
+        Ok(())
+    }
+}
+
+/// Delay with up to `MAX_NUM_TAPS` taps reading one shared buffer. The
+/// stereo input is summed to mono, each tap is filtered, panned into the
+/// stereo output and fed back by its own amount. The tap setters ignore
+/// indices from `MAX_NUM_TAPS` on.
+#[derive(Clone)]
+pub struct MultiTapDelay<T: Sample = f32> {
+    bufs: Buffers<T>,
+    taps: [Tap<T>; MAX_NUM_TAPS],
+    interpolator: Interpolator<T, { MAX_NUM_TAPS * NUM_FORWARD_HEADS }>,
+    num_taps: usize,
+    /// Scales the summed tap feedback, see `update_feedback_scale`.
+    feedback_scale: T,
+    max_delay_time_ms: f32,
+    tempo_bpm: f32,
+    sample_rate: f32,
+}
+
+impl MultiTapDelay {
+    pub fn new() -> Self {
+        Self::with_sample_type()
+    }
+}
+
+impl Default for MultiTapDelay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Sample> MultiTapDelay<T> {
+    const LC: usize = 0;
+    const RC: usize = 1;
+    const MONO: usize = 0;
+    const NUM_BUF_CHANNELS: usize = 1;
+    const MIN_BUF_SIZE: usize = 2;
+    const MIN_DELAY_SAMPLES: f32 = 1.;
+    const STATE_TAG: &'static [u8; 4] = b"MTAP";
+
+    /// Creates a delay for the sample type `T`, for instance
+    /// `MultiTapDelay::<f64>::with_sample_type()`.
+    pub fn with_sample_type() -> Self {
+        Self::with_buffers(Buffers::owned(Self::NUM_BUF_CHANNELS, Self::MIN_BUF_SIZE))
+    }
+
+    /// Creates a delay working on caller-supplied memory instead of
+    /// allocating its buffer, e.g. a `static` array on embedded targets.
+    /// The tap times are limited to the length of `memory`. A clone
+    /// allocates its own copy of the memory.
+    pub fn with_buffer(memory: &'static mut [T]) -> Self {
+        assert!(memory.len() >= Self::MIN_BUF_SIZE, "delay memory too small");
+        Self::with_buffers(Buffers::borrowed(Self::NUM_BUF_CHANNELS, memory))
+    }
+
+    /// The taps jump to new times by default, see `set_time_change_mode`.
+    fn with_buffers(bufs: Buffers<T>) -> Self {
+        const DEFAULT_MAX_DELAY_TIME_MS: f32 = 2000.;
+        const DEFAULT_TAP_SPACING_MS: f32 = 250.;
+
+        let mut delay = Self {
+            bufs,
+            taps: core::array::from_fn(|i| Tap::new(DEFAULT_TAP_SPACING_MS * (i + 1) as f32)),
+            interpolator: Interpolator::new(),
+            num_taps: 1,
+            feedback_scale: T::ONE,
+            max_delay_time_ms: DEFAULT_MAX_DELAY_TIME_MS,
+            tempo_bpm: DEFAULT_TEMPO_BPM,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+        };
+
+        for tap in delay.taps.iter_mut() {
+            tap.heads.set_time_change_mode(TimeChangeMode::Jump);
+        }
+        delay.update_sample_rate_dependents();
+        delay
+    }
+
+    pub fn process_stereo(&mut self, outputs: &mut AudioFrame<T>) {
+        let input = (outputs[Self::LC] + outputs[Self::RC]) * T::from_f32(0.5);
+
+        let mut wet = [T::ZERO; NUM_STEREO_CHANNELS];
+        let mut feedback = T::ZERO;
+        let buf = &self.bufs[Self::MONO];
+        for (i, tap) in self.taps[..self.num_taps].iter_mut().enumerate() {
+            let value = tap.heads.read(&mut self.interpolator, i, buf);
+            let value = tap.process_filter(value);
+
+            wet[Self::LC] += value * tap.gains[Self::LC];
+            wet[Self::RC] += value * tap.gains[Self::RC];
+            feedback += value * T::from_f32(tap.feedback);
+        }
+
+        // All taps share the write head, inactive ones keep up with it.
+        let write_pos = self.taps[0].heads.write_pos();
+        self.bufs[Self::MONO][write_pos] = input + feedback * self.feedback_scale;
+        for tap in self.taps.iter_mut() {
+            tap.heads.advance();
+        }
+
+        outputs[Self::LC] = wet[Self::LC];
+        outputs[Self::RC] = wet[Self::RC];
+    }
+
+    /// Sets the number of active taps, from one up to `MAX_NUM_TAPS`.
+    pub fn set_num_taps(&mut self, num_taps: usize) {
+        self.num_taps = num_taps.clamp(1, MAX_NUM_TAPS);
+        self.update_feedback_scale();
+    }
+
+    pub fn num_taps(&self) -> usize {
+        self.num_taps
+    }
+
+    pub fn set_tap_timing(&mut self, tap: usize, timing: TapTiming) {
+        if tap < MAX_NUM_TAPS {
+            self.taps[tap].timing = timing;
+            self.update_delay_time(tap);
+        }
+    }
+
+    pub fn set_tap_time_ms(&mut self, tap: usize, time_ms: f32) {
+        if tap < MAX_NUM_TAPS {
+            self.taps[tap].time_ms = time_ms;
+            self.update_delay_time(tap);
+        }
+    }
+
+    /// Sets the tap time as a note length, e.g. `1. / 4.` for a quarter or
+    /// `3. / 16.` for a dotted 1/8.
+    pub fn set_tap_note_len(&mut self, tap: usize, note_len: f32) {
+        if tap < MAX_NUM_TAPS {
+            self.taps[tap].note_len = note_len;
+            self.update_delay_time(tap);
+        }
+    }
+
+    /// Sets the linear output gain of a tap.
+    pub fn set_tap_level(&mut self, tap: usize, level: f32) {
+        if tap < MAX_NUM_TAPS {
+            self.taps[tap].level = level;
+            self.taps[tap].update_gains();
+        }
+    }
+
+    /// Pans a tap from -1, left, to 1, right.
+    pub fn set_tap_pan(&mut self, tap: usize, pan: f32) {
+        if tap < MAX_NUM_TAPS {
+            self.taps[tap].pan = pan.clamp(-1., 1.);
+            self.taps[tap].update_gains();
+        }
+    }
+
+    /// Sets how much of a tap is fed back into the buffer. The feedback of
+    /// all active taps adds up, a sum above `MAX_FEEDBACK_SUM` scales them
+    /// down so the repeats always die out.
+    pub fn set_tap_feedback(&mut self, tap: usize, feedback: f32) {
+        if tap < MAX_NUM_TAPS {
+            self.taps[tap].feedback = feedback;
+            self.update_feedback_scale();
+        }
+    }
+
+    pub fn set_tap_filter(&mut self, tap: usize, filter: TapFilter) {
+        if tap < MAX_NUM_TAPS {
+            self.taps[tap].filter_type = filter;
+            self.taps[tap].update_filter(self.sample_rate);
+        }
+    }
+
+    pub fn set_tap_filter_freq(&mut self, tap: usize, freq: f32) {
+        if tap < MAX_NUM_TAPS {
+            self.taps[tap].filter_freq = freq;
+            self.taps[tap].update_filter(self.sample_rate);
+        }
+    }
+
+    /// Selects how the taps read the buffer between two samples, linear by
+    /// default.
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.interpolator.set_interpolation(interpolation);
+    }
+
+    /// Selects how the taps move to new times, jumping by default.
+    pub fn set_time_change_mode(&mut self, time_change_mode: TimeChangeMode) {
+        for tap in self.taps.iter_mut() {
+            tap.heads.set_time_change_mode(time_change_mode);
+        }
+    }
+
+    /// Sets the speed deviation of the taps while gliding to a new time,
+    /// 0.3 by default.
+    pub fn set_glide_rate(&mut self, glide_rate: f32) {
+        for tap in self.taps.iter_mut() {
+            tap.heads.set_glide_rate(glide_rate);
+        }
+    }
+
+    /// Sizes the buffer to hold `max_delay_time_ms`, 2000 ms by default.
+    /// Allocates, so call it outside the audio thread. Caller-supplied
+    /// memory limits the size instead. Longer tap times are limited to the
+    /// maximum.
+    pub fn set_max_delay_time_ms(&mut self, max_delay_time_ms: f32) {
+        self.max_delay_time_ms = max_delay_time_ms;
+        self.update_buffer_size();
+    }
+
+    /// Sets the tempo for note length tap times.
+    pub fn set_tempo(&mut self, tempo_bpm: f32) {
+        self.tempo_bpm = tempo_bpm;
+        self.update_delay_times();
+    }
+
+    /// Resizes the buffer to keep the maximum delay time, see
+    /// `set_max_delay_time_ms`.
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.update_sample_rate_dependents();
+    }
+
+    pub fn clear_buffer(&mut self) {
+        self.bufs.clear();
+    }
+
+    /// Saves the complete runtime state including the buffer in the
+    /// versioned binary format.
+    pub fn save_state_binary(&self) -> Vec<u8> {
+        self.write_state().to_binary(Self::STATE_TAG)
+    }
+
+    /// Saves the complete runtime state in the versioned text format.
+    pub fn save_state_text(&self) -> String {
+        self.write_state().to_text(Self::STATE_TAG)
+    }
+
+    /// Restores a state saved by `save_state_binary`. On error the delay
+    /// stays unchanged.
+    pub fn restore_state_binary(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut reader = StateReader::from_binary(data, Self::STATE_TAG)?;
+        self.restore_state(&mut reader)
+    }
+
+    /// Restores a state saved by `save_state_text`.
+    pub fn restore_state_text(&mut self, text: &str) -> Result<(), StateError> {
+        let mut reader = StateReader::from_text(text, Self::STATE_TAG)?;
+        self.restore_state(&mut reader)
+    }
+
+    fn write_state(&self) -> StateWriter {
+        let mut writer = StateWriter::new();
+
+        writer.usize("buffer_size", self.bufs.len());
+        writer.samples("buffer", &self.bufs[Self::MONO]);
+        for tap in &self.taps {
+            tap.write_state(&mut writer);
+        }
+        self.interpolator.write_state(&mut writer);
+        writer.usize("num_taps", self.num_taps);
+        writer.f32("max_delay_time_ms", self.max_delay_time_ms);
+        writer.f32("tempo_bpm", self.tempo_bpm);
+        writer.f32("sample_rate", self.sample_rate);
+
+        writer
+    }
+
+    /// Reads everything first and only applies it when the whole state is
+    /// valid, the delay memory may be borrowed and cannot be cloned.
+    fn restore_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        let buffer_size = reader.usize("buffer_size")?;
+        if buffer_size < Self::MIN_BUF_SIZE || buffer_size > self.bufs.capacity() {
+            return Err(StateError::InvalidValue);
+        }
+
+        let buf = reader.samples("buffer")?;
+        if buf.len() != buffer_size {
+            return Err(StateError::InvalidValue);
+        }
+        let mut taps = self.taps.clone();
+        for tap in taps.iter_mut() {
+            tap.heads.set_buffer_size(buffer_size);
+            tap.read_state(reader)?;
+        }
+        let mut interpolator = self.interpolator;
+        interpolator.read_state(reader)?;
+        let num_taps = reader.usize("num_taps")?;
+        let max_delay_time_ms = reader.f32("max_delay_time_ms")?;
+        let tempo_bpm = reader.f32("tempo_bpm")?;
+        let sample_rate = reader.f32("sample_rate")?;
+
+        let write_pos = taps[0].heads.write_pos();
+        let is_write_pos_shared = taps.iter().all(|tap| tap.heads.write_pos() == write_pos);
+        if !is_write_pos_shared || !(1..=MAX_NUM_TAPS).contains(&num_taps) {
+            return Err(StateError::InvalidValue);
+        }
+
+        self.bufs.resize(buffer_size);
+        self.bufs[Self::MONO].copy_from_slice(&buf);
+        self.taps = taps;
+        self.interpolator = interpolator;
+        self.num_taps = num_taps;
+        self.max_delay_time_ms = max_delay_time_ms;
+        self.tempo_bpm = tempo_bpm;
+        self.sample_rate = sample_rate;
+        self.update_feedback_scale();
+        Ok(())
+    }
+
+    /// Taps at different times can add up in phase, so the sum of their
+    /// absolute feedbacks bounds the gain of the feedback loop.
+    fn update_feedback_scale(&mut self) {
+        let sum: f32 = self.taps[..self.num_taps]
+            .iter()
+            .map(|tap| tap.feedback.abs())
+            .sum();
+        self.feedback_scale = match sum > MAX_FEEDBACK_SUM {
+            true => T::from_f32(MAX_FEEDBACK_SUM / sum),
+            false => T::ONE,
+        };
+    }
+
+    fn update_sample_rate_dependents(&mut self) {
+        let crossfade_len = delay_time::ms_to_samples(delay_time::CROSSFADE_MS, self.sample_rate);
+        for tap in self.taps.iter_mut() {
+            tap.update_filter(self.sample_rate);
+            tap.heads.set_crossfade_len(crossfade_len as usize);
+        }
+        self.update_buffer_size();
+    }
+
+    fn update_buffer_size(&mut self) {
+        let max_delay_samples = delay_time::ms_to_samples(self.max_delay_time_ms, self.sample_rate);
+        let size = self.bufs.resize(delay_time::buffer_size(max_delay_samples));
+        for tap in self.taps.iter_mut() {
+            tap.heads.set_buffer_size(size);
+        }
+        self.update_delay_times();
+    }
+
+    fn update_delay_times(&mut self) {
+        for tap in 0..MAX_NUM_TAPS {
+            self.update_delay_time(tap);
+        }
+    }
+
+    /// The heads limit the time to the buffer size.
+    fn update_delay_time(&mut self, tap: usize) {
+        let tap = &mut self.taps[tap];
+        let delay_samples = match tap.timing {
+            TapTiming::Milliseconds => delay_time::ms_to_samples(tap.time_ms, self.sample_rate),
+            TapTiming::NoteLength => {
+                delay_time::note_len_to_samples(tap.note_len, self.tempo_bpm, self.sample_rate)
+            }
+        };
+        tap.heads
+            .set_heads_diff_samples(delay_samples.max(Self::MIN_DELAY_SAMPLES));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multi_tap_delay_taps() {
+        const SAMPLE_RATE: f32 = 1000.;
+        const TOLERANCE: f32 = 1e-6;
+
+        let mut delay = MultiTapDelay::new();
+        delay.set_sample_rate(SAMPLE_RATE);
+        delay.set_max_delay_time_ms(500.);
+        delay.set_num_taps(3);
+        delay.set_tap_time_ms(0, 100.);
+        delay.set_tap_pan(0, -1.);
+        // An eighth note lasts 250 ms at 120 BPM.
+        delay.set_tap_timing(1, TapTiming::NoteLength);
+        delay.set_tap_note_len(1, 1. / 8.);
+        delay.set_tap_pan(1, 1.);
+        delay.set_tap_level(1, 0.5);
+        delay.set_tap_time_ms(2, 300.);
+        delay.set_tap_filter(2, TapFilter::LowPass);
+        delay.set_tap_filter_freq(2, 10.);
+        delay.set_tap_feedback(0, 0.5);
+        delay.clear_buffer();
+
+        let outputs: Vec<AudioFrame> = (0..500)
+            .map(|n| {
+                let value = match n {
+                    0 => 1.,
+                    _ => 0.,
+                };
+                let mut frame: AudioFrame = [value, value, 0., 0.];
+                delay.process_stereo(&mut frame);
+                frame
+            })
+            .collect();
+
+        // The first tap is panned left and fed back, the second one right.
+        assert!((outputs[100][0] - 1.).abs() < TOLERANCE);
+        assert!(outputs[100][1].abs() < TOLERANCE);
+        assert!((outputs[200][0] - 0.5).abs() < TOLERANCE);
+        assert!((outputs[250][1] - 0.5).abs() < TOLERANCE);
+        assert!(outputs[250][0].abs() < TOLERANCE);
+
+        // The low pass smears the third tap, which is centered.
+        assert!(outputs[300][1] > 0. && outputs[300][1] < 0.1);
+        assert!(outputs[301][1] > 0.);
+
+        // Out of range taps are ignored.
+        delay.set_tap_timing(MAX_NUM_TAPS, TapTiming::NoteLength);
+        delay.set_tap_time_ms(MAX_NUM_TAPS, 1.);
+        delay.set_tap_note_len(MAX_NUM_TAPS, 1.);
+        delay.set_tap_level(MAX_NUM_TAPS, 1.);
+        delay.set_tap_pan(MAX_NUM_TAPS, 1.);
+        delay.set_tap_feedback(MAX_NUM_TAPS, 1.);
+        delay.set_tap_filter(MAX_NUM_TAPS, TapFilter::HighPass);
+        delay.set_tap_filter_freq(MAX_NUM_TAPS, 1.);
+
+        let mut restored = MultiTapDelay::new();
+        restored
+            .restore_state_binary(&delay.save_state_binary())
+            .unwrap();
+        let mut frame: AudioFrame = [0.; 4];
+        let mut restored_frame: AudioFrame = [0.; 4];
+        delay.process_stereo(&mut frame);
+        restored.process_stereo(&mut restored_frame);
+        assert_eq!(frame, restored_frame);
+    }
+
+    #[test]
+    fn test_multi_tap_delay_feedback_stays_bounded() {
+        const SAMPLE_RATE: f32 = 1000.;
+        const NUM_SAMPLES: usize = 4000;
+
+        for interpolation in [
+            Interpolation::Linear,
+            Interpolation::CubicHermite,
+            Interpolation::Lagrange,
+            Interpolation::Allpass,
+            Interpolation::Sinc,
+        ] {
+            let mut delay = MultiTapDelay::new();
+            delay.set_sample_rate(SAMPLE_RATE);
+            delay.set_interpolation(interpolation);
+            delay.set_num_taps(MAX_NUM_TAPS);
+            for tap in 0..MAX_NUM_TAPS {
+                delay.set_tap_time_ms(tap, 10.5 + 3. * tap as f32);
+                delay.set_tap_feedback(tap, 0.5);
+            }
+            delay.clear_buffer();
+
+            let outputs: Vec<f32> = (0..NUM_SAMPLES)
+                .map(|n| {
+                    let value = match n {
+                        0 => 1.,
+                        _ => 0.,
+                    };
+                    let mut frame: AudioFrame = [value, value, 0., 0.];
+                    delay.process_stereo(&mut frame);
+                    frame[0]
+                })
+                .collect();
+
+            // The repeats die out instead of growing by up to four times per
+            // round trip.
+            let peak = |values: &[f32]| values.iter().fold(0_f32, |peak, x| peak.max(x.abs()));
+            assert!(peak(&outputs) < 10.);
+            assert!(peak(&outputs[NUM_SAMPLES - 500..]) < 0.1 * peak(&outputs[..500]));
+        }
+    }
+
+    #[test]
+    fn test_multi_tap_delay_with_buffer() {
+        const MEMORY_SIZE: usize = 64;
+        const SAMPLE_RATE: f32 = 1000.;
+
+        let memory = Box::leak(Box::new([1_f32; MEMORY_SIZE]));
+        let mut delay = MultiTapDelay::with_buffer(memory);
+        delay.set_sample_rate(SAMPLE_RATE);
+        delay.set_tap_time_ms(0, 100.);
+        delay.set_tap_pan(0, -1.);
+        delay.clear_buffer();
+
+        let process = |delay: &mut MultiTapDelay, value: f32| {
+            let mut frame: AudioFrame = [value, value, 0., 0.];
+            delay.process_stereo(&mut frame);
+            frame[0]
+        };
+
+        // The 100 ms tap is limited to the memory.
+        let outputs: Vec<f32> = (0..MEMORY_SIZE)
+            .map(|n| match n {
+                0 => process(&mut delay, 1.),
+                _ => process(&mut delay, 0.),
+            })
+            .collect();
+        assert_eq!(outputs[MEMORY_SIZE - 1], 1.);
+        assert!(outputs[..MEMORY_SIZE - 1].iter().all(|&x| x == 0.));
+
+        // A state with a larger buffer does not fit into the memory.
+        let state = MultiTapDelay::default().save_state_binary();
+        assert!(delay.restore_state_binary(&state).is_err());
+
+        // A clone owns a copy of the memory and continues identically.
+        let mut clone = delay.clone();
+        assert!(matches!(clone.bufs, Buffers::Owned(_)));
+        for _ in 0..MEMORY_SIZE {
+            assert_eq!(process(&mut clone, 0.), process(&mut delay, 0.));
+        }
+    }
+}
@@ -0,0 +1,155 @@
+// Copyright(c) 2022 Hansen Audio.
+
+//! Port of `dsp_tool_box_rs::filtering::one_pole::OnePole`, which keeps the
+//! filter memory accessible for saving and restoring the effect state.
+
+use crate::state::{StateError, StateReader, StateWriter};
+use crate::{AudioFrame, Sample, NUM_CHANNELS};
+use core::f64::consts::PI;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OnePoleType {
+    LP,
+    HP,
+}
+
+/// Like the original, coefficients are only computed in `set_frequency`,
+/// using the sample rate set before.
+#[derive(Debug, Clone)]
+pub(crate) struct OnePole<T: Sample = f32> {
+    filter_type: OnePoleType,
+    sample_rate: T,
+    alpha: T,
+    x1: AudioFrame<T>,
+    y1: AudioFrame<T>,
+}
+
+impl<T: Sample> OnePole<T> {
+    const MONO: usize = 0;
+
+    pub fn new() -> Self {
+        Self {
+            filter_type: OnePoleType::LP,
+            sample_rate: T::ONE,
+            alpha: T::ONE,
+            x1: [T::ZERO; NUM_CHANNELS],
+            y1: [T::ZERO; NUM_CHANNELS],
+        }
+    }
+
+    pub fn set_filter_type(&mut self, filter_type: OnePoleType) {
+        self.filter_type = filter_type;
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = T::from_f32(sample_rate);
+    }
+
+    pub fn set_frequency(&mut self, frequency: f32) {
+        let two = T::from_f32(2.);
+        let rc = T::ONE / (two * T::from_f64(PI) * T::from_f32(frequency));
+        let dt = T::ONE / self.sample_rate;
+
+        self.alpha = match self.filter_type {
+            OnePoleType::LP => dt / (rc + dt),
+            OnePoleType::HP => rc / (rc + dt),
+        };
+    }
+
+    pub fn process_mono(&mut self, input: T) -> T {
+        self.process_channel(Self::MONO, input)
+    }
+
+    pub fn process(&mut self, outputs: &mut AudioFrame<T>) {
+        let alpha = [self.alpha; NUM_CHANNELS];
+
+        let y = match self.filter_type {
+            OnePoleType::LP => {
+                let rest = [T::ONE - self.alpha; NUM_CHANNELS];
+                T::add_frame(
+                    &T::mul_frame(&alpha, outputs),
+                    &T::mul_frame(&rest, &self.y1),
+                )
+            }
+            OnePoleType::HP => {
+                let diff = T::sub_frame(&T::add_frame(&self.y1, outputs), &self.x1);
+                T::mul_frame(&alpha, &diff)
+            }
+        };
+
+        self.x1 = *outputs;
+        self.y1 = y;
+        *outputs = y;
+    }
+
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.sample("one_pole_sample_rate", self.sample_rate);
+        writer.sample("one_pole_alpha", self.alpha);
+        writer.samples("one_pole_x1", &self.x1);
+        writer.samples("one_pole_y1", &self.y1);
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.sample_rate = reader.sample("one_pole_sample_rate")?;
+        self.alpha = reader.sample("one_pole_alpha")?;
+        reader.samples_into("one_pole_x1", &mut self.x1)?;
+        reader.samples_into("one_pole_y1", &mut self.y1)
+    }
+
+    fn process_channel(&mut self, ch: usize, x: T) -> T {
+        let y = match self.filter_type {
+            OnePoleType::LP => self.alpha * x + (T::ONE - self.alpha) * self.y1[ch],
+            OnePoleType::HP => self.alpha * (self.y1[ch] + x - self.x1[ch]),
+        };
+
+        self.x1[ch] = x;
+        self.y1[ch] = y;
+        y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_one_pole_matches_original() {
+        // Impulse response of the original high pass followed by the original
+        // low pass, taken from the stereo delay tests before the port. The
+        // frequencies were set before the sample rate, so the default sample
+        // rate of one applies.
+        const EXPECTED: [f32; 8] = [
+            0.007894864,
+            -0.007832478,
+            -6.1894214e-5,
+            -4.8865047e-7,
+            -3.857857e-9,
+            -3.0457484e-11,
+            -2.4045948e-13,
+            -1.8984088e-15,
+        ];
+
+        let filters = || {
+            let mut hp = OnePole::<f32>::new();
+            hp.set_filter_type(OnePoleType::HP);
+            hp.set_frequency(20.);
+            let mut lp = OnePole::<f32>::new();
+            lp.set_frequency(22050.);
+            (hp, lp)
+        };
+        let (mut hp, mut lp) = filters();
+        let (mut hp_mono, mut lp_mono) = filters();
+
+        let mut input = 1.;
+        for expected in EXPECTED {
+            let mut frame = [input; NUM_CHANNELS];
+            hp.process(&mut frame);
+            lp.process(&mut frame);
+            let mono = lp_mono.process_mono(hp_mono.process_mono(input));
+
+            assert_eq!(mono, expected);
+            assert_eq!(frame, [expected; NUM_CHANNELS]);
+            input = 0.;
+        }
+    }
+}
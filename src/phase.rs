@@ -0,0 +1,231 @@
+// Copyright(c) 2022 Hansen Audio.
+
+//! Port of `dsp_tool_box_rs::modulation::phase::Phase`, which keeps the
+//! project time accessible for saving and restoring the effect state and
+//! builds without `std`.
+
+use crate::math;
+use crate::state::{impl_state_enum, StateError, StateReader, StateWriter};
+use crate::{DEFAULT_SAMPLE_RATE, DEFAULT_TEMPO_BPM};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SyncMode {
+    /// The rate is in Hz.
+    FreeRunning,
+    /// The rate is in cycles per quarter note and follows the tempo.
+    TempoSync,
+    /// Like `TempoSync`, but the phase follows the project time.
+    ProjectSync,
+}
+
+impl_state_enum!(
+    SyncMode,
+    [
+        SyncMode::FreeRunning,
+        SyncMode::TempoSync,
+        SyncMode::ProjectSync,
+    ]
+);
+
+/// Like the original, the phase value is owned by the caller and passed to
+/// `advance`, the phase only holds the rate and the timing information.
+#[derive(Debug, Clone)]
+pub(crate) struct Phase {
+    rate: f32,
+    sync_mode: SyncMode,
+    tempo_bpm: f32,
+    sample_rate: f32,
+    project_time: f64,
+}
+
+impl Phase {
+    const QUARTERS_PER_NOTE: f32 = 4.;
+    const SECS_PER_MINUTE: f32 = 60.;
+
+    pub fn new() -> Self {
+        Self {
+            rate: 1.,
+            sync_mode: SyncMode::FreeRunning,
+            tempo_bpm: DEFAULT_TEMPO_BPM,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            project_time: 0.,
+        }
+    }
+
+    /// Converts a note length, e.g. `1. / 4.` for a quarter, into cycles per
+    /// quarter note.
+    pub fn note_len_to_rate(note_len: f32) -> f32 {
+        1. / (note_len * Self::QUARTERS_PER_NOTE)
+    }
+
+    pub fn rate_to_note_len(rate: f32) -> f32 {
+        1. / (rate * Self::QUARTERS_PER_NOTE)
+    }
+
+    pub fn set_rate(&mut self, rate: f32) {
+        self.rate = rate;
+    }
+
+    pub fn set_note_len(&mut self, note_len: f32) {
+        self.rate = Self::note_len_to_rate(note_len);
+    }
+
+    pub fn note_len(&self) -> f32 {
+        Self::rate_to_note_len(self.rate)
+    }
+
+    pub fn set_sync_mode(&mut self, sync_mode: SyncMode) {
+        self.sync_mode = sync_mode;
+    }
+
+    pub fn set_tempo(&mut self, tempo_bpm: f32) {
+        self.tempo_bpm = tempo_bpm;
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    /// Sets the project time in quarter notes. Between two calls it moves on
+    /// with the tempo.
+    pub fn set_project_time(&mut self, project_time: f64) {
+        self.project_time = project_time;
+    }
+
+    /// Advances `phase` by `num_samples` and wraps it into [0, 1). Returns
+    /// true if the phase wrapped around. With `ProjectSync` the phase is
+    /// taken from the project time.
+    pub fn advance(&mut self, phase: &mut f32, num_samples: usize) -> bool {
+        if self.sync_mode == SyncMode::ProjectSync {
+            return self.advance_project_sync(phase, num_samples);
+        }
+
+        *phase += self.increment() * num_samples as f32;
+        if *phase < 1. {
+            return false;
+        }
+
+        *phase -= math::floorf(*phase);
+        true
+    }
+
+    /// Advances `phase` by `num_samples` and stops at one. Returns true once
+    /// one is reached. A one shot starts with its trigger, so it follows the
+    /// tempo but not the project time.
+    pub fn advance_one_shot(&mut self, phase: &mut f32, num_samples: usize) -> bool {
+        if *phase >= 1. {
+            return true;
+        }
+
+        *phase += self.increment() * num_samples as f32;
+        if *phase < 1. {
+            return false;
+        }
+
+        *phase = 1.;
+        true
+    }
+
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.f32("phase_rate", self.rate);
+        writer.enumeration("phase_sync_mode", self.sync_mode);
+        writer.f32("phase_tempo_bpm", self.tempo_bpm);
+        writer.f32("phase_sample_rate", self.sample_rate);
+        writer.f64("phase_project_time", self.project_time);
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.rate = reader.f32("phase_rate")?;
+        self.sync_mode = reader.enumeration("phase_sync_mode")?;
+        self.tempo_bpm = reader.f32("phase_tempo_bpm")?;
+        self.sample_rate = reader.f32("phase_sample_rate")?;
+        self.project_time = reader.f64("phase_project_time")?;
+        Ok(())
+    }
+
+    /// Phase increment per sample.
+    fn increment(&self) -> f32 {
+        match self.sync_mode {
+            SyncMode::FreeRunning => self.rate / self.sample_rate,
+            _ => self.rate * self.tempo_bpm / Self::SECS_PER_MINUTE / self.sample_rate,
+        }
+    }
+
+    fn advance_project_sync(&mut self, phase: &mut f32, num_samples: usize) -> bool {
+        // The largest value below one, the cast to f32 could round up to one.
+        const MAX_PHASE: f32 = 1. - f32::EPSILON / 2.;
+
+        let quarters_per_sample =
+            self.tempo_bpm as f64 / Self::SECS_PER_MINUTE as f64 / self.sample_rate as f64;
+        self.project_time += quarters_per_sample * num_samples as f64;
+
+        let cycles = self.project_time.max(0.) * self.rate as f64;
+        let next_phase = ((cycles - math::floor(cycles)) as f32).min(MAX_PHASE);
+        let is_overflow = next_phase < *phase;
+        *phase = next_phase;
+        is_overflow
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phase_sync_modes() {
+        const SAMPLE_RATE: f32 = 1000.;
+
+        let mut phase = Phase::new();
+        phase.set_sample_rate(SAMPLE_RATE);
+        phase.set_rate(100.);
+        let mut value = 0.;
+        assert!(!phase.advance(&mut value, 5));
+        assert!((value - 0.5).abs() < 1e-6);
+        assert!(phase.advance(&mut value, 6));
+        assert!((value - 0.1).abs() < 1e-6);
+
+        // A quarter note lasts 500 ms at 120 bpm.
+        phase.set_sync_mode(SyncMode::TempoSync);
+        phase.set_note_len(1. / 4.);
+        assert_eq!(phase.note_len(), 0.25);
+        let mut value = 0.;
+        assert!(!phase.advance_one_shot(&mut value, 250));
+        assert!((value - 0.5).abs() < 1e-6);
+        assert!(phase.advance_one_shot(&mut value, 500));
+        assert_eq!(value, 1.);
+
+        // The project time sets the phase, whatever the value was before.
+        phase.set_sync_mode(SyncMode::ProjectSync);
+        phase.set_project_time(2.75);
+        let mut value = 0.;
+        assert!(!phase.advance(&mut value, 50));
+        assert!((value - 0.85).abs() < 1e-6);
+        assert!(phase.advance(&mut value, 100));
+        assert!((value - 0.05).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_phase_note_len_conversion() {
+        // Cycles per quarter note, like the original.
+        for (note_len, rate) in [
+            (1., 0.25),
+            (1. / 4., 1.),
+            (1. / 16., 4.),
+            (3. / 8., 2. / 3.),
+        ] {
+            assert_eq!(Phase::note_len_to_rate(note_len), rate);
+            assert!((Phase::rate_to_note_len(rate) - note_len).abs() < 1e-7);
+        }
+
+        // A sixteenth at 120 bpm and 48 kHz lasts 6000 samples.
+        let mut phase = Phase::new();
+        phase.set_sample_rate(48000.);
+        phase.set_sync_mode(SyncMode::TempoSync);
+        phase.set_note_len(1. / 16.);
+        let mut value = 0.;
+        assert!(!phase.advance(&mut value, 3000));
+        assert!((value - 0.5).abs() < 1e-6);
+        assert!(phase.advance(&mut value, 4500));
+        assert!((value - 0.25).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,251 @@
+// Copyright(c) 2022 Hansen Audio.
+
+//! Sample types the effects can process, `f32` and `f64`.
+
+use crate::math;
+use crate::simd::F32x4;
+use crate::{AudioFrame, NUM_CHANNELS};
+use core::fmt::Debug;
+use core::ops::{Add, AddAssign, Div, Mul, MulAssign, Neg, Sub, SubAssign};
+
+mod private {
+    pub trait Sealed {}
+
+    impl Sealed for f32 {}
+    impl Sealed for f64 {}
+}
+
+pub trait Sample:
+    Copy
+    + Debug
+    + Default
+    + PartialEq
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+    + AddAssign
+    + SubAssign
+    + MulAssign
+    + private::Sealed
+    + Send
+    + Sync
+    + 'static
+{
+    const ZERO: Self;
+    const ONE: Self;
+    /// Number of bits of the type, 32 or 64.
+    const BITS: u32;
+
+    fn from_f32(value: f32) -> Self;
+    fn from_f64(value: f64) -> Self;
+    fn from_usize(value: usize) -> Self;
+    fn to_f32(self) -> f32;
+    fn to_f64(self) -> f64;
+    /// Truncates towards zero, negative values saturate at zero.
+    fn to_usize(self) -> usize;
+
+    fn floor(self) -> Self;
+    fn fract(self) -> Self;
+    fn abs(self) -> Self;
+    fn tanh(self) -> Self;
+    fn max(self, other: Self) -> Self;
+    fn min(self, other: Self) -> Self;
+
+    /// Lane-wise `a + b`.
+    fn add_frame(a: &AudioFrame<Self>, b: &AudioFrame<Self>) -> AudioFrame<Self>;
+    /// Lane-wise `a - b`.
+    fn sub_frame(a: &AudioFrame<Self>, b: &AudioFrame<Self>) -> AudioFrame<Self>;
+    /// Lane-wise `a * b`.
+    fn mul_frame(a: &AudioFrame<Self>, b: &AudioFrame<Self>) -> AudioFrame<Self>;
+    /// Lane-wise maximum of `a` and `b`.
+    fn max_frame(a: &AudioFrame<Self>, b: &AudioFrame<Self>) -> AudioFrame<Self>;
+    /// Swaps lane 0 with 1 and lane 2 with 3.
+    fn swap_pairs_frame(a: &AudioFrame<Self>) -> AudioFrame<Self>;
+}
+
+impl Sample for f32 {
+    const ZERO: Self = 0.;
+    const ONE: Self = 1.;
+    const BITS: u32 = 32;
+
+    #[inline]
+    fn from_f32(value: f32) -> Self {
+        value
+    }
+
+    #[inline]
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+
+    #[inline]
+    fn from_usize(value: usize) -> Self {
+        value as f32
+    }
+
+    #[inline]
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    #[inline]
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    #[inline]
+    fn to_usize(self) -> usize {
+        self as usize
+    }
+
+    #[inline]
+    fn floor(self) -> Self {
+        math::floorf(self)
+    }
+
+    #[inline]
+    fn fract(self) -> Self {
+        self - math::truncf(self)
+    }
+
+    #[inline]
+    fn abs(self) -> Self {
+        math::fabsf(self)
+    }
+
+    #[inline]
+    fn tanh(self) -> Self {
+        math::tanhf(self)
+    }
+
+    #[inline]
+    fn max(self, other: Self) -> Self {
+        f32::max(self, other)
+    }
+
+    #[inline]
+    fn min(self, other: Self) -> Self {
+        f32::min(self, other)
+    }
+
+    #[inline]
+    fn add_frame(a: &AudioFrame<Self>, b: &AudioFrame<Self>) -> AudioFrame<Self> {
+        (F32x4::from_array(*a) + F32x4::from_array(*b)).to_array()
+    }
+
+    #[inline]
+    fn sub_frame(a: &AudioFrame<Self>, b: &AudioFrame<Self>) -> AudioFrame<Self> {
+        (F32x4::from_array(*a) - F32x4::from_array(*b)).to_array()
+    }
+
+    #[inline]
+    fn mul_frame(a: &AudioFrame<Self>, b: &AudioFrame<Self>) -> AudioFrame<Self> {
+        (F32x4::from_array(*a) * F32x4::from_array(*b)).to_array()
+    }
+
+    #[inline]
+    fn max_frame(a: &AudioFrame<Self>, b: &AudioFrame<Self>) -> AudioFrame<Self> {
+        F32x4::from_array(*a).max(F32x4::from_array(*b)).to_array()
+    }
+
+    #[inline]
+    fn swap_pairs_frame(a: &AudioFrame<Self>) -> AudioFrame<Self> {
+        F32x4::from_array(*a).swap_pairs().to_array()
+    }
+}
+
+// Four doubles do not fit into one 128 bit register, the loops below are
+// left to the auto-vectorizer.
+impl Sample for f64 {
+    const ZERO: Self = 0.;
+    const ONE: Self = 1.;
+    const BITS: u32 = 64;
+
+    #[inline]
+    fn from_f32(value: f32) -> Self {
+        value as f64
+    }
+
+    #[inline]
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+
+    #[inline]
+    fn from_usize(value: usize) -> Self {
+        value as f64
+    }
+
+    #[inline]
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+
+    #[inline]
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    #[inline]
+    fn to_usize(self) -> usize {
+        self as usize
+    }
+
+    #[inline]
+    fn floor(self) -> Self {
+        math::floor(self)
+    }
+
+    #[inline]
+    fn fract(self) -> Self {
+        self - math::trunc(self)
+    }
+
+    #[inline]
+    fn abs(self) -> Self {
+        math::fabs(self)
+    }
+
+    #[inline]
+    fn tanh(self) -> Self {
+        math::tanh(self)
+    }
+
+    #[inline]
+    fn max(self, other: Self) -> Self {
+        f64::max(self, other)
+    }
+
+    #[inline]
+    fn min(self, other: Self) -> Self {
+        f64::min(self, other)
+    }
+
+    #[inline]
+    fn add_frame(a: &AudioFrame<Self>, b: &AudioFrame<Self>) -> AudioFrame<Self> {
+        core::array::from_fn::<_, NUM_CHANNELS, _>(|i| a[i] + b[i])
+    }
+
+    #[inline]
+    fn sub_frame(a: &AudioFrame<Self>, b: &AudioFrame<Self>) -> AudioFrame<Self> {
+        core::array::from_fn::<_, NUM_CHANNELS, _>(|i| a[i] - b[i])
+    }
+
+    #[inline]
+    fn mul_frame(a: &AudioFrame<Self>, b: &AudioFrame<Self>) -> AudioFrame<Self> {
+        core::array::from_fn::<_, NUM_CHANNELS, _>(|i| a[i] * b[i])
+    }
+
+    #[inline]
+    fn max_frame(a: &AudioFrame<Self>, b: &AudioFrame<Self>) -> AudioFrame<Self> {
+        core::array::from_fn::<_, NUM_CHANNELS, _>(|i| a[i].max(b[i]))
+    }
+
+    #[inline]
+    fn swap_pairs_frame(a: &AudioFrame<Self>) -> AudioFrame<Self> {
+        [a[1], a[0], a[3], a[2]]
+    }
+}
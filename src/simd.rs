@@ -0,0 +1,88 @@
+// Copyright(c) 2022 Hansen Audio.
+
+//! Four lane `f32` vector with the layout of an `AudioFrame`.
+//!
+//! Uses SSE2 on x86 and x86_64, NEON on aarch64 and a scalar fallback on
+//! every other target. Only lane-wise add, sub, mul and max are provided and
+//! none of them is fused, so each backend rounds exactly like the scalar
+//! code. For finite inputs the results are bit-identical to the scalar path,
+//! i.e. the tolerance is `SIMD_TOLERANCE`. Only NaN handling of `max` may
+//! differ between the backends.
+
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature = "sse2"
+))]
+mod sse2;
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature = "sse2"
+))]
+pub(crate) use sse2::F32x4;
+
+#[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+mod neon;
+#[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+pub(crate) use neon::F32x4;
+
+// Compiled on SIMD targets too, so tests can compare against it.
+#[cfg(any(
+    test,
+    not(any(
+        all(
+            any(target_arch = "x86", target_arch = "x86_64"),
+            target_feature = "sse2"
+        ),
+        all(target_arch = "aarch64", target_feature = "neon")
+    ))
+))]
+mod scalar;
+#[cfg(not(any(
+    all(
+        any(target_arch = "x86", target_arch = "x86_64"),
+        target_feature = "sse2"
+    ),
+    all(target_arch = "aarch64", target_feature = "neon")
+)))]
+pub(crate) use scalar::F32x4;
+
+/// Maximum absolute deviation of the SIMD path from the scalar path for
+/// finite inputs.
+pub const SIMD_TOLERANCE: f32 = 0.;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simd_matches_scalar() {
+        let a = [0.1, -2.5, 3.75, 1e-3];
+        let b = [0.7, 0.3, -1.25, 8.];
+        let c = [0.35; 4];
+
+        let (va, vb) = (F32x4::from_array(a), F32x4::from_array(b));
+        let (sa, sb) = (scalar::F32x4::from_array(a), scalar::F32x4::from_array(b));
+
+        let results = [
+            (va + vb).to_array(),
+            (va - vb).to_array(),
+            (va * vb + F32x4::from_array(c)).to_array(),
+            va.max(vb * F32x4::from_array(c)).to_array(),
+            va.swap_pairs().to_array(),
+        ];
+        let expected = [
+            (sa + sb).to_array(),
+            (sa - sb).to_array(),
+            (sa * sb + scalar::F32x4::from_array(c)).to_array(),
+            sa.max(sb * scalar::F32x4::from_array(c)).to_array(),
+            sa.swap_pairs().to_array(),
+        ];
+
+        for (result, expected) in results.iter().zip(expected.iter()) {
+            for (value, expected) in result.iter().zip(expected.iter()) {
+                assert!((value - expected).abs() <= SIMD_TOLERANCE);
+            }
+        }
+        assert_eq!(results[4], [-2.5, 0.1, 1e-3, 3.75]);
+    }
+}
@@ -0,0 +1,62 @@
+// Copyright(c) 2022 Hansen Audio.
+
+use core::arch::aarch64::*;
+use core::ops::{Add, Mul, Sub};
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct F32x4(float32x4_t);
+
+// SAFETY: All intrinsics below only require NEON, which this module is
+// compiled for.
+impl F32x4 {
+    #[inline]
+    pub fn from_array(values: [f32; 4]) -> Self {
+        unsafe { Self(vld1q_f32(values.as_ptr())) }
+    }
+
+    #[inline]
+    pub fn to_array(self) -> [f32; 4] {
+        let mut values = [0.; 4];
+        unsafe { vst1q_f32(values.as_mut_ptr(), self.0) };
+        values
+    }
+
+    /// Uses `maxNum` semantics like `f32::max`.
+    #[inline]
+    pub fn max(self, other: Self) -> Self {
+        unsafe { Self(vmaxnmq_f32(self.0, other.0)) }
+    }
+
+    /// Swaps lanes 0 and 1 as well as lanes 2 and 3.
+    #[inline]
+    pub fn swap_pairs(self) -> Self {
+        unsafe { Self(vrev64q_f32(self.0)) }
+    }
+}
+
+impl Add for F32x4 {
+    type Output = Self;
+
+    #[inline]
+    fn add(self, other: Self) -> Self {
+        unsafe { Self(vaddq_f32(self.0, other.0)) }
+    }
+}
+
+impl Sub for F32x4 {
+    type Output = Self;
+
+    #[inline]
+    fn sub(self, other: Self) -> Self {
+        unsafe { Self(vsubq_f32(self.0, other.0)) }
+    }
+}
+
+impl Mul for F32x4 {
+    type Output = Self;
+
+    #[inline]
+    fn mul(self, other: Self) -> Self {
+        unsafe { Self(vmulq_f32(self.0, other.0)) }
+    }
+}
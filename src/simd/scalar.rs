@@ -0,0 +1,57 @@
+// Copyright(c) 2022 Hansen Audio.
+
+use core::ops::{Add, Mul, Sub};
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct F32x4([f32; 4]);
+
+impl F32x4 {
+    #[inline]
+    pub fn from_array(values: [f32; 4]) -> Self {
+        Self(values)
+    }
+
+    #[inline]
+    pub fn to_array(self) -> [f32; 4] {
+        self.0
+    }
+
+    #[inline]
+    pub fn max(self, other: Self) -> Self {
+        Self(core::array::from_fn(|i| self.0[i].max(other.0[i])))
+    }
+
+    /// Swaps lanes 0 and 1 as well as lanes 2 and 3.
+    #[inline]
+    pub fn swap_pairs(self) -> Self {
+        let [a, b, c, d] = self.0;
+        Self([b, a, d, c])
+    }
+}
+
+impl Add for F32x4 {
+    type Output = Self;
+
+    #[inline]
+    fn add(self, other: Self) -> Self {
+        Self(core::array::from_fn(|i| self.0[i] + other.0[i]))
+    }
+}
+
+impl Sub for F32x4 {
+    type Output = Self;
+
+    #[inline]
+    fn sub(self, other: Self) -> Self {
+        Self(core::array::from_fn(|i| self.0[i] - other.0[i]))
+    }
+}
+
+impl Mul for F32x4 {
+    type Output = Self;
+
+    #[inline]
+    fn mul(self, other: Self) -> Self {
+        Self(core::array::from_fn(|i| self.0[i] * other.0[i]))
+    }
+}
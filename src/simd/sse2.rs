@@ -0,0 +1,65 @@
+// Copyright(c) 2022 Hansen Audio.
+
+#[cfg(target_arch = "x86")]
+use core::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::*;
+use core::ops::{Add, Mul, Sub};
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct F32x4(__m128);
+
+// SAFETY: All intrinsics below only require SSE2, which this module is
+// compiled for.
+impl F32x4 {
+    #[inline]
+    pub fn from_array(values: [f32; 4]) -> Self {
+        unsafe { Self(_mm_loadu_ps(values.as_ptr())) }
+    }
+
+    #[inline]
+    pub fn to_array(self) -> [f32; 4] {
+        let mut values = [0.; 4];
+        unsafe { _mm_storeu_ps(values.as_mut_ptr(), self.0) };
+        values
+    }
+
+    #[inline]
+    pub fn max(self, other: Self) -> Self {
+        unsafe { Self(_mm_max_ps(self.0, other.0)) }
+    }
+
+    /// Swaps lanes 0 and 1 as well as lanes 2 and 3.
+    #[inline]
+    pub fn swap_pairs(self) -> Self {
+        const SWAP_PAIRS: i32 = 0b10_11_00_01;
+        unsafe { Self(_mm_shuffle_ps::<SWAP_PAIRS>(self.0, self.0)) }
+    }
+}
+
+impl Add for F32x4 {
+    type Output = Self;
+
+    #[inline]
+    fn add(self, other: Self) -> Self {
+        unsafe { Self(_mm_add_ps(self.0, other.0)) }
+    }
+}
+
+impl Sub for F32x4 {
+    type Output = Self;
+
+    #[inline]
+    fn sub(self, other: Self) -> Self {
+        unsafe { Self(_mm_sub_ps(self.0, other.0)) }
+    }
+}
+
+impl Mul for F32x4 {
+    type Output = Self;
+
+    #[inline]
+    fn mul(self, other: Self) -> Self {
+        unsafe { Self(_mm_mul_ps(self.0, other.0)) }
+    }
+}
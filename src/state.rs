@@ -0,0 +1,534 @@
+// Copyright(c) 2022 Hansen Audio.
+
+//! Versioned binary and text state of the effects.
+//!
+//! A state is an ordered list of named fields. The binary format starts with
+//! the magic `HAFX`, a four byte effect tag, the version and the number of
+//! fields. The text format starts with a `hao-fx-state <tag> <version>` line
+//! followed by one `<key> <type> <value>...` line per field. Floats are written
+//! in their shortest round trip representation, so both formats restore an
+//! effect bit-exactly.
+
+use crate::Sample;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+pub const STATE_VERSION: u32 = 1;
+
+const MAGIC: &[u8; 4] = b"HAFX";
+const TEXT_HEADER: &str = "hao-fx-state";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateError {
+    InvalidHeader,
+    UnsupportedVersion(u32),
+    UnexpectedEnd,
+    UnexpectedField,
+    InvalidValue,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::InvalidHeader => write!(f, "invalid state header"),
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "unsupported state version {}", version)
+            }
+            StateError::UnexpectedEnd => write!(f, "unexpected end of state"),
+            StateError::UnexpectedField => write!(f, "unexpected state field"),
+            StateError::InvalidValue => write!(f, "invalid state value"),
+        }
+    }
+}
+
+impl core::error::Error for StateError {}
+
+/// Enums which are stored in a state by their index.
+pub(crate) trait StateEnum: Copy + PartialEq + 'static {
+    const VARIANTS: &'static [Self];
+}
+
+macro_rules! impl_state_enum {
+    ($name:ty, [$($variant:expr),+ $(,)?]) => {
+        impl crate::state::StateEnum for $name {
+            const VARIANTS: &'static [Self] = &[$($variant),+];
+        }
+    };
+}
+pub(crate) use impl_state_enum;
+
+#[derive(Debug, Clone, PartialEq)]
+enum StateValue {
+    Bool(bool),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+    F32s(Vec<f32>),
+    F64s(Vec<f64>),
+}
+
+impl StateValue {
+    const TYPE_BOOL: u8 = 0;
+    const TYPE_U64: u8 = 1;
+    const TYPE_F32: u8 = 2;
+    const TYPE_F64: u8 = 3;
+    const TYPE_F32S: u8 = 4;
+    const TYPE_F64S: u8 = 5;
+
+    fn type_id(&self) -> u8 {
+        match self {
+            StateValue::Bool(_) => Self::TYPE_BOOL,
+            StateValue::U64(_) => Self::TYPE_U64,
+            StateValue::F32(_) => Self::TYPE_F32,
+            StateValue::F64(_) => Self::TYPE_F64,
+            StateValue::F32s(_) => Self::TYPE_F32S,
+            StateValue::F64s(_) => Self::TYPE_F64S,
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            StateValue::Bool(_) => "bool",
+            StateValue::U64(_) => "u64",
+            StateValue::F32(_) => "f32",
+            StateValue::F64(_) => "f64",
+            StateValue::F32s(_) => "f32s",
+            StateValue::F64s(_) => "f64s",
+        }
+    }
+}
+
+pub(crate) struct StateWriter {
+    fields: Vec<(&'static str, StateValue)>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self { fields: Vec::new() }
+    }
+
+    pub fn bool(&mut self, key: &'static str, value: bool) {
+        self.fields.push((key, StateValue::Bool(value)));
+    }
+
+    pub fn u64(&mut self, key: &'static str, value: u64) {
+        self.fields.push((key, StateValue::U64(value)));
+    }
+
+    pub fn usize(&mut self, key: &'static str, value: usize) {
+        self.u64(key, value as u64);
+    }
+
+    pub fn f32(&mut self, key: &'static str, value: f32) {
+        self.fields.push((key, StateValue::F32(value)));
+    }
+
+    pub fn f64(&mut self, key: &'static str, value: f64) {
+        self.fields.push((key, StateValue::F64(value)));
+    }
+
+    pub fn f32s(&mut self, key: &'static str, values: &[f32]) {
+        self.fields.push((key, StateValue::F32s(values.to_vec())));
+    }
+
+    /// Writes a sample value in its own precision.
+    pub fn sample<T: Sample>(&mut self, key: &'static str, value: T) {
+        match T::BITS {
+            32 => self.f32(key, value.to_f32()),
+            _ => self.f64(key, value.to_f64()),
+        }
+    }
+
+    pub fn samples<T: Sample>(&mut self, key: &'static str, values: &[T]) {
+        match T::BITS {
+            32 => self.fields.push((
+                key,
+                StateValue::F32s(values.iter().map(|el| el.to_f32()).collect()),
+            )),
+            _ => self.fields.push((
+                key,
+                StateValue::F64s(values.iter().map(|el| el.to_f64()).collect()),
+            )),
+        }
+    }
+
+    pub fn enumeration<T: StateEnum>(&mut self, key: &'static str, value: T) {
+        let index = T::VARIANTS.iter().position(|el| *el == value).unwrap_or(0);
+        self.usize(key, index);
+    }
+
+    pub fn to_binary(&self, tag: &[u8; 4]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(tag);
+        data.extend_from_slice(&STATE_VERSION.to_le_bytes());
+        data.extend_from_slice(&(self.fields.len() as u32).to_le_bytes());
+
+        for (key, value) in &self.fields {
+            data.push(key.len() as u8);
+            data.extend_from_slice(key.as_bytes());
+            data.push(value.type_id());
+            match value {
+                StateValue::Bool(v) => data.push(*v as u8),
+                StateValue::U64(v) => data.extend_from_slice(&v.to_le_bytes()),
+                StateValue::F32(v) => data.extend_from_slice(&v.to_bits().to_le_bytes()),
+                StateValue::F64(v) => data.extend_from_slice(&v.to_bits().to_le_bytes()),
+                StateValue::F32s(v) => {
+                    data.extend_from_slice(&(v.len() as u64).to_le_bytes());
+                    for el in v {
+                        data.extend_from_slice(&el.to_bits().to_le_bytes());
+                    }
+                }
+                StateValue::F64s(v) => {
+                    data.extend_from_slice(&(v.len() as u64).to_le_bytes());
+                    for el in v {
+                        data.extend_from_slice(&el.to_bits().to_le_bytes());
+                    }
+                }
+            }
+        }
+
+        data
+    }
+
+    pub fn to_text(&self, tag: &[u8; 4]) -> String {
+        use core::fmt::Write;
+
+        let mut text = String::new();
+        let tag = String::from_utf8_lossy(tag);
+        let _ = writeln!(text, "{} {} {}", TEXT_HEADER, tag, STATE_VERSION);
+
+        for (key, value) in &self.fields {
+            let _ = write!(text, "{} {}", key, value.type_name());
+            let _ = match value {
+                StateValue::Bool(v) => write!(text, " {}", v),
+                StateValue::U64(v) => write!(text, " {}", v),
+                StateValue::F32(v) => write!(text, " {}", v),
+                StateValue::F64(v) => write!(text, " {}", v),
+                StateValue::F32s(v) => {
+                    let _ = write!(text, " {}", v.len());
+                    v.iter().try_for_each(|el| write!(text, " {}", el))
+                }
+                StateValue::F64s(v) => {
+                    let _ = write!(text, " {}", v.len());
+                    v.iter().try_for_each(|el| write!(text, " {}", el))
+                }
+            };
+            text.push('\n');
+        }
+
+        text
+    }
+}
+
+pub(crate) struct StateReader {
+    fields: Vec<(String, StateValue)>,
+    pos: usize,
+}
+
+impl StateReader {
+    pub fn from_binary(data: &[u8], tag: &[u8; 4]) -> Result<Self, StateError> {
+        let mut bytes = ByteReader { data, pos: 0 };
+
+        if bytes.take(MAGIC.len())? != MAGIC || bytes.take(tag.len())? != tag {
+            return Err(StateError::InvalidHeader);
+        }
+
+        Self::check_version(bytes.u32()?)?;
+
+        let num_fields = bytes.u32()? as usize;
+        let mut fields = Vec::new();
+        for _ in 0..num_fields {
+            let key_len = bytes.u8()? as usize;
+            let key = core::str::from_utf8(bytes.take(key_len)?)
+                .map_err(|_| StateError::InvalidValue)?
+                .to_string();
+
+            let value = match bytes.u8()? {
+                StateValue::TYPE_BOOL => StateValue::Bool(bytes.u8()? != 0),
+                StateValue::TYPE_U64 => StateValue::U64(bytes.u64()?),
+                StateValue::TYPE_F32 => StateValue::F32(f32::from_bits(bytes.u32()?)),
+                StateValue::TYPE_F64 => StateValue::F64(f64::from_bits(bytes.u64()?)),
+                StateValue::TYPE_F32S => {
+                    let len = bytes.u64()? as usize;
+                    let mut values = Vec::new();
+                    for _ in 0..len {
+                        values.push(f32::from_bits(bytes.u32()?));
+                    }
+                    StateValue::F32s(values)
+                }
+                StateValue::TYPE_F64S => {
+                    let len = bytes.u64()? as usize;
+                    let mut values = Vec::new();
+                    for _ in 0..len {
+                        values.push(f64::from_bits(bytes.u64()?));
+                    }
+                    StateValue::F64s(values)
+                }
+                _ => return Err(StateError::InvalidValue),
+            };
+
+            fields.push((key, value));
+        }
+
+        Ok(Self { fields, pos: 0 })
+    }
+
+    pub fn from_text(text: &str, tag: &[u8; 4]) -> Result<Self, StateError> {
+        let mut lines = text.lines();
+
+        let mut header = lines.next().ok_or(StateError::InvalidHeader)?.split(' ');
+        if header.next() != Some(TEXT_HEADER) || header.next().map(str::as_bytes) != Some(tag) {
+            return Err(StateError::InvalidHeader);
+        }
+
+        let version = header.next().ok_or(StateError::InvalidHeader)?;
+        Self::check_version(version.parse().map_err(|_| StateError::InvalidHeader)?)?;
+
+        let mut fields = Vec::new();
+        for line in lines.filter(|el| !el.is_empty()) {
+            let mut tokens = line.split(' ');
+            let key = tokens.next().ok_or(StateError::UnexpectedEnd)?.to_string();
+            let type_name = tokens.next().ok_or(StateError::UnexpectedEnd)?;
+            let mut next = || tokens.next().ok_or(StateError::UnexpectedEnd);
+
+            let value = match type_name {
+                "bool" => StateValue::Bool(parse(next()?)?),
+                "u64" => StateValue::U64(parse(next()?)?),
+                "f32" => StateValue::F32(parse(next()?)?),
+                "f64" => StateValue::F64(parse(next()?)?),
+                "f32s" => {
+                    let len: usize = parse(next()?)?;
+                    let mut values = Vec::new();
+                    for _ in 0..len {
+                        values.push(parse(next()?)?);
+                    }
+                    StateValue::F32s(values)
+                }
+                "f64s" => {
+                    let len: usize = parse(next()?)?;
+                    let mut values = Vec::new();
+                    for _ in 0..len {
+                        values.push(parse(next()?)?);
+                    }
+                    StateValue::F64s(values)
+                }
+                _ => return Err(StateError::InvalidValue),
+            };
+
+            fields.push((key, value));
+        }
+
+        Ok(Self { fields, pos: 0 })
+    }
+
+    pub fn bool(&mut self, key: &str) -> Result<bool, StateError> {
+        match self.next(key)? {
+            StateValue::Bool(v) => Ok(v),
+            _ => Err(StateError::InvalidValue),
+        }
+    }
+
+    pub fn u64(&mut self, key: &str) -> Result<u64, StateError> {
+        match self.next(key)? {
+            StateValue::U64(v) => Ok(v),
+            _ => Err(StateError::InvalidValue),
+        }
+    }
+
+    pub fn usize(&mut self, key: &str) -> Result<usize, StateError> {
+        usize::try_from(self.u64(key)?).map_err(|_| StateError::InvalidValue)
+    }
+
+    pub fn f32(&mut self, key: &str) -> Result<f32, StateError> {
+        match self.next(key)? {
+            StateValue::F32(v) => Ok(v),
+            _ => Err(StateError::InvalidValue),
+        }
+    }
+
+    pub fn f64(&mut self, key: &str) -> Result<f64, StateError> {
+        match self.next(key)? {
+            StateValue::F64(v) => Ok(v),
+            _ => Err(StateError::InvalidValue),
+        }
+    }
+
+    pub fn f32s(&mut self, key: &str) -> Result<Vec<f32>, StateError> {
+        match self.next(key)? {
+            StateValue::F32s(v) => Ok(v),
+            _ => Err(StateError::InvalidValue),
+        }
+    }
+
+    /// Reads a list of floats which must match the length of `values`.
+    pub fn f32s_into(&mut self, key: &str, values: &mut [f32]) -> Result<(), StateError> {
+        let read = self.f32s(key)?;
+        if read.len() != values.len() {
+            return Err(StateError::InvalidValue);
+        }
+
+        values.copy_from_slice(&read);
+        Ok(())
+    }
+
+    /// Reads a sample value, which must have been saved in the same
+    /// precision.
+    pub fn sample<T: Sample>(&mut self, key: &str) -> Result<T, StateError> {
+        match T::BITS {
+            32 => Ok(T::from_f32(self.f32(key)?)),
+            _ => Ok(T::from_f64(self.f64(key)?)),
+        }
+    }
+
+    pub fn samples<T: Sample>(&mut self, key: &str) -> Result<Vec<T>, StateError> {
+        match (T::BITS, self.next(key)?) {
+            (32, StateValue::F32s(v)) => Ok(v.into_iter().map(T::from_f32).collect()),
+            (64, StateValue::F64s(v)) => Ok(v.into_iter().map(T::from_f64).collect()),
+            _ => Err(StateError::InvalidValue),
+        }
+    }
+
+    /// Reads a list of samples which must match the length of `values`.
+    pub fn samples_into<T: Sample>(
+        &mut self,
+        key: &str,
+        values: &mut [T],
+    ) -> Result<(), StateError> {
+        let read = self.samples(key)?;
+        if read.len() != values.len() {
+            return Err(StateError::InvalidValue);
+        }
+
+        values.copy_from_slice(&read);
+        Ok(())
+    }
+
+    pub fn enumeration<T: StateEnum>(&mut self, key: &str) -> Result<T, StateError> {
+        let index = self.usize(key)?;
+        T::VARIANTS
+            .get(index)
+            .copied()
+            .ok_or(StateError::InvalidValue)
+    }
+
+    fn next(&mut self, key: &str) -> Result<StateValue, StateError> {
+        let (field_key, value) = self.fields.get(self.pos).ok_or(StateError::UnexpectedEnd)?;
+        if field_key != key {
+            return Err(StateError::UnexpectedField);
+        }
+
+        self.pos += 1;
+        Ok(value.clone())
+    }
+
+    fn check_version(version: u32) -> Result<(), StateError> {
+        match version {
+            STATE_VERSION => Ok(()),
+            _ => Err(StateError::UnsupportedVersion(version)),
+        }
+    }
+}
+
+fn parse<T: core::str::FromStr>(token: &str) -> Result<T, StateError> {
+    token.parse().map_err(|_| StateError::InvalidValue)
+}
+
+struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        let end = self.pos.checked_add(len).ok_or(StateError::UnexpectedEnd)?;
+        let bytes = self
+            .data
+            .get(self.pos..end)
+            .ok_or(StateError::UnexpectedEnd)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, StateError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64, StateError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TAG: &[u8; 4] = b"TEST";
+
+    fn write_fields() -> StateWriter {
+        let mut writer = StateWriter::new();
+        writer.bool("active", true);
+        writer.usize("pos", 17);
+        writer.f32("mix", 0.1);
+        writer.f32("denormal", 1.4e-45);
+        writer.f32("limit", f32::INFINITY);
+        writer.f64("time", 12.345678901234);
+        writer.f32s("steps", &[0., -0., 1. / 3.]);
+        writer.samples("buffer", &[0.1_f64, -1. / 3.]);
+        writer
+    }
+
+    fn read_fields(mut reader: StateReader) -> Result<(), StateError> {
+        assert!(reader.bool("active")?);
+        assert_eq!(reader.usize("pos")?, 17);
+        assert_eq!(reader.f32("mix")?, 0.1);
+        assert_eq!(reader.f32("denormal")?, 1.4e-45);
+        assert_eq!(reader.f32("limit")?, f32::INFINITY);
+        assert_eq!(reader.f64("time")?, 12.345678901234);
+
+        let steps = reader.f32s("steps")?;
+        assert_eq!(steps[1].to_bits(), (-0_f32).to_bits());
+        assert_eq!(steps[2], 1. / 3.);
+        assert_eq!(reader.samples::<f64>("buffer")?, vec![0.1, -1. / 3.]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_state_binary_round_trip() {
+        let data = write_fields().to_binary(TAG);
+        read_fields(StateReader::from_binary(&data, TAG).unwrap()).unwrap();
+
+        assert_eq!(
+            StateReader::from_binary(&data, b"NONE").err(),
+            Some(StateError::InvalidHeader)
+        );
+        assert_eq!(
+            StateReader::from_binary(&data[..data.len() - 1], TAG).err(),
+            Some(StateError::UnexpectedEnd)
+        );
+    }
+
+    #[test]
+    fn test_state_text_round_trip() {
+        let text = write_fields().to_text(TAG);
+        read_fields(StateReader::from_text(&text, TAG).unwrap()).unwrap();
+
+        let mut reader = StateReader::from_text(&text, TAG).unwrap();
+        assert_eq!(reader.f32("mix").err(), Some(StateError::UnexpectedField));
+
+        let text = text.replacen(" 1\n", " 2\n", 1);
+        assert_eq!(
+            StateReader::from_text(&text, TAG).err(),
+            Some(StateError::UnsupportedVersion(2))
+        );
+    }
+}
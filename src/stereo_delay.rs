@@ -1,108 +1,530 @@
 // Copyright(c) 2021 Hansen Audio.
 
-use crate::{AudioFrame, NUM_STEREO_CHANNELS};
+use crate::one_pole::{OnePole, OnePoleType};
+use crate::state::{impl_state_enum, StateError, StateReader, StateWriter};
+use crate::{
+    AudioFrame, Sample, DEFAULT_SAMPLE_RATE, DEFAULT_TEMPO_BPM, NUM_CHANNELS, NUM_STEREO_CHANNELS,
+};
+use alloc::string::String;
+use alloc::vec::Vec;
 
-mod delay_line_heads;
-use delay_line_heads::DelayLineHeads;
-use dsp_tool_box_rs::filtering::one_pole::OnePole;
-use dsp_tool_box_rs::filtering::one_pole::OnePoleType;
+pub(crate) mod buffers;
+pub(crate) mod delay_line_heads;
+pub(crate) mod delay_time;
+pub(crate) mod interpolation;
+mod mix;
+mod saturation;
+mod wow_flutter;
+use buffers::Buffers;
+use delay_line_heads::{DelayLineHeads, NUM_FORWARD_HEADS};
+use interpolation::Interpolator;
+use mix::Mix;
+use saturation::Saturator;
+use wow_flutter::WowFlutter;
 
+pub use delay_line_heads::TimeChangeMode;
+pub use interpolation::Interpolation;
+pub use mix::MixLaw;
+pub use saturation::Saturation;
+
+/// Defines how the delay times are specified.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DelayTiming {
+    /// Delay time as a fraction of the buffer size.
+    Normalized,
+    /// Delay time as a note length, following the tempo.
+    NoteLength,
+    /// Delay time in milliseconds, following the sample rate.
+    Milliseconds,
+    /// Delay time in samples.
+    Samples,
+}
+
+impl_state_enum!(
+    DelayTiming,
+    [
+        DelayTiming::Normalized,
+        DelayTiming::NoteLength,
+        DelayTiming::Milliseconds,
+        DelayTiming::Samples,
+    ]
+);
+
+/// Defines how the repeats are fed back into the delay lines.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedbackMode {
+    /// Each channel feeds back into itself and, by the cross terms of the
+    /// feedback matrix, into the other channel.
+    Matrix,
+    /// Each channel feeds back only into the other one, repeats bounce
+    /// between the sides.
+    PingPong,
+}
+
+impl_state_enum!(FeedbackMode, [FeedbackMode::Matrix, FeedbackMode::PingPong]);
+
+/// Defines how the stereo input enters the delay lines in ping-pong mode.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputMode {
+    /// Both inputs are summed to mono and enter the left delay line only, so
+    /// the repeats start on the left and bounce to the right.
+    MonoSum,
+    /// Each input enters its own delay line.
+    Stereo,
+}
+
+impl_state_enum!(InputMode, [InputMode::MonoSum, InputMode::Stereo]);
+
+/// Stereo delay processing samples of type `T`, `f32` or `f64`. Buffers,
+/// read heads and filters all run in `T`, parameters are given as `f32`.
 #[derive(Clone)]
-pub struct StereoDelay {
-    bufs: Vec<Vec<f32>>,
-    feedbacks: [f32; NUM_STEREO_CHANNELS],
-    heads: [DelayLineHeads; NUM_STEREO_CHANNELS],
-    hp: OnePole,
-    lp: OnePole,
+pub struct StereoDelay<T: Sample = f32> {
+    bufs: Buffers<T>,
+    feedbacks: [T; NUM_STEREO_CHANNELS],
+    feedback_amounts: [f32; NUM_STEREO_CHANNELS],
+    feedback_balance: f32,
+    is_feedback_linked: bool,
+    /// Feedback from the other channel, indexed by the receiving channel.
+    cross_feedbacks: [T; NUM_STEREO_CHANNELS],
+    cross_feedback_amounts: [f32; NUM_STEREO_CHANNELS],
+    feedback_mode: FeedbackMode,
+    input_mode: InputMode,
+    heads: [DelayLineHeads<T>; NUM_STEREO_CHANNELS],
+    interpolator: Interpolator<T, { NUM_STEREO_CHANNELS * NUM_FORWARD_HEADS }>,
+    wow_flutter: WowFlutter,
+    saturator: Saturator<T>,
+    hp: OnePole<T>,
+    lp: OnePole<T>,
+    mix: Mix<T>,
+    is_frozen: bool,
+    freeze_amount: T,
+    freeze_step: T,
+    delay_timing: DelayTiming,
+    normalized_delays: [f32; NUM_STEREO_CHANNELS],
+    note_lens: [f32; NUM_STEREO_CHANNELS],
+    delay_times_ms: [f32; NUM_STEREO_CHANNELS],
+    delay_samples: [f32; NUM_STEREO_CHANNELS],
+    max_delay_time_ms: Option<f32>,
+    tempo_bpm: f32,
+    sample_rate: f32,
 }
 
 impl StereoDelay {
+    pub fn new() -> Self {
+        Self::with_sample_type()
+    }
+}
+
+impl Default for StereoDelay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Sample> StereoDelay<T> {
     const LC: usize = 0;
     const RC: usize = 1;
+    const STATE_TAG: &'static [u8; 4] = b"SDLY";
 
-    pub fn new() -> Self {
+    /// Creates a delay for the sample type `T`, for instance
+    /// `StereoDelay::<f64>::with_sample_type()`.
+    pub fn with_sample_type() -> Self {
         const DEFAULT_BUF_SIZE: usize = 8000;
 
+        Self::with_buffers(Buffers::owned(NUM_STEREO_CHANNELS, DEFAULT_BUF_SIZE))
+    }
+
+    /// Creates a delay working on caller-supplied memory instead of
+    /// allocating its buffers, e.g. a `static` array on embedded targets.
+    /// Each channel gets one half of `memory`, `set_buffer_size` cannot
+    /// grow beyond that. A clone allocates its own copy of the memory.
+    pub fn with_buffer(memory: &'static mut [T]) -> Self {
+        const MIN_BUF_SIZE: usize = 2;
+
+        assert!(
+            memory.len() >= MIN_BUF_SIZE * NUM_STEREO_CHANNELS,
+            "delay memory too small"
+        );
+        Self::with_buffers(Buffers::borrowed(NUM_STEREO_CHANNELS, memory))
+    }
+
+    fn with_buffers(bufs: Buffers<T>) -> Self {
+        const DEFAULT_NOTE_LEN: f32 = 1. / 8.;
+        const DEFAULT_FEEDBACK: f32 = 0.75;
+
+        let buffer_size = bufs.len();
         let mut delay_line = Self {
-            bufs: vec![vec![0_f32; DEFAULT_BUF_SIZE]; NUM_STEREO_CHANNELS],
-            feedbacks: [0.75; NUM_STEREO_CHANNELS],
+            bufs,
+            feedbacks: [T::from_f32(DEFAULT_FEEDBACK); NUM_STEREO_CHANNELS],
+            feedback_amounts: [DEFAULT_FEEDBACK; NUM_STEREO_CHANNELS],
+            feedback_balance: 0.,
+            is_feedback_linked: false,
+            cross_feedbacks: [T::ZERO; NUM_STEREO_CHANNELS],
+            cross_feedback_amounts: [0.; NUM_STEREO_CHANNELS],
+            feedback_mode: FeedbackMode::Matrix,
+            input_mode: InputMode::MonoSum,
             heads: [DelayLineHeads::new(); NUM_STEREO_CHANNELS],
+            interpolator: Interpolator::new(),
+            wow_flutter: WowFlutter::new(),
+            saturator: Saturator::new(),
             hp: OnePole::new(),
             lp: OnePole::new(),
+            mix: Mix::new(),
+            is_frozen: false,
+            freeze_amount: T::ZERO,
+            freeze_step: T::ONE,
+            delay_timing: DelayTiming::Normalized,
+            normalized_delays: [0.; NUM_STEREO_CHANNELS],
+            note_lens: [DEFAULT_NOTE_LEN; NUM_STEREO_CHANNELS],
+            delay_times_ms: [0.; NUM_STEREO_CHANNELS],
+            delay_samples: [0.; NUM_STEREO_CHANNELS],
+            max_delay_time_ms: None,
+            tempo_bpm: DEFAULT_TEMPO_BPM,
+            sample_rate: DEFAULT_SAMPLE_RATE,
         };
 
         delay_line.hp.set_filter_type(OnePoleType::HP);
         delay_line.lp.set_filter_type(OnePoleType::LP);
         for el in delay_line.heads.iter_mut() {
-            el.set_buffer_size(DEFAULT_BUF_SIZE);
+            el.set_buffer_size(buffer_size);
         }
+        delay_line.update_fade_lens();
 
         delay_line
     }
 
-    pub fn process_mono(&mut self, input: f32) -> f32 {
-        let mut output = self.read(Self::LC, self.heads[Self::LC].read_pos());
-        output = self.filter(output);
+    pub fn process_mono(&mut self, input: T) -> T {
+        self.modulate_heads();
+        self.advance_freeze();
+        let unfiltered =
+            self.heads[Self::LC].read(&mut self.interpolator, Self::LC, &self.bufs[Self::LC]);
+        let mut output = self.filter(unfiltered);
 
-        let value = input + output * self.feedbacks[Self::LC];
+        let mut value = self
+            .saturator
+            .process(input + output * self.feedbacks[Self::LC]);
+        if self.freeze_amount > T::ZERO {
+            value = self.freeze_value(value, unfiltered);
+            output = self.freeze_value(output, unfiltered);
+        }
         self.write(Self::LC, self.heads[Self::LC].write_pos(), value);
 
         self.heads[Self::LC].advance();
 
-        output
+        self.mix.process_mono(input, output)
     }
 
-    pub fn process_stereo(&mut self, outputs: &mut AudioFrame) {
-        let inputs = outputs.clone();
-
-        outputs[Self::LC] = self.read(Self::LC, self.heads[Self::LC].read_pos());
-        outputs[Self::RC] = self.read(Self::RC, self.heads[Self::RC].read_pos());
+    pub fn process_stereo(&mut self, outputs: &mut AudioFrame<T>) {
+        let dry = *outputs;
+        let inputs = self.route_inputs(outputs);
 
+        self.modulate_heads();
+        self.advance_freeze();
+        self.read_stereo(outputs);
+        let unfiltered = *outputs;
         self.filter_multi(outputs);
-
-        let mut value_left = inputs[Self::LC];
-        value_left += outputs[Self::LC] * self.feedbacks[Self::LC];
-        self.write(Self::LC, self.heads[Self::LC].write_pos(), value_left);
-
-        let mut value_right = inputs[Self::RC];
-        value_right += outputs[Self::RC] * self.feedbacks[Self::RC];
-        self.write(Self::RC, self.heads[Self::RC].write_pos(), value_right);
+        self.write_stereo(&inputs, outputs, &unfiltered);
+        self.freeze_frame(outputs, &unfiltered);
 
         for el in self.heads.iter_mut() {
             el.advance();
         }
+
+        self.mix.process(&dry, outputs);
     }
 
     pub fn set_normalized_delay_left(&mut self, speed: f32) {
-        self.heads[Self::LC].set_heads_diff(speed);
+        self.normalized_delays[Self::LC] = speed;
+        self.update_delay_time(Self::LC);
     }
 
     pub fn set_normalized_delay_right(&mut self, speed: f32) {
-        self.heads[Self::RC].set_heads_diff(speed);
+        self.normalized_delays[Self::RC] = speed;
+        self.update_delay_time(Self::RC);
+    }
+
+    /// Selects whether the delay times are fractions of the buffer size,
+    /// note lengths, milliseconds or samples. On a change the read heads
+    /// glide to the new times.
+    pub fn set_delay_timing(&mut self, delay_timing: DelayTiming) {
+        if self.delay_timing == delay_timing {
+            return;
+        }
+
+        self.delay_timing = delay_timing;
+        self.update_delay_times();
+    }
+
+    /// Sets the left delay time as a note length, e.g. `1. / 4.` for a
+    /// quarter, `3. / 16.` for a dotted 1/8 or `1. / 12.` for a 1/8 triplet.
+    /// Times beyond the buffer size are limited, see `is_delay_time_limited`.
+    pub fn set_note_len_left(&mut self, note_len: f32) {
+        self.note_lens[Self::LC] = note_len;
+        self.update_delay_time(Self::LC);
+    }
+
+    /// Sets the right delay time as a note length, see `set_note_len_left`.
+    pub fn set_note_len_right(&mut self, note_len: f32) {
+        self.note_lens[Self::RC] = note_len;
+        self.update_delay_time(Self::RC);
+    }
+
+    pub fn set_delay_time_ms_left(&mut self, delay_time_ms: f32) {
+        self.delay_times_ms[Self::LC] = delay_time_ms;
+        self.update_delay_time(Self::LC);
+    }
+
+    pub fn set_delay_time_ms_right(&mut self, delay_time_ms: f32) {
+        self.delay_times_ms[Self::RC] = delay_time_ms;
+        self.update_delay_time(Self::RC);
+    }
+
+    pub fn set_delay_samples_left(&mut self, delay_samples: f32) {
+        self.delay_samples[Self::LC] = delay_samples;
+        self.update_delay_time(Self::LC);
+    }
+
+    pub fn set_delay_samples_right(&mut self, delay_samples: f32) {
+        self.delay_samples[Self::RC] = delay_samples;
+        self.update_delay_time(Self::RC);
+    }
+
+    /// Sizes the buffers to hold `max_delay_time_ms` at the current sample
+    /// rate and resizes them whenever the sample rate changes. Both allocate,
+    /// so call them outside the audio thread. Longer delay times are limited
+    /// to the maximum.
+    pub fn set_max_delay_time_ms(&mut self, max_delay_time_ms: f32) {
+        self.max_delay_time_ms = Some(max_delay_time_ms);
+        self.update_buffer_size();
+    }
+
+    /// Sets the tempo for note length delay times, the read heads glide to
+    /// the new times.
+    pub fn set_tempo(&mut self, tempo_bpm: f32) {
+        self.tempo_bpm = tempo_bpm;
+        if self.delay_timing == DelayTiming::NoteLength {
+            self.update_delay_times();
+        }
     }
 
     pub fn set_feedback(&mut self, feedback: f32) {
-        for el in self.feedbacks.iter_mut() {
-            *el = feedback;
+        self.feedback_amounts = [feedback; NUM_STEREO_CHANNELS];
+        self.update_feedbacks();
+    }
+
+    /// Sets the feedback of the left channel, of both when linked.
+    pub fn set_feedback_left(&mut self, feedback: f32) {
+        self.set_channel_feedback(Self::LC, feedback);
+    }
+
+    /// Sets the feedback of the right channel, of both when linked.
+    pub fn set_feedback_right(&mut self, feedback: f32) {
+        self.set_channel_feedback(Self::RC, feedback);
+    }
+
+    /// Shifts the feedback between the channels, -1 keeps only the left
+    /// repeats, 1 only the right ones and 0 leaves both unchanged.
+    pub fn set_feedback_balance(&mut self, balance: f32) {
+        self.feedback_balance = balance.clamp(-1., 1.);
+        self.update_feedbacks();
+    }
+
+    /// Links the feedback of both channels. When enabled the right channel
+    /// takes over the feedback of the left one, including its cross term.
+    pub fn set_feedback_link(&mut self, is_linked: bool) {
+        self.is_feedback_linked = is_linked;
+        if is_linked {
+            self.cross_feedback_amounts[Self::RC] = self.cross_feedback_amounts[Self::LC];
+            self.set_channel_feedback(Self::LC, self.feedback_amounts[Self::LC]);
         }
     }
 
-    pub fn clear_buffer(&mut self) {
-        for item in &mut self.bufs {
-            for v in item.iter_mut() {
-                *v = 0_f32;
+    /// Sets all four feedback gains, `l_to_r` is the amount of the left
+    /// repeats fed into the right delay line and so on. Used in the
+    /// `FeedbackMode::Matrix` mode, `set_feedback` only sets `l_to_l` and
+    /// `r_to_r`. Link and balance apply per receiving delay line: when
+    /// linked the right line takes over `l_to_l` and `r_to_l`, and the
+    /// balance scales both gains into a line.
+    pub fn set_feedback_matrix(&mut self, l_to_l: f32, l_to_r: f32, r_to_l: f32, r_to_r: f32) {
+        match self.is_feedback_linked {
+            true => {
+                self.feedback_amounts = [l_to_l; NUM_STEREO_CHANNELS];
+                self.cross_feedback_amounts = [r_to_l; NUM_STEREO_CHANNELS];
+            }
+            false => {
+                self.feedback_amounts = [l_to_l, r_to_r];
+                self.cross_feedback_amounts = [r_to_l, l_to_r];
             }
         }
+        self.update_feedbacks();
     }
 
-    pub fn set_buffer_size(&mut self, size: usize) {
-        for item in &mut self.bufs {
-            (*item).resize(size, 0f32);
+    /// In `FeedbackMode::PingPong` the feedback of each channel goes to the
+    /// other channel and the cross terms of the matrix are not used.
+    pub fn set_feedback_mode(&mut self, feedback_mode: FeedbackMode) {
+        self.feedback_mode = feedback_mode;
+    }
+
+    /// Only used in `FeedbackMode::PingPong`, otherwise each input enters
+    /// its own delay line.
+    pub fn set_input_mode(&mut self, input_mode: InputMode) {
+        self.input_mode = input_mode;
+    }
+
+    /// Crossfades from the dry signal at 0 to the wet signal at 1, the
+    /// default.
+    pub fn set_mix(&mut self, mix: f32) {
+        self.mix.set_mix(mix);
+    }
+
+    pub fn set_mix_law(&mut self, mix_law: MixLaw) {
+        self.mix.set_mix_law(mix_law);
+    }
+
+    /// Sets the linear gain of the dry signal, applied after the mix.
+    pub fn set_dry_level(&mut self, dry_level: f32) {
+        self.mix.set_dry_level(dry_level);
+    }
+
+    /// Sets the linear gain of the wet signal, applied after the mix.
+    pub fn set_wet_level(&mut self, wet_level: f32) {
+        self.mix.set_wet_level(wet_level);
+    }
+
+    pub fn set_output_gain_db(&mut self, output_gain_db: f32) {
+        self.mix.set_output_gain_db(output_gain_db);
+    }
+
+    /// Outputs only the wet signal regardless of the mix, for send/return
+    /// use.
+    pub fn set_wet_only(&mut self, is_wet_only: bool) {
+        self.mix.set_wet_only(is_wet_only);
+    }
+
+    /// Selects how the delay lines are read between two samples, linear by
+    /// default.
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.interpolator.set_interpolation(interpolation);
+    }
+
+    /// Sets the rate of the slow wow modulation, 0.5 Hz by default.
+    pub fn set_wow_rate_hz(&mut self, rate_hz: f32) {
+        self.wow_flutter.set_wow_rate_hz(rate_hz);
+    }
+
+    /// Sets how far the wow modulation moves the read heads into the past,
+    /// up to 20 ms, zero disables it.
+    pub fn set_wow_depth_ms(&mut self, depth_ms: f32) {
+        self.wow_flutter.set_wow_depth_ms(depth_ms);
+    }
+
+    /// Sets the rate of the fast flutter modulation, 8 Hz by default.
+    pub fn set_flutter_rate_hz(&mut self, rate_hz: f32) {
+        self.wow_flutter.set_flutter_rate_hz(rate_hz);
+    }
+
+    /// Sets how far the flutter modulation moves the read heads into the
+    /// past, up to 20 ms, zero disables it.
+    pub fn set_flutter_depth_ms(&mut self, depth_ms: f32) {
+        self.wow_flutter.set_flutter_depth_ms(depth_ms);
+    }
+
+    /// Offsets the right wow and flutter LFOs against the left ones, in
+    /// cycles from 0 to 1.
+    pub fn set_wow_flutter_stereo_phase(&mut self, stereo_phase: f32) {
+        self.wow_flutter.set_stereo_phase(stereo_phase);
+    }
+
+    /// Blends the wow and flutter LFOs from a pure sine at 0 to random drift
+    /// at 1.
+    pub fn set_wow_flutter_noise(&mut self, amount: f32) {
+        self.wow_flutter.set_noise_amount(amount);
+    }
+
+    /// Selects how the read heads move to new delay times, gliding by
+    /// default.
+    pub fn set_time_change_mode(&mut self, time_change_mode: TimeChangeMode) {
+        for el in self.heads.iter_mut() {
+            el.set_time_change_mode(time_change_mode);
         }
+    }
+
+    /// Sets the speed deviation of the read heads while gliding to a new
+    /// delay time. The default 0.3 reads at 0.7 or 1.3 times the normal
+    /// speed, higher rates glide faster with a stronger pitch bend.
+    pub fn set_glide_rate(&mut self, glide_rate: f32) {
+        for el in self.heads.iter_mut() {
+            el.set_glide_rate(glide_rate);
+        }
+    }
+
+    /// Selects the saturation curve in the feedback loop, off by default.
+    /// With saturation the repeats stay bounded even for feedback above one.
+    pub fn set_saturation(&mut self, saturation: Saturation) {
+        self.saturator.set_saturation(saturation);
+    }
+
+    pub fn set_saturation_drive_db(&mut self, drive_db: f32) {
+        self.saturator.set_drive_db(drive_db);
+    }
+
+    /// Compensates the drive after the saturation curve, enabled by default.
+    pub fn set_saturation_compensation(&mut self, is_compensated: bool) {
+        self.saturator.set_compensation(is_compensated);
+    }
+
+    /// Plays each delay segment backwards. The read heads run in reverse
+    /// over windows of the delay time, the window boundaries are
+    /// crossfaded.
+    pub fn set_reverse(&mut self, is_reverse: bool) {
+        for el in self.heads.iter_mut() {
+            el.set_reverse(is_reverse);
+        }
+    }
+
+    /// Freezes the delay lines. The input is no longer written and the
+    /// current content loops with unity feedback, bypassing filters and
+    /// saturation. Engaging and releasing fades over 20 ms. The loop keeps
+    /// the delay time of the moment of freezing rounded to whole samples,
+    /// time changes, wow, flutter and reverse apply again after the release.
+    pub fn set_freeze(&mut self, is_frozen: bool) {
+        self.is_frozen = is_frozen;
+    }
+
+    pub fn clear_buffer(&mut self) {
+        self.bufs.clear();
+    }
+
+    /// Returns true if a delay time is longer than the buffers can hold and
+    /// is limited to the buffer size. The default buffers hold 8000 samples,
+    /// size them with `set_max_delay_time_ms` for longer note lengths or
+    /// times.
+    pub fn is_delay_time_limited(&self) -> bool {
+        let max_delay_samples = self.bufs.len().saturating_sub(1) as f32;
+        [Self::LC, Self::RC].into_iter().any(|ch| {
+            self.delay_time_samples(ch)
+                .is_some_and(|samples| samples > max_delay_samples)
+        })
+    }
+
+    /// Sets the buffer size of both channels, replacing a size derived from
+    /// `set_max_delay_time_ms`. With caller-supplied memory the size is
+    /// limited to what that memory can hold.
+    pub fn set_buffer_size(&mut self, size: usize) {
+        self.max_delay_time_ms = None;
+        self.resize_buffers(size);
+    }
+
+    fn resize_buffers(&mut self, size: usize) {
+        let size = self.bufs.resize(size);
 
         for el in self.heads.iter_mut() {
             el.set_buffer_size(size);
         }
+        self.update_delay_times();
     }
 
     pub fn reset_heads(&mut self) {
@@ -120,31 +542,375 @@ impl StereoDelay {
     }
 
     pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
         self.hp.set_sample_rate(sample_rate);
         self.lp.set_sample_rate(sample_rate);
+        self.mix.set_sample_rate(sample_rate);
+        self.wow_flutter.set_sample_rate(sample_rate);
+        self.update_fade_lens();
+        match self.max_delay_time_ms {
+            Some(_) => self.update_buffer_size(),
+            None => self.update_delay_times(),
+        }
+    }
+
+    /// Saves the complete runtime state including the delay buffers in the
+    /// versioned binary format.
+    pub fn save_state_binary(&self) -> Vec<u8> {
+        self.write_state().to_binary(Self::STATE_TAG)
+    }
+
+    /// Saves the complete runtime state in the versioned text format.
+    pub fn save_state_text(&self) -> String {
+        self.write_state().to_text(Self::STATE_TAG)
+    }
+
+    /// Restores a state saved by `save_state_binary`. On error the delay
+    /// stays unchanged.
+    pub fn restore_state_binary(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut reader = StateReader::from_binary(data, Self::STATE_TAG)?;
+        self.restore_state(&mut reader)
+    }
+
+    /// Restores a state saved by `save_state_text`.
+    pub fn restore_state_text(&mut self, text: &str) -> Result<(), StateError> {
+        let mut reader = StateReader::from_text(text, Self::STATE_TAG)?;
+        self.restore_state(&mut reader)
+    }
+
+    fn write_state(&self) -> StateWriter {
+        let mut writer = StateWriter::new();
+
+        writer.usize("buffer_size", self.bufs.len());
+        for ch in [Self::LC, Self::RC] {
+            writer.samples("buffer", &self.bufs[ch]);
+        }
+        writer.samples("feedbacks", &self.feedbacks);
+        writer.f32s("feedback_amounts", &self.feedback_amounts);
+        writer.f32("feedback_balance", self.feedback_balance);
+        writer.bool("is_feedback_linked", self.is_feedback_linked);
+        writer.samples("cross_feedbacks", &self.cross_feedbacks);
+        writer.f32s("cross_feedback_amounts", &self.cross_feedback_amounts);
+        writer.enumeration("feedback_mode", self.feedback_mode);
+        writer.enumeration("input_mode", self.input_mode);
+        for head in &self.heads {
+            head.write_state(&mut writer);
+        }
+        self.interpolator.write_state(&mut writer);
+        self.wow_flutter.write_state(&mut writer);
+        self.saturator.write_state(&mut writer);
+        self.hp.write_state(&mut writer);
+        self.lp.write_state(&mut writer);
+        self.mix.write_state(&mut writer);
+        writer.bool("is_frozen", self.is_frozen);
+        writer.sample("freeze_amount", self.freeze_amount);
+        writer.sample("freeze_step", self.freeze_step);
+        writer.enumeration("delay_timing", self.delay_timing);
+        writer.f32s("normalized_delays", &self.normalized_delays);
+        writer.f32s("note_lens", &self.note_lens);
+        writer.f32s("delay_times_ms", &self.delay_times_ms);
+        writer.f32s("delay_samples", &self.delay_samples);
+        writer.bool("has_max_delay_time", self.max_delay_time_ms.is_some());
+        writer.f32("max_delay_time_ms", self.max_delay_time_ms.unwrap_or(0.));
+        writer.f32("tempo_bpm", self.tempo_bpm);
+        writer.f32("sample_rate", self.sample_rate);
+
+        writer
+    }
+
+    /// Reads everything first and only applies it when the whole state is
+    /// valid, the delay memory may be borrowed and cannot be cloned.
+    fn restore_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        const MIN_BUF_SIZE: usize = 2;
+
+        let buffer_size = reader.usize("buffer_size")?;
+        if buffer_size < MIN_BUF_SIZE || buffer_size > self.bufs.capacity() {
+            return Err(StateError::InvalidValue);
+        }
+
+        let mut bufs = Vec::with_capacity(NUM_STEREO_CHANNELS);
+        for _ in 0..NUM_STEREO_CHANNELS {
+            let values = reader.samples("buffer")?;
+            if values.len() != buffer_size {
+                return Err(StateError::InvalidValue);
+            }
+            bufs.push(values);
+        }
+        let mut feedbacks = self.feedbacks;
+        reader.samples_into("feedbacks", &mut feedbacks)?;
+        let mut feedback_amounts = self.feedback_amounts;
+        reader.f32s_into("feedback_amounts", &mut feedback_amounts)?;
+        let feedback_balance = reader.f32("feedback_balance")?;
+        let is_feedback_linked = reader.bool("is_feedback_linked")?;
+        let mut cross_feedbacks = self.cross_feedbacks;
+        reader.samples_into("cross_feedbacks", &mut cross_feedbacks)?;
+        let mut cross_feedback_amounts = self.cross_feedback_amounts;
+        reader.f32s_into("cross_feedback_amounts", &mut cross_feedback_amounts)?;
+        let feedback_mode = reader.enumeration("feedback_mode")?;
+        let input_mode = reader.enumeration("input_mode")?;
+        let mut heads = self.heads;
+        for head in heads.iter_mut() {
+            head.set_buffer_size(buffer_size);
+            head.read_state(reader)?;
+        }
+        let mut interpolator = self.interpolator;
+        interpolator.read_state(reader)?;
+        let mut wow_flutter = self.wow_flutter.clone();
+        wow_flutter.read_state(reader)?;
+        let mut saturator = self.saturator;
+        saturator.read_state(reader)?;
+        let mut hp = self.hp.clone();
+        hp.read_state(reader)?;
+        let mut lp = self.lp.clone();
+        lp.read_state(reader)?;
+        let mut mix = self.mix.clone();
+        mix.read_state(reader)?;
+        let is_frozen = reader.bool("is_frozen")?;
+        let freeze_amount = reader.sample("freeze_amount")?;
+        let freeze_step = reader.sample("freeze_step")?;
+        let delay_timing = reader.enumeration("delay_timing")?;
+        let mut normalized_delays = self.normalized_delays;
+        reader.f32s_into("normalized_delays", &mut normalized_delays)?;
+        let mut note_lens = self.note_lens;
+        reader.f32s_into("note_lens", &mut note_lens)?;
+        let mut delay_times_ms = self.delay_times_ms;
+        reader.f32s_into("delay_times_ms", &mut delay_times_ms)?;
+        let mut delay_samples = self.delay_samples;
+        reader.f32s_into("delay_samples", &mut delay_samples)?;
+        let has_max_delay_time = reader.bool("has_max_delay_time")?;
+        let max_delay_time_ms = reader.f32("max_delay_time_ms")?;
+        let tempo_bpm = reader.f32("tempo_bpm")?;
+        let sample_rate = reader.f32("sample_rate")?;
+
+        self.bufs.resize(buffer_size);
+        for (ch, values) in bufs.iter().enumerate() {
+            self.bufs[ch].copy_from_slice(values);
+        }
+        self.feedbacks = feedbacks;
+        self.feedback_amounts = feedback_amounts;
+        self.feedback_balance = feedback_balance;
+        self.is_feedback_linked = is_feedback_linked;
+        self.cross_feedbacks = cross_feedbacks;
+        self.cross_feedback_amounts = cross_feedback_amounts;
+        self.feedback_mode = feedback_mode;
+        self.input_mode = input_mode;
+        self.heads = heads;
+        self.interpolator = interpolator;
+        self.wow_flutter = wow_flutter;
+        self.saturator = saturator;
+        self.hp = hp;
+        self.lp = lp;
+        self.mix = mix;
+        self.is_frozen = is_frozen;
+        self.freeze_amount = freeze_amount;
+        self.freeze_step = freeze_step;
+        self.delay_timing = delay_timing;
+        self.normalized_delays = normalized_delays;
+        self.note_lens = note_lens;
+        self.delay_times_ms = delay_times_ms;
+        self.delay_samples = delay_samples;
+        self.max_delay_time_ms = match has_max_delay_time {
+            true => Some(max_delay_time_ms),
+            false => None,
+        };
+        self.tempo_bpm = tempo_bpm;
+        self.sample_rate = sample_rate;
+        Ok(())
+    }
+
+    fn set_channel_feedback(&mut self, ch: usize, feedback: f32) {
+        match self.is_feedback_linked {
+            true => self.feedback_amounts = [feedback; NUM_STEREO_CHANNELS],
+            false => self.feedback_amounts[ch] = feedback,
+        }
+        self.update_feedbacks();
+    }
+
+    fn update_feedbacks(&mut self) {
+        let balance = self.feedback_balance;
+        let gains = [(1. - balance).min(1.), (1. + balance).min(1.)];
+        for ch in [Self::LC, Self::RC] {
+            self.feedbacks[ch] = T::from_f32(self.feedback_amounts[ch] * gains[ch]);
+            self.cross_feedbacks[ch] = T::from_f32(self.cross_feedback_amounts[ch] * gains[ch]);
+        }
+    }
+
+    fn update_buffer_size(&mut self) {
+        if let Some(max_delay_time_ms) = self.max_delay_time_ms {
+            // Wow and flutter read up to their full depth further into the
+            // past.
+            let max_delay_samples = delay_time::ms_to_samples(
+                max_delay_time_ms + WowFlutter::MAX_OFFSET_MS,
+                self.sample_rate,
+            );
+            self.resize_buffers(delay_time::buffer_size(max_delay_samples));
+        }
+    }
+
+    fn update_fade_lens(&mut self) {
+        const FREEZE_FADE_MS: f32 = 20.;
+
+        let num_samples = delay_time::ms_to_samples(delay_time::CROSSFADE_MS, self.sample_rate);
+        for el in self.heads.iter_mut() {
+            el.set_crossfade_len(num_samples as usize);
+        }
+
+        let num_samples = delay_time::ms_to_samples(FREEZE_FADE_MS, self.sample_rate);
+        self.freeze_step = T::ONE / T::from_f32(num_samples.max(1.));
     }
 
-    fn read(&self, ch: usize, read_pos: f32) -> f32 {
-        let mut buf_pos = read_pos.floor() as usize;
-        let a = self.bufs[ch][buf_pos];
+    fn advance_freeze(&mut self) {
+        self.freeze_amount = match self.is_frozen {
+            true => (self.freeze_amount + self.freeze_step).min(T::ONE),
+            false => (self.freeze_amount - self.freeze_step).max(T::ZERO),
+        };
 
-        buf_pos = self.heads[ch].increment_pos(buf_pos);
-        let b = self.bufs[ch][buf_pos];
+        // The heads stay frozen until the release has faded out.
+        let is_head_frozen = self.freeze_amount > T::ZERO;
+        for el in self.heads.iter_mut() {
+            el.set_freeze(is_head_frozen);
+        }
+    }
 
-        a + (b - a) * read_pos.fract()
+    /// Fades `value` towards the unfiltered read value while freezing, which
+    /// then loops with unity feedback.
+    fn freeze_value(&self, value: T, unfiltered: T) -> T {
+        value + (unfiltered - value) * self.freeze_amount
     }
 
-    fn write(&mut self, ch: usize, pos: usize, value: f32) {
+    fn freeze_frame(&self, frame: &mut AudioFrame<T>, unfiltered: &AudioFrame<T>) {
+        if self.freeze_amount == T::ZERO {
+            return;
+        }
+
+        for ch in [Self::LC, Self::RC] {
+            frame[ch] = self.freeze_value(frame[ch], unfiltered[ch]);
+        }
+    }
+
+    fn update_delay_times(&mut self) {
+        for ch in [Self::LC, Self::RC] {
+            self.update_delay_time(ch);
+        }
+    }
+
+    fn update_delay_time(&mut self, ch: usize) {
+        match self.delay_time_samples(ch) {
+            Some(samples) => self.heads[ch].set_heads_diff_samples(samples),
+            None => self.heads[ch].set_heads_diff(self.normalized_delays[ch]),
+        }
+    }
+
+    /// Delay time of channel `ch` in samples before the heads limit it to
+    /// the buffer size, `None` for times relative to the buffer size.
+    fn delay_time_samples(&self, ch: usize) -> Option<f32> {
+        match self.delay_timing {
+            DelayTiming::Normalized => None,
+            DelayTiming::NoteLength => Some(delay_time::note_len_to_samples(
+                self.note_lens[ch],
+                self.tempo_bpm,
+                self.sample_rate,
+            )),
+            DelayTiming::Milliseconds => Some(delay_time::ms_to_samples(
+                self.delay_times_ms[ch],
+                self.sample_rate,
+            )),
+            DelayTiming::Samples => Some(self.delay_samples[ch]),
+        }
+    }
+
+    fn modulate_heads(&mut self) {
+        let offsets = self.wow_flutter.process();
+        for (head, offset) in self.heads.iter_mut().zip(offsets) {
+            head.set_modulation(offset);
+        }
+    }
+
+    /// Reads and interpolates both channels at once, see
+    /// `DelayLineHeads::read`. Only linear interpolation of a single forward
+    /// head is vectorized.
+    fn read_stereo(&mut self, outputs: &mut AudioFrame<T>) {
+        let has_more_heads = self
+            .heads
+            .iter()
+            .any(|head| !head.is_frozen() && (head.is_crossfading() || head.is_reverse()));
+        if has_more_heads || self.interpolator.interpolation() != Interpolation::Linear {
+            for ch in [Self::LC, Self::RC] {
+                outputs[ch] = self.heads[ch].read(&mut self.interpolator, ch, &self.bufs[ch]);
+            }
+            return;
+        }
+
+        let mut a = [T::ZERO; NUM_CHANNELS];
+        let mut b = [T::ZERO; NUM_CHANNELS];
+        let mut fract = [T::ZERO; NUM_CHANNELS];
+        for ch in [Self::LC, Self::RC] {
+            let read_pos = self.heads[ch].read_pos();
+            let buf_pos = read_pos.floor().to_usize();
+            a[ch] = self.bufs[ch][buf_pos];
+            b[ch] = self.bufs[ch][self.heads[ch].increment_pos(buf_pos)];
+            fract[ch] = read_pos.fract();
+        }
+
+        let values = T::add_frame(&a, &T::mul_frame(&T::sub_frame(&b, &a), &fract));
+        outputs[Self::LC] = values[Self::LC];
+        outputs[Self::RC] = values[Self::RC];
+    }
+
+    fn route_inputs(&self, inputs: &AudioFrame<T>) -> AudioFrame<T> {
+        let mut routed = *inputs;
+        if self.feedback_mode == FeedbackMode::PingPong && self.input_mode == InputMode::MonoSum {
+            routed[Self::LC] = (inputs[Self::LC] + inputs[Self::RC]) * T::from_f32(0.5);
+            routed[Self::RC] = T::ZERO;
+        }
+
+        routed
+    }
+
+    /// Writes input plus feedback of both channels at once.
+    fn write_stereo(
+        &mut self,
+        inputs: &AudioFrame<T>,
+        outputs: &AudioFrame<T>,
+        unfiltered: &AudioFrame<T>,
+    ) {
+        let (direct, cross) = match self.feedback_mode {
+            FeedbackMode::Matrix => (self.feedbacks, self.cross_feedbacks),
+            FeedbackMode::PingPong => (
+                [T::ZERO; NUM_STEREO_CHANNELS],
+                [self.feedbacks[Self::RC], self.feedbacks[Self::LC]],
+            ),
+        };
+
+        let mut feedbacks = [T::ZERO; NUM_CHANNELS];
+        feedbacks[..NUM_STEREO_CHANNELS].copy_from_slice(&direct);
+        let mut cross_feedbacks = [T::ZERO; NUM_CHANNELS];
+        cross_feedbacks[..NUM_STEREO_CHANNELS].copy_from_slice(&cross);
+        let mut swapped = [T::ZERO; NUM_CHANNELS];
+        swapped[Self::LC] = outputs[Self::RC];
+        swapped[Self::RC] = outputs[Self::LC];
+
+        let values = T::add_frame(inputs, &T::mul_frame(outputs, &feedbacks));
+        let mut values = T::add_frame(&values, &T::mul_frame(&swapped, &cross_feedbacks));
+        self.saturator.process_frame(&mut values);
+        self.freeze_frame(&mut values, unfiltered);
+        for ch in [Self::LC, Self::RC] {
+            let pos = self.heads[ch].write_pos();
+            self.write(ch, pos, values[ch]);
+        }
+    }
+
+    fn write(&mut self, ch: usize, pos: usize, value: T) {
         self.bufs[ch][pos] = value;
     }
 
-    fn filter(&mut self, input: f32) -> f32 {
+    fn filter(&mut self, input: T) -> T {
         let mut val = self.hp.process_mono(input);
         val = self.lp.process_mono(val);
         val
     }
 
-    fn filter_multi(&mut self, outputs: &mut AudioFrame) {
+    fn filter_multi(&mut self, outputs: &mut AudioFrame<T>) {
         self.hp.process(outputs);
         self.lp.process(outputs);
     }
@@ -152,7 +918,8 @@ impl StereoDelay {
 
 #[cfg(test)]
 mod tests {
-    use crate::{DEFAULT_SAMPLE_RATE, NUM_CHANNELS};
+    use crate::{math, DEFAULT_SAMPLE_RATE, NUM_CHANNELS};
+    use core::f32::consts::PI;
 
     use super::*;
     const TEST_BUF_SIZE: usize = 128;
@@ -328,12 +1095,15 @@ mod tests {
 
         let mut outputs: AudioFrame = [1.; NUM_CHANNELS];
         delay_line.process_stereo(&mut outputs);
-        test_output.push(outputs[StereoDelay::LC]);
+        test_output.push(outputs[StereoDelay::<f32>::LC]);
         for _ in 0..(TEST_BUF_SIZE - 1) {
             outputs.copy_from_slice(&[0., 0., 0., 0.]);
             delay_line.process_stereo(&mut outputs);
-            assert_eq!(outputs[StereoDelay::LC], outputs[StereoDelay::RC]);
-            test_output.push(outputs[StereoDelay::LC]);
+            assert_eq!(
+                outputs[StereoDelay::<f32>::LC],
+                outputs[StereoDelay::<f32>::RC]
+            );
+            test_output.push(outputs[StereoDelay::<f32>::LC]);
         }
 
         //println!("{:#?}", test_output);
@@ -488,14 +1258,577 @@ mod tests {
 
         let mut outputs: AudioFrame = [1.; NUM_CHANNELS];
         delay_line.process_stereo(&mut outputs);
-        test_output.push(outputs[StereoDelay::LC]);
+        test_output.push(outputs[StereoDelay::<f32>::LC]);
         for _ in 0..(TEST_BUF_SIZE - 1) {
             outputs.copy_from_slice(&[0., 0., 0., 0.]);
             delay_line.process_stereo(&mut outputs);
-            test_output.push(outputs[StereoDelay::LC]);
+            test_output.push(outputs[StereoDelay::<f32>::LC]);
         }
 
         //println!("{:#?}", test_output);
         assert_eq!(EXPECTED_RESULT.to_vec(), test_output);
     }
+
+    #[test]
+    fn test_stereo_delay_state_restore_is_bit_exact() {
+        let mut delay_line = StereoDelay::new();
+        delay_line.set_buffer_size(64);
+        delay_line.set_normalized_delay_left(0.25);
+        delay_line.set_normalized_delay_right(0.4);
+        delay_line.set_feedback(0.6);
+        delay_line.set_sample_rate(DEFAULT_SAMPLE_RATE);
+        delay_line.set_hp_freq(50.);
+        delay_line.set_lp_freq(8000.);
+        delay_line.set_wow_depth_ms(0.2);
+        delay_line.set_flutter_depth_ms(0.05);
+        delay_line.set_wow_flutter_stereo_phase(0.25);
+        delay_line.set_wow_flutter_noise(0.5);
+        delay_line.reset_heads();
+
+        let process = |delay_line: &mut StereoDelay, num_samples: usize| {
+            let mut outputs = Vec::new();
+            for i in 0..num_samples {
+                let value = ((i * 7919) % 200) as f32 / 100. - 1.;
+                let mut frame: AudioFrame = [value, -value, 0., 0.];
+                delay_line.process_stereo(&mut frame);
+                outputs.push(frame.map(f32::to_bits));
+            }
+            outputs
+        };
+
+        process(&mut delay_line, 100);
+
+        let mut from_binary = StereoDelay::new();
+        from_binary
+            .restore_state_binary(&delay_line.save_state_binary())
+            .unwrap();
+        let mut from_text = StereoDelay::new();
+        from_text
+            .restore_state_text(&delay_line.save_state_text())
+            .unwrap();
+
+        let expected = process(&mut delay_line, 300);
+        assert_eq!(process(&mut from_binary, 300), expected);
+        assert_eq!(process(&mut from_text, 300), expected);
+    }
+
+    #[test]
+    fn test_stereo_delay_f64_follows_f32() {
+        fn setup<T: Sample>(delay_line: &mut StereoDelay<T>) {
+            delay_line.set_buffer_size(64);
+            delay_line.set_normalized_delay_left(0.25);
+            delay_line.set_normalized_delay_right(0.4);
+            delay_line.set_feedback(0.6);
+            delay_line.set_sample_rate(DEFAULT_SAMPLE_RATE);
+            delay_line.set_hp_freq(50.);
+            delay_line.set_lp_freq(8000.);
+            delay_line.reset_heads();
+        }
+
+        let mut single = StereoDelay::new();
+        let mut double = StereoDelay::<f64>::with_sample_type();
+        setup(&mut single);
+        setup(&mut double);
+
+        for i in 0..500 {
+            let value = ((i * 7919) % 200) as f32 / 100. - 1.;
+            let mut frame: AudioFrame = [value, -value, 0., 0.];
+            let mut frame_f64: AudioFrame<f64> = frame.map(f64::from);
+            single.process_stereo(&mut frame);
+            double.process_stereo(&mut frame_f64);
+
+            for (a, b) in frame.iter().zip(frame_f64.iter()) {
+                assert!((*a as f64 - b).abs() < 1e-4);
+            }
+        }
+
+        let mut restored = StereoDelay::<f64>::with_sample_type();
+        restored
+            .restore_state_binary(&double.save_state_binary())
+            .unwrap();
+        assert!(StereoDelay::new()
+            .restore_state_binary(&double.save_state_binary())
+            .is_err());
+    }
+
+    #[test]
+    fn test_stereo_delay_with_buffer() {
+        const MEMORY_SIZE: usize = 2 * 64;
+
+        let memory = Box::leak(Box::new([1_f32; MEMORY_SIZE]));
+        let mut delay_line = StereoDelay::with_buffer(memory);
+        delay_line.set_buffer_size(1000);
+        assert_eq!(delay_line.bufs.len(), MEMORY_SIZE / 2);
+
+        delay_line.set_buffer_size(32);
+        delay_line.set_normalized_delay_left(0.);
+        delay_line.set_feedback(1.);
+        delay_line.set_hp_freq(20.);
+        delay_line.set_lp_freq(22050.);
+        delay_line.reset_heads();
+        delay_line.clear_buffer();
+        delay_line.set_sample_rate(44100_f32);
+
+        let mut test_output = Vec::new();
+
+        test_output.push(delay_line.process_mono(1.));
+        for _ in 0..(TEST_BUF_SIZE - 1) {
+            test_output.push(delay_line.process_mono(0.));
+        }
+
+        assert_eq!(RESULT_LEFT.to_vec(), test_output);
+
+        // A clone owns a copy of the memory and continues identically.
+        let mut clone = delay_line.clone();
+        assert!(matches!(clone.bufs, Buffers::Owned(_)));
+        for _ in 0..TEST_BUF_SIZE {
+            assert_eq!(clone.process_mono(0.), delay_line.process_mono(0.));
+        }
+    }
+
+    #[test]
+    fn test_stereo_delay_note_length() {
+        const SAMPLE_RATE: f32 = 1000.;
+        // A quarter note lasts 500 ms at 120 BPM and 250 ms at 240 BPM.
+        const QUARTER_DELAY: usize = 500;
+        const FAST_QUARTER_DELAY: usize = 250;
+        const GLIDE_LEN: usize = 2000;
+
+        fn first_echo(delay_line: &mut StereoDelay, num_samples: usize) -> usize {
+            let mut outputs = Vec::new();
+            outputs.push(delay_line.process_mono(1.));
+            for _ in 0..num_samples {
+                outputs.push(delay_line.process_mono(0.));
+            }
+            outputs.iter().position(|v| *v != 0.).unwrap()
+        }
+
+        let mut delay_line = StereoDelay::new();
+        delay_line.set_buffer_size(1000);
+        delay_line.set_sample_rate(SAMPLE_RATE);
+        delay_line.set_tempo(120.);
+        delay_line.set_feedback(0.);
+        delay_line.set_delay_timing(DelayTiming::NoteLength);
+        delay_line.set_note_len_left(1. / 4.);
+        delay_line.reset_heads();
+        delay_line.clear_buffer();
+        assert_eq!(first_echo(&mut delay_line, 1000), QUARTER_DELAY);
+
+        // The read head glides to the new time instead of jumping.
+        delay_line.set_tempo(240.);
+        for _ in 0..GLIDE_LEN {
+            delay_line.process_mono(0.);
+        }
+        assert_eq!(first_echo(&mut delay_line, 1000), FAST_QUARTER_DELAY);
+
+        // At 44.1 kHz a quarter takes 22050 samples, more than the default
+        // buffers hold.
+        let mut delay_line = StereoDelay::new();
+        delay_line.set_sample_rate(44100.);
+        delay_line.set_tempo(120.);
+        delay_line.set_feedback(0.);
+        delay_line.set_delay_timing(DelayTiming::NoteLength);
+        delay_line.set_note_len_left(1. / 4.);
+        assert!(delay_line.is_delay_time_limited());
+
+        delay_line.set_max_delay_time_ms(1000.);
+        assert!(!delay_line.is_delay_time_limited());
+        delay_line.reset_heads();
+        delay_line.clear_buffer();
+        assert_eq!(first_echo(&mut delay_line, 30000), 22050);
+    }
+
+    #[test]
+    fn test_stereo_delay_max_delay_time() {
+        const MAX_DELAY_TIME_MS: f32 = 100.;
+        const DELAY_TIME_MS: f32 = 50.;
+
+        let mut delay_line = StereoDelay::new();
+        delay_line.set_sample_rate(1000.);
+        delay_line.set_max_delay_time_ms(MAX_DELAY_TIME_MS);
+        // Two extra samples plus 40 ms for wow and flutter at full depth.
+        assert_eq!(delay_line.bufs.len(), 142);
+
+        delay_line.set_feedback(0.);
+        delay_line.set_delay_timing(DelayTiming::Milliseconds);
+        delay_line.set_delay_time_ms_left(DELAY_TIME_MS);
+        for _ in 0..1000 {
+            delay_line.process_mono(1.);
+        }
+
+        // Shrinking the buffer keeps the heads inside it.
+        delay_line.set_sample_rate(500.);
+        assert_eq!(delay_line.bufs.len(), 72);
+        delay_line.reset_heads();
+        delay_line.clear_buffer();
+
+        let mut outputs = Vec::new();
+        outputs.push(delay_line.process_mono(1.));
+        for _ in 0..100 {
+            outputs.push(delay_line.process_mono(0.));
+        }
+        assert_eq!(outputs.iter().position(|v| *v != 0.), Some(25));
+    }
+
+    #[test]
+    fn test_stereo_delay_modulation_stays_in_buffer() {
+        const BUF_SIZE: usize = 16;
+        const MAX_DIFF: f32 = (BUF_SIZE - 1) as f32;
+
+        let mut delay_line = StereoDelay::new();
+        delay_line.set_buffer_size(BUF_SIZE);
+        delay_line.set_time_change_mode(TimeChangeMode::Crossfade);
+        delay_line.set_delay_timing(DelayTiming::Samples);
+        delay_line.set_delay_samples_left(MAX_DIFF - 0.5);
+        delay_line.reset_heads();
+        for _ in 0..BUF_SIZE {
+            delay_line.process_mono(0.);
+        }
+
+        // Starts a crossfade from the old read head to a shorter delay.
+        delay_line.set_delay_samples_left(4.);
+        let head = &mut delay_line.heads[StereoDelay::<f32>::LC];
+        head.set_modulation(10.);
+
+        let write_pos = head.write_pos() as f32;
+        let diff = |pos: f32| (write_pos + BUF_SIZE as f32 - pos) % BUF_SIZE as f32;
+        assert!(head.is_crossfading());
+        assert_eq!(diff(head.fade_read_pos().0), MAX_DIFF);
+        assert_eq!(diff(head.read_pos()), 14.);
+
+        head.set_reverse(true);
+        for (pos, _) in head.reverse_read_pos() {
+            assert!(diff(pos) <= MAX_DIFF);
+        }
+    }
+
+    #[test]
+    fn test_stereo_delay_ping_pong() {
+        const DELAY_SAMPLES: usize = 10;
+        // The echo side is louder than the filter tail on the other side.
+        const RATIO: f32 = 100.;
+
+        let mut delay_line = StereoDelay::new();
+        delay_line.set_buffer_size(100);
+        delay_line.set_sample_rate(DEFAULT_SAMPLE_RATE);
+        delay_line.set_hp_freq(1.);
+        delay_line.set_lp_freq(20000.);
+        delay_line.set_delay_timing(DelayTiming::Samples);
+        delay_line.set_delay_samples_left(DELAY_SAMPLES as f32);
+        delay_line.set_delay_samples_right(DELAY_SAMPLES as f32);
+        delay_line.set_feedback(0.5);
+        delay_line.set_feedback_mode(FeedbackMode::PingPong);
+        delay_line.set_input_mode(InputMode::MonoSum);
+        delay_line.reset_heads();
+        delay_line.clear_buffer();
+
+        // An impulse on the right input starts on the left and bounces.
+        let mut outputs = Vec::new();
+        for i in 0..4 * DELAY_SAMPLES {
+            let mut frame = [0.; NUM_CHANNELS];
+            if i == 0 {
+                frame[StereoDelay::<f32>::RC] = 1.;
+            }
+            delay_line.process_stereo(&mut frame);
+            outputs.push(frame);
+        }
+
+        for (n, side) in [
+            (1, StereoDelay::<f32>::LC),
+            (2, StereoDelay::<f32>::RC),
+            (3, StereoDelay::<f32>::LC),
+        ] {
+            let frame = &outputs[n * DELAY_SAMPLES];
+            assert!(frame[side].abs() > RATIO * frame[1 - side].abs());
+        }
+    }
+
+    #[test]
+    fn test_stereo_delay_feedback_per_channel() {
+        let mut delay_line = StereoDelay::new();
+        delay_line.set_feedback_left(0.5);
+        delay_line.set_feedback_right(0.25);
+        assert_eq!(delay_line.feedbacks, [0.5, 0.25]);
+
+        delay_line.set_feedback_balance(0.5);
+        assert_eq!(delay_line.feedbacks, [0.25, 0.25]);
+
+        delay_line.set_feedback_balance(0.);
+        delay_line.set_feedback_link(true);
+        assert_eq!(delay_line.feedbacks, [0.5, 0.5]);
+        delay_line.set_feedback_right(0.75);
+        assert_eq!(delay_line.feedbacks, [0.75, 0.75]);
+    }
+
+    #[test]
+    fn test_stereo_delay_feedback_matrix_link_and_balance() {
+        let mut delay_line = StereoDelay::new();
+        delay_line.set_feedback_matrix(0.5, 0.125, 0.25, 0.75);
+        assert_eq!(delay_line.feedbacks, [0.5, 0.75]);
+        assert_eq!(delay_line.cross_feedbacks, [0.25, 0.125]);
+
+        // The balance scales everything fed into a delay line.
+        delay_line.set_feedback_balance(0.5);
+        assert_eq!(delay_line.feedbacks, [0.25, 0.75]);
+        assert_eq!(delay_line.cross_feedbacks, [0.125, 0.125]);
+
+        delay_line.set_feedback_balance(0.);
+        delay_line.set_feedback_link(true);
+        assert_eq!(delay_line.feedbacks, [0.5, 0.5]);
+        assert_eq!(delay_line.cross_feedbacks, [0.25, 0.25]);
+        delay_line.set_feedback_matrix(0.5, 0.125, 0.375, 0.75);
+        assert_eq!(delay_line.feedbacks, [0.5, 0.5]);
+        assert_eq!(delay_line.cross_feedbacks, [0.375, 0.375]);
+
+        // Restoring keeps the amounts the balance applies to.
+        let mut restored = StereoDelay::new();
+        restored
+            .restore_state_binary(&delay_line.save_state_binary())
+            .unwrap();
+        restored.set_feedback_balance(-1.);
+        assert_eq!(restored.feedbacks, [0.5, 0.]);
+        assert_eq!(restored.cross_feedbacks, [0.375, 0.]);
+    }
+
+    #[test]
+    fn test_stereo_delay_time_change_modes() {
+        const CHANGE_POS: usize = 300;
+        // 50 ms at the sample rate below.
+        const CROSSFADE_LEN: usize = 50;
+        const TOLERANCE: f32 = 1e-3;
+
+        // The input is a ramp, so each output tells the delay time.
+        fn delays_after_change(time_change_mode: TimeChangeMode) -> Vec<f32> {
+            let mut delay_line = StereoDelay::new();
+            delay_line.set_buffer_size(1000);
+            delay_line.set_sample_rate(1000.);
+            delay_line.set_feedback(0.);
+            delay_line.set_delay_timing(DelayTiming::Samples);
+            delay_line.set_delay_samples_left(100.);
+            delay_line.set_time_change_mode(time_change_mode);
+            delay_line.reset_heads();
+            delay_line.clear_buffer();
+
+            let mut delays = Vec::new();
+            for n in 0..CHANGE_POS + 2 * CROSSFADE_LEN {
+                if n == CHANGE_POS {
+                    delay_line.set_delay_samples_left(200.);
+                }
+                let output = delay_line.process_mono(n as f32);
+                if n >= CHANGE_POS {
+                    delays.push(n as f32 - output);
+                }
+            }
+            delays
+        }
+
+        let jump = delays_after_change(TimeChangeMode::Jump);
+        assert!(jump.iter().all(|delay| (delay - 200.).abs() < TOLERANCE));
+
+        let crossfade = delays_after_change(TimeChangeMode::Crossfade);
+        assert!((crossfade[0] - 100.).abs() < TOLERANCE);
+        assert!(crossfade[CROSSFADE_LEN / 2] > 110. && crossfade[CROSSFADE_LEN / 2] < 190.);
+        assert!(crossfade[CROSSFADE_LEN + 1..]
+            .iter()
+            .all(|delay| (delay - 200.).abs() < TOLERANCE));
+
+        // The default glide reads at 0.7 times the speed, so the delay grows
+        // by 0.3 samples per sample.
+        let glide = delays_after_change(TimeChangeMode::Glide);
+        assert!((glide[1] - glide[0] - 0.3).abs() < TOLERANCE);
+        assert!(glide[2 * CROSSFADE_LEN - 1] < 200.);
+    }
+
+    #[test]
+    fn test_stereo_delay_allpass_crossfade() {
+        const CHANGE_POS: usize = 300;
+        const NUM_SAMPLES: usize = 500;
+        const PERIOD: f32 = 40.;
+        const TOLERANCE: f32 = 1e-2;
+
+        // A slow sine, which both interpolations read almost exactly.
+        fn outputs(interpolation: Interpolation, is_reverse: bool) -> Vec<f32> {
+            let mut delay_line = StereoDelay::new();
+            delay_line.set_buffer_size(1000);
+            delay_line.set_sample_rate(1000.);
+            delay_line.set_feedback(0.);
+            delay_line.set_delay_timing(DelayTiming::Samples);
+            delay_line.set_delay_samples_left(100.25);
+            delay_line.set_time_change_mode(TimeChangeMode::Crossfade);
+            delay_line.set_interpolation(interpolation);
+            delay_line.set_reverse(is_reverse);
+            delay_line.reset_heads();
+            delay_line.clear_buffer();
+
+            (0..NUM_SAMPLES)
+                .map(|n| {
+                    if n == CHANGE_POS {
+                        delay_line.set_delay_samples_left(150.75);
+                    }
+                    let input = math::sinf(2. * PI * n as f32 / PERIOD);
+                    delay_line.process_mono(input)
+                })
+                .collect()
+        }
+
+        for is_reverse in [false, true] {
+            let allpass = outputs(Interpolation::Allpass, is_reverse);
+            let lagrange = outputs(Interpolation::Lagrange, is_reverse);
+            assert!(allpass
+                .iter()
+                .zip(lagrange.iter())
+                .all(|(a, b)| (a - b).abs() < TOLERANCE));
+        }
+    }
+
+    #[test]
+    fn test_stereo_delay_saturated_feedback_stays_bounded() {
+        const NUM_SAMPLES: usize = 20000;
+
+        let mut delay_line = StereoDelay::new();
+        delay_line.set_buffer_size(100);
+        delay_line.set_normalized_delay_left(0.5);
+        delay_line.set_normalized_delay_right(0.3);
+        delay_line.set_feedback(1.5);
+        delay_line.set_saturation(Saturation::Tape);
+        delay_line.reset_heads();
+        delay_line.clear_buffer();
+
+        let mut max_output: f32 = 0.;
+        for i in 0..NUM_SAMPLES {
+            let value = match i {
+                0 => 1.,
+                _ => 0.,
+            };
+            let mut frame: AudioFrame = [value, value, 0., 0.];
+            delay_line.process_stereo(&mut frame);
+            max_output = max_output.max(frame[0].abs()).max(frame[1].abs());
+        }
+
+        // The repeats grow until the curve limits them.
+        assert!(max_output > 0.5 && max_output <= 1.5);
+    }
+
+    #[test]
+    fn test_stereo_delay_reverse() {
+        // The reverse heads read up to twice the delay time back, the burst
+        // returns reversed right before the forward echo would.
+        const BURST: [f32; 3] = [1., 2., 3.];
+        const REVERSED_POS: usize = 97;
+        const TOLERANCE: f32 = 0.1;
+
+        let mut delay_line = StereoDelay::new();
+        delay_line.set_buffer_size(1000);
+        delay_line.set_sample_rate(1000.);
+        delay_line.set_feedback(0.);
+        delay_line.set_delay_timing(DelayTiming::Samples);
+        delay_line.set_delay_samples_left(100.);
+        delay_line.set_reverse(true);
+        delay_line.reset_heads();
+        delay_line.clear_buffer();
+
+        let outputs: Vec<f32> = (0..200)
+            .map(|n| delay_line.process_mono(BURST.get(n).copied().unwrap_or(0.)))
+            .collect();
+
+        for (n, output) in outputs.iter().enumerate() {
+            match n.checked_sub(REVERSED_POS) {
+                Some(i) if i < BURST.len() => {
+                    let expected = BURST[BURST.len() - 1 - i];
+                    assert!((output - expected).abs() < TOLERANCE, "{}", n);
+                }
+                _ => assert!(output.abs() < TOLERANCE, "{}", n),
+            }
+        }
+    }
+
+    #[test]
+    fn test_stereo_delay_freeze() {
+        // A fractional delay loops at the rounded length, so the impulse is
+        // not smeared by the interpolation on every pass.
+        for (delay, loop_len) in [(50., 50), (50.5, 51)] {
+            check_freeze(delay, loop_len);
+        }
+    }
+
+    fn check_freeze(delay: f32, loop_len: usize) {
+        // The freeze fades in over 20 samples at the sample rate below.
+        const FREEZE_POS: usize = 10;
+        const INPUT_POS: usize = 40;
+        const RELEASE_POS: usize = 500;
+        const NUM_SAMPLES: usize = 700;
+        const TOLERANCE: f32 = 1e-6;
+
+        let mut delay_line = StereoDelay::new();
+        delay_line.set_buffer_size(100);
+        delay_line.set_sample_rate(1000.);
+        delay_line.set_feedback(0.);
+        delay_line.set_delay_timing(DelayTiming::Samples);
+        delay_line.set_delay_samples_left(delay);
+        delay_line.set_delay_samples_right(delay);
+        delay_line.reset_heads();
+        delay_line.clear_buffer();
+
+        let mut outputs = Vec::new();
+        for n in 0..NUM_SAMPLES {
+            match n {
+                FREEZE_POS => delay_line.set_freeze(true),
+                RELEASE_POS => delay_line.set_freeze(false),
+                _ => (),
+            }
+            let value = match n {
+                0 => 1.,
+                n if n >= INPUT_POS => 0.5,
+                _ => 0.,
+            };
+            let mut frame: AudioFrame = [value, value, 0., 0.];
+            delay_line.process_stereo(&mut frame);
+            outputs.push(frame[0]);
+        }
+
+        // The impulse keeps looping at unity gain, the input stays out.
+        for (n, output) in outputs[..RELEASE_POS].iter().enumerate() {
+            let expected = match n > 0 && n % loop_len == 0 {
+                true => 1.,
+                false => 0.,
+            };
+            assert!((output - expected).abs() < TOLERANCE, "{} {}", delay, n);
+        }
+
+        // After the release the input enters again.
+        assert!((outputs[NUM_SAMPLES - 1] - 0.5).abs() < TOLERANCE);
+    }
+
+    #[test]
+    fn test_stereo_delay_mix() {
+        // The wet signal stays silent until the first echo at 500 samples.
+        const NUM_SAMPLES: usize = 100;
+        const TOLERANCE: f32 = 1e-3;
+
+        fn settle(delay_line: &mut StereoDelay) -> f32 {
+            let mut output = 0.;
+            for _ in 0..NUM_SAMPLES {
+                output = delay_line.process_mono(1.);
+            }
+            output
+        }
+
+        let mut delay_line = StereoDelay::new();
+        delay_line.set_buffer_size(1000);
+        delay_line.set_sample_rate(1000.);
+        delay_line.set_delay_timing(DelayTiming::Samples);
+        delay_line.set_delay_samples_left(500.);
+        delay_line.reset_heads();
+        delay_line.clear_buffer();
+
+        delay_line.set_mix(0.);
+        assert!(delay_line.process_mono(1.) < 0.5);
+        assert!((settle(&mut delay_line) - 1.).abs() < TOLERANCE);
+
+        delay_line.set_output_gain_db(-20.);
+        assert!((settle(&mut delay_line) - 0.1).abs() < TOLERANCE);
+
+        delay_line.set_wet_only(true);
+        assert!(settle(&mut delay_line).abs() < TOLERANCE);
+    }
 }
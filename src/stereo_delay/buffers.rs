@@ -0,0 +1,141 @@
+// Copyright(c) 2022 Hansen Audio.
+
+use crate::Sample;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::{Index, IndexMut};
+
+/// Delay memory of all channels, either allocated by the delay itself or
+/// supplied by the caller. A supplied slice is split into equal parts, one
+/// per channel, and the buffer size is limited to the length of one part.
+pub(crate) enum Buffers<T: Sample> {
+    Owned(Vec<Vec<T>>),
+    Borrowed {
+        memory: &'static mut [T],
+        size: usize,
+        num_channels: usize,
+    },
+}
+
+impl<T: Sample> Buffers<T> {
+    pub fn owned(num_channels: usize, size: usize) -> Self {
+        Buffers::Owned(vec![vec![T::ZERO; size]; num_channels])
+    }
+
+    pub fn borrowed(num_channels: usize, memory: &'static mut [T]) -> Self {
+        let size = memory.len() / num_channels;
+        Buffers::Borrowed {
+            memory,
+            size,
+            num_channels,
+        }
+    }
+
+    pub fn num_channels(&self) -> usize {
+        match self {
+            Buffers::Owned(bufs) => bufs.len(),
+            Buffers::Borrowed { num_channels, .. } => *num_channels,
+        }
+    }
+
+    /// Size of one channel.
+    pub fn len(&self) -> usize {
+        match self {
+            Buffers::Owned(bufs) => bufs[0].len(),
+            Buffers::Borrowed { size, .. } => *size,
+        }
+    }
+
+    /// Maximum size of one channel, unlimited for owned memory.
+    pub fn capacity(&self) -> usize {
+        match self {
+            Buffers::Owned(_) => usize::MAX,
+            Buffers::Borrowed {
+                memory,
+                num_channels,
+                ..
+            } => memory.len() / *num_channels,
+        }
+    }
+
+    /// Resizes all channels and returns the new size, which is clamped to
+    /// `capacity`. Samples up to the old size are kept, new ones are zero.
+    pub fn resize(&mut self, new_size: usize) -> usize {
+        let new_size = new_size.min(self.capacity());
+        match self {
+            Buffers::Owned(bufs) => {
+                for buf in bufs.iter_mut() {
+                    buf.resize(new_size, T::ZERO);
+                }
+            }
+            Buffers::Borrowed {
+                memory,
+                size,
+                num_channels,
+            } => {
+                let stride = memory.len() / *num_channels;
+                if new_size > *size {
+                    for ch in 0..*num_channels {
+                        let start = ch * stride;
+                        memory[start + *size..start + new_size].fill(T::ZERO);
+                    }
+                }
+                *size = new_size;
+            }
+        }
+
+        new_size
+    }
+
+    pub fn clear(&mut self) {
+        for ch in 0..self.num_channels() {
+            self[ch].fill(T::ZERO);
+        }
+    }
+}
+
+/// Caller-supplied memory cannot be shared, so a clone always owns a copy of
+/// the delay memory, with the same size and contents.
+impl<T: Sample> Clone for Buffers<T> {
+    fn clone(&self) -> Self {
+        Buffers::Owned(
+            (0..self.num_channels())
+                .map(|ch| self[ch].to_vec())
+                .collect(),
+        )
+    }
+}
+
+impl<T: Sample> Index<usize> for Buffers<T> {
+    type Output = [T];
+
+    fn index(&self, ch: usize) -> &[T] {
+        match self {
+            Buffers::Owned(bufs) => &bufs[ch],
+            Buffers::Borrowed {
+                memory,
+                size,
+                num_channels,
+            } => {
+                let start = ch * (memory.len() / *num_channels);
+                &memory[start..start + *size]
+            }
+        }
+    }
+}
+
+impl<T: Sample> IndexMut<usize> for Buffers<T> {
+    fn index_mut(&mut self, ch: usize) -> &mut [T] {
+        match self {
+            Buffers::Owned(bufs) => &mut bufs[ch],
+            Buffers::Borrowed {
+                memory,
+                size,
+                num_channels,
+            } => {
+                let start = ch * (memory.len() / *num_channels);
+                &mut memory[start..start + *size]
+            }
+        }
+    }
+}
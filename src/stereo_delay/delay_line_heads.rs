@@ -1,29 +1,86 @@
 // Copyright(c) 2022 Hansen Audio.
 
+use super::interpolation::Interpolator;
+use crate::math;
+use crate::state::{impl_state_enum, StateError, StateReader, StateWriter};
+use crate::Sample;
+use core::f32::consts::PI;
+
+/// Defines how the read head moves to a new delay time.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeChangeMode {
+    /// The read head speeds up or slows down until it reaches the new time,
+    /// which bends the pitch like a tape machine.
+    Glide,
+    /// A second read head starts at the new time and the output crossfades
+    /// from the old to the new head, the pitch stays unchanged.
+    Crossfade,
+    /// The read head jumps to the new time at once.
+    Jump,
+}
+
+/// Heads of one delay line which read forwards and keep their own allpass
+/// state, the current one and the one crossfading out.
+pub(crate) const NUM_FORWARD_HEADS: usize = 2;
+
+impl_state_enum!(
+    TimeChangeMode,
+    [
+        TimeChangeMode::Glide,
+        TimeChangeMode::Crossfade,
+        TimeChangeMode::Jump,
+    ]
+);
+
 #[derive(Clone, Copy)]
-pub(super) struct DelayLineHeads {
-    read_head: f32,
+pub(crate) struct DelayLineHeads<T: Sample = f32> {
+    read_head: T,
     write_head: usize,
-    read_head_increment: f32,
-    heads_diff_dst: f32,
+    read_head_increment: T,
+    heads_diff_dst: T,
+    time_change_mode: TimeChangeMode,
+    slow_increment: T,
+    fast_increment: T,
+    fade_read_head: T,
+    fade_gain: T,
+    fade_step: T,
+    main_slot: usize,
+    is_reverse: bool,
+    reverse_phase: T,
+    modulation: T,
+    is_frozen: bool,
+    frozen_diff: T,
     buffer_size: usize,
-    buffer_size_f: f32,
+    buffer_size_f: T,
 }
 
-impl DelayLineHeads {
+impl<T: Sample> DelayLineHeads<T> {
     const ONE_SAMPLE: usize = 1;
-    const ONE_SAMPLE_F: f32 = 1.;
-    const SLOW_INCREMENT_F: f32 = 0.7;
-    const FAST_INCREMENT_F: f32 = 1.3;
+    const SLOW_INCREMENT_F: f64 = 0.7;
+    const FAST_INCREMENT_F: f64 = 1.3;
+    const MIN_GLIDE_RATE: f32 = 0.01;
 
     pub fn new() -> Self {
         Self {
-            read_head: 0.,
+            read_head: T::ZERO,
             write_head: 0,
-            read_head_increment: 0.,
-            heads_diff_dst: 0.,
+            read_head_increment: T::ZERO,
+            heads_diff_dst: T::ZERO,
+            time_change_mode: TimeChangeMode::Glide,
+            slow_increment: T::from_f64(Self::SLOW_INCREMENT_F),
+            fast_increment: T::from_f64(Self::FAST_INCREMENT_F),
+            fade_read_head: T::ZERO,
+            fade_gain: T::ZERO,
+            fade_step: T::ONE,
+            main_slot: 0,
+            is_reverse: false,
+            reverse_phase: T::ZERO,
+            modulation: T::ZERO,
+            is_frozen: false,
+            frozen_diff: T::ONE,
             buffer_size: 8000,
-            buffer_size_f: 8000.,
+            buffer_size_f: T::from_usize(8000),
         }
     }
 
@@ -33,77 +90,367 @@ impl DelayLineHeads {
         self.write_head += Self::ONE_SAMPLE;
         self.read_head += self.read_head_increment;
 
-        if diff <= Self::ONE_SAMPLE_F {
-            self.read_head = self.write_head as f32 - self.heads_diff_dst;
+        if diff <= T::ONE {
+            self.read_head = T::from_usize(self.write_head) - self.heads_diff_dst;
         }
 
-        self.read_head = Self::bind_to_buffer_f32(self.read_head, self.buffer_size_f);
+        self.read_head = Self::bind_to_buffer_f(self.read_head, self.buffer_size_f);
         self.write_head = Self::bind_to_buffer_usize(self.write_head, self.buffer_size);
+
+        if self.is_reverse {
+            self.reverse_phase += T::ONE / self.reverse_window_len();
+            self.reverse_phase = self.reverse_phase.fract();
+        }
+
+        if self.is_crossfading() {
+            self.fade_read_head =
+                Self::bind_to_buffer_f(self.fade_read_head + T::ONE, self.buffer_size_f);
+            self.fade_gain = (self.fade_gain - self.fade_step).max(T::ZERO);
+
+            // Catch up with a time change which arrived during the fade.
+            if !self.is_crossfading() {
+                self.start_crossfade();
+            }
+        }
     }
 
     pub fn reset(&mut self) {
-        self.read_head = self.write_head as f32 - self.heads_diff_dst;
-        self.read_head_increment = Self::ONE_SAMPLE_F;
-        self.read_head = Self::bind_to_buffer_f32(self.read_head, self.buffer_size_f);
+        self.read_head = T::from_usize(self.write_head) - self.heads_diff_dst;
+        self.read_head_increment = T::ONE;
+        self.read_head = Self::bind_to_buffer_f(self.read_head, self.buffer_size_f);
+        self.fade_gain = T::ZERO;
+    }
+
+    pub fn set_time_change_mode(&mut self, time_change_mode: TimeChangeMode) {
+        if self.time_change_mode == time_change_mode {
+            return;
+        }
+
+        self.time_change_mode = time_change_mode;
+        self.fade_gain = T::ZERO;
+        self.calc_read_head_increment(self.heads_diff_dst);
+    }
+
+    /// Sets how much faster or slower than normal the read head moves while
+    /// gliding, 0.3 by default.
+    pub fn set_glide_rate(&mut self, glide_rate: f32) {
+        let glide_rate = glide_rate.clamp(Self::MIN_GLIDE_RATE, 1.) as f64;
+        self.slow_increment = T::from_f64(1. - glide_rate);
+        self.fast_increment = T::from_f64(1. + glide_rate);
+        if self.time_change_mode == TimeChangeMode::Glide {
+            self.calc_read_head_increment(self.heads_diff_dst);
+        }
+    }
+
+    /// Plays the delay line backwards in windows of the delay length.
+    pub fn set_reverse(&mut self, is_reverse: bool) {
+        self.is_reverse = is_reverse;
+        self.reverse_phase = T::ZERO;
+    }
+
+    pub fn is_reverse(&self) -> bool {
+        self.is_reverse
+    }
+
+    /// Positions and gains of the two reverse read heads. Each one starts
+    /// right behind the write head and runs backwards, so its distance grows
+    /// by two samples per sample up to twice the window length. The heads
+    /// are half a window apart and their sine squared gains add up to one,
+    /// which crossfades the window boundaries.
+    pub fn reverse_read_pos(&self) -> [(T, T); 2] {
+        const HALF_WINDOW: f32 = 0.5;
+
+        let window_len = self.reverse_window_len();
+        let write_head = T::from_usize(self.write_head) + self.buffer_size_f;
+        [T::ZERO, T::from_f32(HALF_WINDOW)].map(|offset| {
+            let phase = (self.reverse_phase + offset).fract();
+            let diff = T::ONE + T::from_f32(2.) * phase * window_len;
+            let pos = write_head - (diff + self.modulation).min(self.max_diff());
+            let gain = math::sinf(PI * phase.to_f32());
+            (
+                Self::bind_to_buffer_f(pos, self.buffer_size_f),
+                T::from_f32(gain * gain),
+            )
+        })
+    }
+
+    /// The delay time, limited so that two windows fit into the buffer.
+    fn reverse_window_len(&self) -> T {
+        let max_len = T::from_usize(self.buffer_size.saturating_sub(2) / 2).max(T::ONE);
+        self.heads_diff_dst.max(T::ONE).min(max_len)
+    }
+
+    /// Sets the length of a crossfade between the old and the new read head.
+    pub fn set_crossfade_len(&mut self, num_samples: usize) {
+        self.fade_step = T::ONE / T::from_usize(num_samples.max(1));
     }
 
     pub fn set_buffer_size(&mut self, buffer_size: usize) {
         self.buffer_size = buffer_size;
-        self.buffer_size_f = buffer_size as f32;
+        self.buffer_size_f = T::from_usize(buffer_size);
+        self.frozen_diff = self.frozen_diff.min(self.max_diff());
+
+        // Keep both heads inside a smaller buffer.
+        if self.write_head >= buffer_size || self.read_head >= self.buffer_size_f {
+            self.write_head %= buffer_size;
+            self.heads_diff_dst = self.heads_diff_dst.min(self.max_diff());
+            self.reset();
+        }
     }
 
-    fn calc_read_head_offset(&self, diff: f32) -> f32 {
-        diff * self.buffer_size_f
+    fn calc_read_head_offset(&self, diff: f32) -> T {
+        T::from_f32(diff) * self.buffer_size_f
     }
 
-    fn calc_read_head_increment(&mut self, diff: f32) {
-        self.heads_diff_dst = self.calc_read_head_offset(diff);
+    fn calc_read_head_increment(&mut self, heads_diff: T) {
+        self.heads_diff_dst = heads_diff;
 
-        self.read_head_increment = if self.current_diff() < self.heads_diff_dst {
-            Self::SLOW_INCREMENT_F
-        } else {
-            Self::FAST_INCREMENT_F
-        };
+        match self.time_change_mode {
+            TimeChangeMode::Glide => {
+                self.read_head_increment = if self.current_diff() < self.heads_diff_dst {
+                    self.slow_increment
+                } else {
+                    self.fast_increment
+                };
+            }
+            // A running crossfade picks up the new time when it has finished.
+            TimeChangeMode::Crossfade => {
+                if !self.is_crossfading() {
+                    self.start_crossfade();
+                }
+            }
+            TimeChangeMode::Jump => self.reset(),
+        }
+    }
+
+    /// Hands the current read head over to the fading one and moves it to
+    /// the new time. Changes within one sample just snap.
+    fn start_crossfade(&mut self) {
+        let diff = (self.current_diff() - self.heads_diff_dst).abs();
+        let read_head = self.read_head;
+        self.reset();
+
+        // The old head takes its allpass state along.
+        if diff > T::ONE {
+            self.fade_read_head = read_head;
+            self.fade_gain = T::ONE;
+            self.main_slot = (self.main_slot + 1) % NUM_FORWARD_HEADS;
+        }
+    }
+
+    pub fn is_crossfading(&self) -> bool {
+        self.fade_gain > T::ZERO
+    }
+
+    /// Position and gain of the read head fading out during a crossfade.
+    /// The gain falls from one to zero, the new head gets the remainder.
+    pub fn fade_read_pos(&self) -> (T, T) {
+        (self.modulate(self.fade_read_head), self.fade_gain)
     }
 
     pub fn set_heads_diff(&mut self, diff: f32) {
-        let _diff = diff.clamp(0., 1.);
-        self.calc_read_head_increment(diff);
+        self.calc_read_head_increment(self.calc_read_head_offset(diff));
     }
 
-    fn current_diff(&self) -> f32 {
-        let diff = (self.write_head as f32 + self.buffer_size_f) - self.read_head;
-        Self::bind_to_buffer_f32(diff, self.buffer_size_f)
+    /// Sets the distance of the heads in samples, limited to the buffer size.
+    /// Like `set_heads_diff` the read head glides to the new distance.
+    pub fn set_heads_diff_samples(&mut self, samples: f32) {
+        let heads_diff = T::from_f32(samples).max(T::ZERO).min(self.max_diff());
+        self.calc_read_head_increment(heads_diff);
     }
 
-    pub fn read_pos(&self) -> f32 {
-        self.read_head
+    /// Moves the read position this many samples further into the past,
+    /// limited to the buffer size. Used by wow and flutter.
+    pub fn set_modulation(&mut self, samples: f32) {
+        self.modulation = T::from_f32(samples).max(T::ZERO).min(self.max_diff());
+    }
+
+    /// Holds the read position a whole number of samples behind the write
+    /// head and ignores the modulation, so a frozen loop is read without
+    /// interpolation and keeps its length. The distance is the rounded one
+    /// at the moment of freezing, the read head itself moves on.
+    pub fn set_freeze(&mut self, is_frozen: bool) {
+        if is_frozen && !self.is_frozen {
+            let diff = (self.diff_to(self.read_pos()) + T::from_f32(0.5)).floor();
+            self.frozen_diff = diff.max(T::ONE).min(self.max_diff());
+        }
+        self.is_frozen = is_frozen;
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.is_frozen
+    }
+
+    /// The heads keep at most this many samples apart, so a read never
+    /// passes the write head.
+    fn max_diff(&self) -> T {
+        self.buffer_size_f - T::ONE
+    }
+
+    fn current_diff(&self) -> T {
+        self.diff_to(self.read_head)
+    }
+
+    /// Distance from `head` to the write head.
+    fn diff_to(&self, head: T) -> T {
+        let diff = (T::from_usize(self.write_head) + self.buffer_size_f) - head;
+        Self::bind_to_buffer_f(diff, self.buffer_size_f)
+    }
+
+    /// Moves `head` back by the modulation, at most to `max_diff` behind the
+    /// write head.
+    fn modulate(&self, head: T) -> T {
+        let max_modulation = (self.max_diff() - self.diff_to(head)).max(T::ZERO);
+        let pos = head - self.modulation.min(max_modulation);
+        Self::bind_to_buffer_f(pos, self.buffer_size_f)
+    }
+
+    pub fn read_pos(&self) -> T {
+        match self.is_frozen {
+            true => {
+                let pos = T::from_usize(self.write_head) + self.buffer_size_f - self.frozen_diff;
+                Self::bind_to_buffer_f(pos, self.buffer_size_f)
+            }
+            false => self.modulate(self.read_head),
+        }
     }
 
     pub fn write_pos(&self) -> usize {
         self.write_head
     }
 
+    /// Reads `buf` at the read head and, during a crossfade, blends in the
+    /// old read head. In reverse mode both reverse heads are mixed, a frozen
+    /// head only reads its loop. `stream` selects the allpass states of the
+    /// forward heads in `interpolator`, `NUM_FORWARD_HEADS` per stream.
+    pub fn read<const N: usize>(
+        &self,
+        interpolator: &mut Interpolator<T, N>,
+        stream: usize,
+        buf: &[T],
+    ) -> T {
+        let main_stream = stream * NUM_FORWARD_HEADS + self.main_slot;
+        if self.is_frozen {
+            return self.read_at(interpolator, main_stream, buf, self.read_pos());
+        }
+
+        if self.is_reverse {
+            return self
+                .reverse_read_pos()
+                .iter()
+                .fold(T::ZERO, |sum, (pos, gain)| {
+                    let (buf_pos, fract, num_newer) = self.split_pos(*pos);
+                    sum + interpolator.read_stateless(buf, buf_pos, fract, num_newer) * *gain
+                });
+        }
+
+        let value = self.read_at(interpolator, main_stream, buf, self.read_pos());
+        if !self.is_crossfading() {
+            return value;
+        }
+
+        let fade_slot = (self.main_slot + 1) % NUM_FORWARD_HEADS;
+        let fade_stream = stream * NUM_FORWARD_HEADS + fade_slot;
+        let (fade_pos, fade_gain) = self.fade_read_pos();
+        let fading = self.read_at(interpolator, fade_stream, buf, fade_pos);
+        value + (fading - value) * fade_gain
+    }
+
+    fn read_at<const N: usize>(
+        &self,
+        interpolator: &mut Interpolator<T, N>,
+        stream: usize,
+        buf: &[T],
+        read_pos: T,
+    ) -> T {
+        let (buf_pos, fract, num_newer) = self.split_pos(read_pos);
+        interpolator.read(stream, buf, buf_pos, fract, num_newer)
+    }
+
+    /// Buffer index, fraction and the number of samples written after the
+    /// index.
+    fn split_pos(&self, read_pos: T) -> (usize, T, usize) {
+        let buf_pos = read_pos.floor().to_usize();
+        // The write position still holds the oldest sample.
+        let num_newer = (self.write_head + self.buffer_size - buf_pos - 1) % self.buffer_size;
+        (buf_pos, read_pos.fract(), num_newer)
+    }
+
     pub fn increment_pos(&self, pos: usize) -> usize {
         let next_pos = pos + Self::ONE_SAMPLE;
         Self::bind_to_buffer_usize(next_pos, self.buffer_size)
     }
 
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.sample("read_head", self.read_head);
+        writer.usize("write_head", self.write_head);
+        writer.sample("read_head_increment", self.read_head_increment);
+        writer.sample("heads_diff_dst", self.heads_diff_dst);
+        writer.enumeration("time_change_mode", self.time_change_mode);
+        writer.sample("slow_increment", self.slow_increment);
+        writer.sample("fast_increment", self.fast_increment);
+        writer.sample("fade_read_head", self.fade_read_head);
+        writer.sample("fade_gain", self.fade_gain);
+        writer.sample("fade_step", self.fade_step);
+        writer.usize("main_head_slot", self.main_slot);
+        writer.bool("is_reverse", self.is_reverse);
+        writer.sample("reverse_phase", self.reverse_phase);
+        writer.bool("is_head_frozen", self.is_frozen);
+        writer.sample("frozen_diff", self.frozen_diff);
+    }
+
+    /// Expects the buffer size to be set up already.
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.read_head = reader.sample("read_head")?;
+        self.write_head = reader.usize("write_head")?;
+        self.read_head_increment = reader.sample("read_head_increment")?;
+        self.heads_diff_dst = reader.sample("heads_diff_dst")?;
+        self.time_change_mode = reader.enumeration("time_change_mode")?;
+        self.slow_increment = reader.sample("slow_increment")?;
+        self.fast_increment = reader.sample("fast_increment")?;
+        self.fade_read_head = reader.sample("fade_read_head")?;
+        self.fade_gain = reader.sample("fade_gain")?;
+        self.fade_step = reader.sample("fade_step")?;
+        self.main_slot = reader.usize("main_head_slot")?;
+        self.is_reverse = reader.bool("is_reverse")?;
+        self.reverse_phase = reader.sample("reverse_phase")?;
+        self.is_frozen = reader.bool("is_head_frozen")?;
+        self.frozen_diff = reader.sample("frozen_diff")?;
+
+        let is_valid_pos = |pos: T| pos >= T::ZERO && pos < self.buffer_size_f;
+        if !is_valid_pos(self.read_head)
+            || !is_valid_pos(self.fade_read_head)
+            || !(T::ZERO..T::ONE).contains(&self.reverse_phase)
+            || !(T::ONE..self.buffer_size_f).contains(&self.frozen_diff)
+            || self.write_head >= self.buffer_size
+            || self.main_slot >= NUM_FORWARD_HEADS
+        {
+            return Err(StateError::InvalidValue);
+        }
+
+        Ok(())
+    }
+
     pub fn bind_to_buffer_usize(index: usize, buffer_size: usize) -> usize {
         if index >= buffer_size {
             index - buffer_size
-        } else if index < 0 as usize {
-            index + (buffer_size - 1 as usize)
         } else {
             index
         }
     }
 
-    fn bind_to_buffer_f32(index: f32, buffer_size: f32) -> f32 {
+    fn bind_to_buffer_f(index: T, buffer_size: T) -> T {
         if index >= buffer_size {
             index - buffer_size
-        } else if index < 0 as f32 {
-            index + (buffer_size - 1 as f32)
+        } else if index < T::ZERO {
+            // Wraps by the full buffer size, so -0.5 lies half a sample
+            // before the end. Tiny negative values round up to the buffer
+            // size, which wraps to zero.
+            let index = index + buffer_size;
+            match index < buffer_size {
+                true => index,
+                false => T::ZERO,
+            }
         } else {
             index
         }
@@ -128,3 +475,20 @@ impl DelayLineHeads {
         }
     }*/
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bind_to_buffer_wraps_by_buffer_size() {
+        const BUFFER_SIZE: f32 = 8.;
+
+        let bind = |index| DelayLineHeads::<f32>::bind_to_buffer_f(index, BUFFER_SIZE);
+        assert_eq!(bind(3.25), 3.25);
+        assert_eq!(bind(8.5), 0.5);
+        assert_eq!(bind(-0.5), 7.5);
+        assert_eq!(bind(-1.), 7.);
+        assert_eq!(bind(-1e-9), 0.);
+    }
+}
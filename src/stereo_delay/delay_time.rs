@@ -0,0 +1,30 @@
+// Copyright(c) 2022 Hansen Audio.
+
+//! Delay time conversions shared by the stereo and the multi-tap delay.
+
+/// Duration of a crossfade from the old to the new read head.
+pub const CROSSFADE_MS: f32 = 50.;
+
+pub fn ms_to_samples(time_ms: f32, sample_rate: f32) -> f32 {
+    const MS_PER_SEC: f32 = 1000.;
+
+    time_ms * sample_rate / MS_PER_SEC
+}
+
+/// Converts a note length, e.g. `1. / 4.` for a quarter, into samples.
+pub fn note_len_to_samples(note_len: f32, tempo_bpm: f32, sample_rate: f32) -> f32 {
+    const QUARTERS_PER_NOTE: f32 = 4.;
+    const SECS_PER_MINUTE: f32 = 60.;
+
+    let secs = note_len * QUARTERS_PER_NOTE * SECS_PER_MINUTE / tempo_bpm;
+    secs * sample_rate
+}
+
+/// Buffer size which holds delays of up to `max_delay_samples`. One more
+/// sample than the truncated delay covers the fraction, the heads keep at
+/// most `size - 1` samples apart.
+pub fn buffer_size(max_delay_samples: f32) -> usize {
+    const MIN_BUF_SIZE: usize = 2;
+
+    max_delay_samples.max(0.) as usize + MIN_BUF_SIZE
+}
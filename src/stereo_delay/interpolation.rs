@@ -0,0 +1,283 @@
+// Copyright(c) 2022 Hansen Audio.
+
+use crate::math;
+use crate::state::{impl_state_enum, StateError, StateReader, StateWriter};
+use crate::Sample;
+use core::f32::consts::PI;
+
+/// Defines how the delay lines are read between two samples.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Two samples, cheapest but dulls high frequencies.
+    Linear,
+    /// Four samples, third order Hermite polynomial.
+    CubicHermite,
+    /// Four samples, third order Lagrange polynomial.
+    Lagrange,
+    /// First order Thiran allpass. Flat magnitude response, but it keeps a
+    /// state and suits static or slowly changing delay times best. Heads
+    /// running backwards and delay times up to 2 samples use `Lagrange`.
+    Allpass,
+    /// Hann windowed sinc with 16 samples, for high quality offline renders.
+    /// Delay times up to 8 samples fall back to `Lagrange`, the window would
+    /// reach past the newest sample.
+    Sinc,
+}
+
+impl_state_enum!(
+    Interpolation,
+    [
+        Interpolation::Linear,
+        Interpolation::CubicHermite,
+        Interpolation::Lagrange,
+        Interpolation::Allpass,
+        Interpolation::Sinc,
+    ]
+);
+
+/// Reads the delay lines at a fractional position. Positions grow towards
+/// newer samples. Each of the `N` read streams, e.g. the read heads of all
+/// channels or taps, keeps its own allpass state.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Interpolator<T: Sample, const N: usize> {
+    interpolation: Interpolation,
+    allpass_y1: [T; N],
+}
+
+impl<T: Sample, const N: usize> Interpolator<T, N> {
+    const SINC_HALF_LEN: usize = 8;
+    const ALLPASS_NUM_NEWER: usize = 2;
+
+    pub fn new() -> Self {
+        Self {
+            interpolation: Interpolation::Linear,
+            allpass_y1: [T::ZERO; N],
+        }
+    }
+
+    pub fn interpolation(&self) -> Interpolation {
+        self.interpolation
+    }
+
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        if self.interpolation == interpolation {
+            return;
+        }
+
+        self.interpolation = interpolation;
+        self.allpass_y1 = [T::ZERO; N];
+    }
+
+    /// Reads `buf` for `stream` at `pos + fract`. The allpass state of the
+    /// stream expects `pos` to move on by about one sample per call.
+    /// `num_newer` is the number of samples written after `pos`, reads past
+    /// the newest sample repeat it.
+    pub fn read(&mut self, stream: usize, buf: &[T], pos: usize, fract: T, num_newer: usize) -> T {
+        match self.interpolation {
+            Interpolation::Allpass if num_newer >= Self::ALLPASS_NUM_NEWER => {
+                self.allpass(stream, buf, pos, fract)
+            }
+            _ => self.read_stateless(buf, pos, fract, num_newer),
+        }
+    }
+
+    /// Like `read`, but without an allpass state, the allpass falls back to
+    /// `Lagrange`. For heads which do not move forward, e.g. reverse ones.
+    pub fn read_stateless(&self, buf: &[T], pos: usize, fract: T, num_newer: usize) -> T {
+        match self.interpolation {
+            Interpolation::Linear => {
+                let a = buf[pos];
+                let b = buf[Self::newer(buf, pos, 1, num_newer)];
+                a + (b - a) * fract
+            }
+            Interpolation::CubicHermite => {
+                Self::hermite(Self::four_points(buf, pos, num_newer), fract)
+            }
+            Interpolation::Sinc if num_newer >= Self::SINC_HALF_LEN => Self::sinc(buf, pos, fract),
+            _ => Self::lagrange(Self::four_points(buf, pos, num_newer), fract),
+        }
+    }
+
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.enumeration("interpolation", self.interpolation);
+        writer.samples("allpass_y1", &self.allpass_y1);
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.interpolation = reader.enumeration("interpolation")?;
+        reader.samples_into("allpass_y1", &mut self.allpass_y1)
+    }
+
+    /// Index of `pos + offset`, wrapped into the buffer.
+    fn wrap(buf: &[T], pos: usize, offset: isize) -> usize {
+        let len = buf.len() as isize;
+        (pos as isize + offset).rem_euclid(len) as usize
+    }
+
+    /// Index of `pos + offset`, but at most the newest sample.
+    fn newer(buf: &[T], pos: usize, offset: usize, num_newer: usize) -> usize {
+        Self::wrap(buf, pos, offset.min(num_newer) as isize)
+    }
+
+    /// The samples at `pos - 1` up to `pos + 2`.
+    fn four_points(buf: &[T], pos: usize, num_newer: usize) -> [T; 4] {
+        [
+            buf[Self::wrap(buf, pos, -1)],
+            buf[pos],
+            buf[Self::newer(buf, pos, 1, num_newer)],
+            buf[Self::newer(buf, pos, 2, num_newer)],
+        ]
+    }
+
+    fn hermite(y: [T; 4], x: T) -> T {
+        let half = T::from_f32(0.5);
+        let c0 = y[1];
+        let c1 = half * (y[2] - y[0]);
+        let c2 = y[0] - T::from_f32(2.5) * y[1] + T::from_f32(2.) * y[2] - half * y[3];
+        let c3 = half * (y[3] - y[0]) + T::from_f32(1.5) * (y[1] - y[2]);
+
+        ((c3 * x + c2) * x + c1) * x + c0
+    }
+
+    fn lagrange(y: [T; 4], x: T) -> T {
+        let half = T::from_f32(0.5);
+        let sixth = T::from_f32(1. / 6.);
+        let xm1 = x - T::ONE;
+        let xm2 = x - T::from_f32(2.);
+        let xp1 = x + T::ONE;
+
+        let weights = [
+            -sixth * x * xm1 * xm2,
+            half * xp1 * xm1 * xm2,
+            -half * xp1 * x * xm2,
+            sixth * xp1 * x * xm1,
+        ];
+        weights
+            .iter()
+            .zip(y.iter())
+            .fold(T::ZERO, |sum, (w, y)| sum + *w * *y)
+    }
+
+    /// Delays the sample after `pos` by `1 - fract` or, for fractions above
+    /// one half, the second one after `pos` by `2 - fract`. The delay stays
+    /// in [0.5, 1.5], which keeps the pole away from -1 and avoids its
+    /// ringing.
+    fn allpass(&mut self, stream: usize, buf: &[T], pos: usize, fract: T) -> T {
+        let (offset, delay) = match fract > T::from_f32(0.5) {
+            true => (2, T::from_f32(2.) - fract),
+            false => (1, T::ONE - fract),
+        };
+        let coef = (T::ONE - delay) / (T::ONE + delay);
+        let x = buf[Self::wrap(buf, pos, offset)];
+        let x1 = buf[Self::wrap(buf, pos, offset - 1)];
+
+        let y = coef * (x - self.allpass_y1[stream]) + x1;
+        self.allpass_y1[stream] = y;
+        y
+    }
+
+    fn sinc(buf: &[T], pos: usize, fract: T) -> T {
+        let fract = fract.to_f32();
+        if fract == 0. {
+            return buf[pos];
+        }
+
+        // sin(pi * (k - fract)) only flips its sign from tap to tap.
+        let half_len = Self::SINC_HALF_LEN as f32;
+        let sin = math::sinf(PI * fract);
+        let mut sum = T::ZERO;
+        let mut weights = 0.;
+        for i in 0..2 * Self::SINC_HALF_LEN {
+            let k = i as isize - Self::SINC_HALF_LEN as isize + 1;
+            let x = k as f32 - fract;
+            let sign = match k % 2 == 0 {
+                true => -1.,
+                false => 1.,
+            };
+            let window = 0.5 * (1. + math::cosf(PI * x / half_len));
+            let weight = sign * sin / (PI * x) * window;
+
+            sum += buf[Self::wrap(buf, pos, k)] * T::from_f32(weight);
+            weights += weight;
+        }
+
+        sum / T::from_f32(weights)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::f64::consts::TAU;
+
+    #[test]
+    fn test_interpolation_modes() {
+        const PERIOD: f64 = 32.;
+        const POS: usize = 20;
+        const FRACT: f64 = 0.25;
+        const NUM_NEWER: usize = 16;
+
+        let buf: Vec<f64> = (0..64).map(|n| (TAU * n as f64 / PERIOD).sin()).collect();
+
+        for (interpolation, tolerance) in [
+            (Interpolation::Linear, 1e-2),
+            (Interpolation::CubicHermite, 1e-3),
+            (Interpolation::Lagrange, 1e-4),
+            (Interpolation::Allpass, 1e-3),
+            (Interpolation::Sinc, 1e-3),
+        ] {
+            for fract in [FRACT, 1. - FRACT] {
+                let mut interpolator = Interpolator::<f64, 1>::new();
+                interpolator.set_interpolation(interpolation);
+
+                // The allpass needs the previous positions to settle.
+                let mut value = 0.;
+                for pos in 0..=POS {
+                    value = interpolator.read(0, &buf, pos, fract, NUM_NEWER);
+                }
+                let expected = (TAU * (POS as f64 + fract) / PERIOD).sin();
+                assert!((value - expected).abs() < tolerance, "{:?}", interpolation);
+            }
+        }
+
+        // Without the eight newer samples the sinc falls back to Lagrange.
+        let mut sinc = Interpolator::<f64, 1>::new();
+        sinc.set_interpolation(Interpolation::Sinc);
+        let mut lagrange = Interpolator::<f64, 1>::new();
+        lagrange.set_interpolation(Interpolation::Lagrange);
+        assert_eq!(
+            sinc.read(0, &buf, POS, FRACT, 7),
+            lagrange.read(0, &buf, POS, FRACT, 7)
+        );
+    }
+
+    #[test]
+    fn test_interpolation_stops_at_newest_sample() {
+        const POS: usize = 20;
+        const FRACT: f64 = 0.75;
+
+        let buf: Vec<f64> = (0..64).map(|n| n as f64).collect();
+        for num_newer in 0..2 {
+            // Samples after the newest one are the oldest in the buffer.
+            let mut overwritten = buf.clone();
+            overwritten[POS + num_newer + 1..].fill(1000.);
+
+            for interpolation in [
+                Interpolation::Linear,
+                Interpolation::CubicHermite,
+                Interpolation::Lagrange,
+                Interpolation::Allpass,
+                Interpolation::Sinc,
+            ] {
+                let mut interpolator = Interpolator::<f64, 1>::new();
+                interpolator.set_interpolation(interpolation);
+                let value = interpolator.read(0, &buf, POS, FRACT, num_newer);
+                let mut interpolator = Interpolator::<f64, 1>::new();
+                interpolator.set_interpolation(interpolation);
+                let overwritten_value = interpolator.read(0, &overwritten, POS, FRACT, num_newer);
+                assert_eq!(value, overwritten_value, "{:?}", interpolation);
+            }
+        }
+    }
+}
@@ -0,0 +1,166 @@
+// Copyright(c) 2022 Hansen Audio.
+
+use crate::math;
+use crate::state::{impl_state_enum, StateError, StateReader, StateWriter};
+use crate::{AudioFrame, Sample, DEFAULT_SAMPLE_RATE, NUM_CHANNELS, NUM_STEREO_CHANNELS};
+use core::f32::consts::{FRAC_PI_2, LN_10};
+
+/// Defines how the mix control crossfades between dry and wet signal.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MixLaw {
+    /// Dry and wet gains add up to one.
+    Linear,
+    /// Dry and wet powers add up to one, keeps the loudness of uncorrelated
+    /// signals in the middle position.
+    EqualPower,
+}
+
+impl_state_enum!(MixLaw, [MixLaw::Linear, MixLaw::EqualPower]);
+
+/// Dry/wet mix and output gain. Changes are smoothed with a one pole filter
+/// on the resulting dry and wet gains.
+#[derive(Debug, Clone)]
+pub(super) struct Mix<T: Sample> {
+    mix: f32,
+    mix_law: MixLaw,
+    dry_level: f32,
+    wet_level: f32,
+    output_gain_db: f32,
+    is_wet_only: bool,
+    pole: T,
+    dry_gain_dst: T,
+    wet_gain_dst: T,
+    dry_gain: T,
+    wet_gain: T,
+}
+
+impl<T: Sample> Mix<T> {
+    const SMOOTHING_SECS: f32 = 0.01;
+
+    pub fn new() -> Self {
+        let mut mix = Self {
+            mix: 1.,
+            mix_law: MixLaw::Linear,
+            dry_level: 1.,
+            wet_level: 1.,
+            output_gain_db: 0.,
+            is_wet_only: false,
+            pole: T::ZERO,
+            dry_gain_dst: T::ZERO,
+            wet_gain_dst: T::ONE,
+            dry_gain: T::ZERO,
+            wet_gain: T::ONE,
+        };
+
+        mix.set_sample_rate(DEFAULT_SAMPLE_RATE);
+        mix
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        let tau_samples = Self::SMOOTHING_SECS * sample_rate;
+        self.pole = match tau_samples > 0. {
+            true => T::from_f32(math::expf(-1. / tau_samples)),
+            false => T::ZERO,
+        };
+    }
+
+    pub fn set_mix(&mut self, mix: f32) {
+        self.mix = mix.clamp(0., 1.);
+        self.update_gains();
+    }
+
+    pub fn set_mix_law(&mut self, mix_law: MixLaw) {
+        self.mix_law = mix_law;
+        self.update_gains();
+    }
+
+    pub fn set_dry_level(&mut self, dry_level: f32) {
+        self.dry_level = dry_level;
+        self.update_gains();
+    }
+
+    pub fn set_wet_level(&mut self, wet_level: f32) {
+        self.wet_level = wet_level;
+        self.update_gains();
+    }
+
+    pub fn set_output_gain_db(&mut self, output_gain_db: f32) {
+        self.output_gain_db = output_gain_db;
+        self.update_gains();
+    }
+
+    pub fn set_wet_only(&mut self, is_wet_only: bool) {
+        self.is_wet_only = is_wet_only;
+        self.update_gains();
+    }
+
+    /// Mixes `dry` into the stereo channels of `wet`, the other channels
+    /// keep the wet signal.
+    pub fn process(&mut self, dry: &AudioFrame<T>, wet: &mut AudioFrame<T>) {
+        self.advance();
+
+        let mut dry_gains = [T::ZERO; NUM_CHANNELS];
+        let mut wet_gains = [T::ONE; NUM_CHANNELS];
+        dry_gains[..NUM_STEREO_CHANNELS].fill(self.dry_gain);
+        wet_gains[..NUM_STEREO_CHANNELS].fill(self.wet_gain);
+
+        *wet = T::add_frame(
+            &T::mul_frame(dry, &dry_gains),
+            &T::mul_frame(wet, &wet_gains),
+        );
+    }
+
+    pub fn process_mono(&mut self, dry: T, wet: T) -> T {
+        self.advance();
+        dry * self.dry_gain + wet * self.wet_gain
+    }
+
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.f32("mix", self.mix);
+        writer.enumeration("mix_law", self.mix_law);
+        writer.f32("dry_level", self.dry_level);
+        writer.f32("wet_level", self.wet_level);
+        writer.f32("output_gain_db", self.output_gain_db);
+        writer.bool("is_wet_only", self.is_wet_only);
+        writer.sample("mix_pole", self.pole);
+        writer.sample("dry_gain", self.dry_gain);
+        writer.sample("wet_gain", self.wet_gain);
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.mix = reader.f32("mix")?;
+        self.mix_law = reader.enumeration("mix_law")?;
+        self.dry_level = reader.f32("dry_level")?;
+        self.wet_level = reader.f32("wet_level")?;
+        self.output_gain_db = reader.f32("output_gain_db")?;
+        self.is_wet_only = reader.bool("is_wet_only")?;
+        self.pole = reader.sample("mix_pole")?;
+        self.dry_gain = reader.sample("dry_gain")?;
+        self.wet_gain = reader.sample("wet_gain")?;
+        self.update_gains();
+        Ok(())
+    }
+
+    fn advance(&mut self) {
+        self.dry_gain = self.dry_gain_dst + self.pole * (self.dry_gain - self.dry_gain_dst);
+        self.wet_gain = self.wet_gain_dst + self.pole * (self.wet_gain - self.wet_gain_dst);
+    }
+
+    fn update_gains(&mut self) {
+        const DB_TO_LN: f32 = LN_10 / 20.;
+
+        let (dry, wet) = match (self.is_wet_only, self.mix_law) {
+            (true, _) => (0., 1.),
+            (false, MixLaw::Linear) => (1. - self.mix, self.mix),
+            (false, MixLaw::EqualPower) => {
+                let angle = self.mix * FRAC_PI_2;
+                (math::cosf(angle).max(0.), math::sinf(angle))
+            }
+        };
+
+        let output_gain = math::expf(self.output_gain_db * DB_TO_LN);
+        self.dry_gain_dst = T::from_f32(dry * self.dry_level * output_gain);
+        self.wet_gain_dst = T::from_f32(wet * self.wet_level * output_gain);
+    }
+}
@@ -0,0 +1,172 @@
+// Copyright(c) 2022 Hansen Audio.
+
+use crate::math;
+use crate::state::{impl_state_enum, StateError, StateReader, StateWriter};
+use crate::{AudioFrame, Sample, NUM_STEREO_CHANNELS};
+use core::f32::consts::LN_10;
+
+/// Defines the curve of the saturation in the feedback loop.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Saturation {
+    /// The feedback loop stays linear.
+    Off,
+    /// Cubic curve which reaches its limit at a finite level.
+    SoftClip,
+    /// Symmetric `tanh` curve, odd harmonics only.
+    Tape,
+    /// Asymmetric `tanh` curve, adds even harmonics.
+    Tube,
+}
+
+impl_state_enum!(
+    Saturation,
+    [
+        Saturation::Off,
+        Saturation::SoftClip,
+        Saturation::Tape,
+        Saturation::Tube,
+    ]
+);
+
+/// Saturation of the signal written into the delay lines. All curves have a
+/// slope of one at zero and are bounded, so feedback above one settles at a
+/// limited level instead of growing without bound.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Saturator<T: Sample> {
+    saturation: Saturation,
+    drive_db: f32,
+    is_compensated: bool,
+    drive: T,
+    makeup: T,
+}
+
+impl<T: Sample> Saturator<T> {
+    const SOFT_CLIP_LIMIT: f32 = 1.5;
+    const TUBE_BIAS: f32 = 0.2;
+
+    pub fn new() -> Self {
+        Self {
+            saturation: Saturation::Off,
+            drive_db: 0.,
+            is_compensated: true,
+            drive: T::ONE,
+            makeup: T::ONE,
+        }
+    }
+
+    pub fn set_saturation(&mut self, saturation: Saturation) {
+        self.saturation = saturation;
+    }
+
+    pub fn set_drive_db(&mut self, drive_db: f32) {
+        self.drive_db = drive_db;
+        self.update_gains();
+    }
+
+    /// Divides by the drive after the curve, so quiet signals keep their
+    /// level and the drive only changes how early the curve bends.
+    pub fn set_compensation(&mut self, is_compensated: bool) {
+        self.is_compensated = is_compensated;
+        self.update_gains();
+    }
+
+    pub fn process(&self, input: T) -> T {
+        match self.saturation {
+            Saturation::Off => input,
+            saturation => Self::shape(saturation, input * self.drive) * self.makeup,
+        }
+    }
+
+    /// Saturates the stereo channels of `frame`.
+    pub fn process_frame(&self, frame: &mut AudioFrame<T>) {
+        if self.saturation == Saturation::Off {
+            return;
+        }
+
+        for value in frame[..NUM_STEREO_CHANNELS].iter_mut() {
+            *value = self.process(*value);
+        }
+    }
+
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.enumeration("saturation", self.saturation);
+        writer.f32("saturation_drive_db", self.drive_db);
+        writer.bool("is_saturation_compensated", self.is_compensated);
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.saturation = reader.enumeration("saturation")?;
+        self.drive_db = reader.f32("saturation_drive_db")?;
+        self.is_compensated = reader.bool("is_saturation_compensated")?;
+        self.update_gains();
+        Ok(())
+    }
+
+    fn shape(saturation: Saturation, x: T) -> T {
+        match saturation {
+            Saturation::Off => x,
+            Saturation::SoftClip => {
+                // x - 4/27 x^3 reaches its maximum of one at 1.5.
+                let limit = T::from_f32(Self::SOFT_CLIP_LIMIT);
+                let x = x.max(-limit).min(limit);
+                x - T::from_f32(4. / 27.) * x * x * x
+            }
+            Saturation::Tape => x.tanh(),
+            Saturation::Tube => {
+                // Shifting the curve keeps zero in place, dividing by the
+                // slope at the bias restores unity gain for quiet signals.
+                let bias = T::from_f32(Self::TUBE_BIAS);
+                let bias_tanh = bias.tanh();
+                ((x + bias).tanh() - bias_tanh) / (T::ONE - bias_tanh * bias_tanh)
+            }
+        }
+    }
+
+    fn update_gains(&mut self) {
+        const DB_TO_LN: f32 = LN_10 / 20.;
+
+        let drive = math::expf(self.drive_db * DB_TO_LN);
+        self.drive = T::from_f32(drive);
+        self.makeup = match self.is_compensated {
+            true => T::from_f32(1. / drive),
+            false => T::ONE,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_saturation_curves() {
+        const QUIET: f64 = 1e-4;
+
+        let mut saturator = Saturator::<f64>::new();
+        assert_eq!(saturator.process(10.), 10.);
+
+        for saturation in [Saturation::SoftClip, Saturation::Tape, Saturation::Tube] {
+            saturator.set_saturation(saturation);
+            saturator.set_drive_db(0.);
+
+            // Unity gain for quiet signals, bounded for loud ones.
+            assert!(
+                (saturator.process(QUIET) - QUIET).abs() < 1e-6,
+                "{:?}",
+                saturation
+            );
+            assert!(saturator.process(100.).abs() < 2., "{:?}", saturation);
+            assert!(saturator.process(-100.).abs() < 2., "{:?}", saturation);
+
+            // The compensation keeps quiet signals at unity gain.
+            saturator.set_drive_db(20.);
+            assert!(
+                (saturator.process(QUIET) - QUIET).abs() < 1e-5,
+                "{:?}",
+                saturation
+            );
+            assert!(saturator.process(100.).abs() < 0.2, "{:?}", saturation);
+        }
+    }
+}
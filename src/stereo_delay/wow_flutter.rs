@@ -0,0 +1,230 @@
+// Copyright(c) 2022 Hansen Audio.
+
+use crate::math;
+use crate::state::{StateError, StateReader, StateWriter};
+use crate::{DEFAULT_SAMPLE_RATE, NUM_STEREO_CHANNELS};
+use core::f32::consts::{PI, TAU};
+
+/// Tape style modulation of the read heads. Wow and flutter each combine a
+/// sine LFO with random drift which changes smoothly once per LFO cycle.
+#[derive(Debug, Clone)]
+pub(super) struct WowFlutter {
+    rates_hz: [f32; Self::NUM_SOURCES],
+    depths_ms: [f32; Self::NUM_SOURCES],
+    stereo_phase: f32,
+    noise_amount: f32,
+    sample_rate: f32,
+    rng_state: u32,
+    phases: [[f32; NUM_STEREO_CHANNELS]; Self::NUM_SOURCES],
+    prev_noise: [[f32; NUM_STEREO_CHANNELS]; Self::NUM_SOURCES],
+    next_noise: [[f32; NUM_STEREO_CHANNELS]; Self::NUM_SOURCES],
+}
+
+impl WowFlutter {
+    const WOW: usize = 0;
+    const FLUTTER: usize = 1;
+    const NUM_SOURCES: usize = 2;
+    const SEED: u32 = 0x2545_f491;
+    /// Depth limit of each source.
+    pub const MAX_DEPTH_MS: f32 = 20.;
+    /// Largest offset `process` can return, both sources at full depth.
+    pub const MAX_OFFSET_MS: f32 = Self::MAX_DEPTH_MS * Self::NUM_SOURCES as f32;
+
+    pub fn new() -> Self {
+        const DEFAULT_WOW_RATE_HZ: f32 = 0.5;
+        const DEFAULT_FLUTTER_RATE_HZ: f32 = 8.;
+
+        let mut wow_flutter = Self {
+            rates_hz: [DEFAULT_WOW_RATE_HZ, DEFAULT_FLUTTER_RATE_HZ],
+            depths_ms: [0.; Self::NUM_SOURCES],
+            stereo_phase: 0.,
+            noise_amount: 0.,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            rng_state: 0,
+            phases: [[0.; NUM_STEREO_CHANNELS]; Self::NUM_SOURCES],
+            prev_noise: [[0.; NUM_STEREO_CHANNELS]; Self::NUM_SOURCES],
+            next_noise: [[0.; NUM_STEREO_CHANNELS]; Self::NUM_SOURCES],
+        };
+
+        wow_flutter.reset();
+        wow_flutter
+    }
+
+    /// Restarts the LFOs and the random drift.
+    pub fn reset(&mut self) {
+        self.rng_state = Self::SEED;
+        for source in [Self::WOW, Self::FLUTTER] {
+            self.phases[source] = [0., self.stereo_phase];
+            for ch in 0..NUM_STEREO_CHANNELS {
+                self.prev_noise[source][ch] = 0.;
+                self.next_noise[source][ch] = self.next_bipolar();
+            }
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    pub fn set_wow_rate_hz(&mut self, rate_hz: f32) {
+        self.rates_hz[Self::WOW] = rate_hz.max(0.);
+    }
+
+    pub fn set_wow_depth_ms(&mut self, depth_ms: f32) {
+        self.depths_ms[Self::WOW] = depth_ms.clamp(0., Self::MAX_DEPTH_MS);
+    }
+
+    pub fn set_flutter_rate_hz(&mut self, rate_hz: f32) {
+        self.rates_hz[Self::FLUTTER] = rate_hz.max(0.);
+    }
+
+    pub fn set_flutter_depth_ms(&mut self, depth_ms: f32) {
+        self.depths_ms[Self::FLUTTER] = depth_ms.clamp(0., Self::MAX_DEPTH_MS);
+    }
+
+    /// Shifts the right LFOs against the left ones, in cycles.
+    pub fn set_stereo_phase(&mut self, stereo_phase: f32) {
+        self.stereo_phase = stereo_phase.clamp(0., 1.);
+        for phases in self.phases.iter_mut() {
+            phases[1] = Self::wrap(phases[0] + self.stereo_phase);
+        }
+    }
+
+    /// Crossfades from the pure sine at 0 to pure random drift at 1.
+    pub fn set_noise_amount(&mut self, amount: f32) {
+        self.noise_amount = amount.clamp(0., 1.);
+    }
+
+    /// Advances by one sample and returns how many samples each read head
+    /// lags behind, between zero and the sum of both depths.
+    pub fn process(&mut self) -> [f32; NUM_STEREO_CHANNELS] {
+        const MS_TO_SECS: f32 = 0.001;
+
+        let mut offsets = [0.; NUM_STEREO_CHANNELS];
+        for source in [Self::WOW, Self::FLUTTER] {
+            let depth_samples = self.depths_ms[source] * MS_TO_SECS * self.sample_rate;
+            if depth_samples > 0. {
+                for (ch, offset) in offsets.iter_mut().enumerate() {
+                    *offset += depth_samples * 0.5 * (1. + self.value(source, ch));
+                }
+            }
+
+            self.advance(source);
+        }
+
+        offsets
+    }
+
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.f32s("wow_flutter_rates_hz", &self.rates_hz);
+        writer.f32s("wow_flutter_depths_ms", &self.depths_ms);
+        writer.f32("wow_flutter_stereo_phase", self.stereo_phase);
+        writer.f32("wow_flutter_noise_amount", self.noise_amount);
+        writer.f32("wow_flutter_sample_rate", self.sample_rate);
+        writer.u64("wow_flutter_rng_state", self.rng_state as u64);
+        for source in [Self::WOW, Self::FLUTTER] {
+            writer.f32s("wow_flutter_phases", &self.phases[source]);
+            writer.f32s("wow_flutter_prev_noise", &self.prev_noise[source]);
+            writer.f32s("wow_flutter_next_noise", &self.next_noise[source]);
+        }
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.f32s_into("wow_flutter_rates_hz", &mut self.rates_hz)?;
+        reader.f32s_into("wow_flutter_depths_ms", &mut self.depths_ms)?;
+        self.stereo_phase = reader.f32("wow_flutter_stereo_phase")?;
+        self.noise_amount = reader.f32("wow_flutter_noise_amount")?;
+        self.sample_rate = reader.f32("wow_flutter_sample_rate")?;
+        self.rng_state = u32::try_from(reader.u64("wow_flutter_rng_state")?)
+            .map_err(|_| StateError::InvalidValue)?;
+        for source in [Self::WOW, Self::FLUTTER] {
+            reader.f32s_into("wow_flutter_phases", &mut self.phases[source])?;
+            reader.f32s_into("wow_flutter_prev_noise", &mut self.prev_noise[source])?;
+            reader.f32s_into("wow_flutter_next_noise", &mut self.next_noise[source])?;
+        }
+
+        Ok(())
+    }
+
+    /// Bipolar value of one source, the drift follows a raised cosine from
+    /// the previous to the next random value within one cycle.
+    fn value(&self, source: usize, ch: usize) -> f32 {
+        let phase = self.phases[source][ch];
+        let sine = math::sinf(TAU * phase);
+        if self.noise_amount <= 0. {
+            return sine;
+        }
+
+        let prev = self.prev_noise[source][ch];
+        let next = self.next_noise[source][ch];
+        let noise = prev + (next - prev) * 0.5 * (1. - math::cosf(PI * phase));
+        sine + (noise - sine) * self.noise_amount
+    }
+
+    fn advance(&mut self, source: usize) {
+        let increment = self.rates_hz[source] / self.sample_rate;
+        for ch in 0..NUM_STEREO_CHANNELS {
+            let phase = self.phases[source][ch] + increment;
+            if phase >= 1. {
+                self.prev_noise[source][ch] = self.next_noise[source][ch];
+                self.next_noise[source][ch] = self.next_bipolar();
+            }
+            self.phases[source][ch] = Self::wrap(phase);
+        }
+    }
+
+    fn wrap(phase: f32) -> f32 {
+        phase - math::floorf(phase)
+    }
+
+    fn next_bipolar(&mut self) -> f32 {
+        const MANTISSA_BITS: u32 = 24;
+        const SCALE: f32 = 1. / (1_u32 << MANTISSA_BITS) as f32;
+
+        // xorshift32
+        let mut x = self.rng_state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rng_state = x;
+
+        ((x >> (32 - MANTISSA_BITS)) as f32 * SCALE) * 2. - 1.
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wow_flutter_offsets() {
+        const SAMPLE_RATE: f32 = 1000.;
+        const WOW_DEPTH_MS: f32 = 4.;
+        const FLUTTER_DEPTH_MS: f32 = 1.;
+        // Both depths at the sample rate above.
+        const MAX_OFFSET: f32 = 5.;
+
+        let mut wow_flutter = WowFlutter::new();
+        wow_flutter.set_sample_rate(SAMPLE_RATE);
+        assert_eq!(wow_flutter.process(), [0., 0.]);
+
+        wow_flutter.set_wow_depth_ms(WOW_DEPTH_MS);
+        wow_flutter.set_flutter_depth_ms(FLUTTER_DEPTH_MS);
+        wow_flutter.set_stereo_phase(0.5);
+        wow_flutter.set_noise_amount(0.5);
+
+        let mut max_offset: f32 = 0.;
+        let mut max_stereo_diff: f32 = 0.;
+        for _ in 0..4000 {
+            let [left, right] = wow_flutter.process();
+            for offset in [left, right] {
+                assert!((0.0..=MAX_OFFSET).contains(&offset));
+                max_offset = max_offset.max(offset);
+            }
+            max_stereo_diff = max_stereo_diff.max((left - right).abs());
+        }
+
+        assert!(max_offset > 0.5 * MAX_OFFSET);
+        assert!(max_stereo_diff > 1.);
+    }
+}
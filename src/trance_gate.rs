@@ -1,70 +1,221 @@
 // Copyright(c) 2021 Hansen Audio.
 
-use dsp_tool_box_rs::filtering;
-use dsp_tool_box_rs::modulation;
-
+mod contour;
+mod declick;
+mod fade_curve;
+mod humanize;
+mod pattern_chain;
 mod shuffle_note;
 mod step;
 
-use crate::{AudioFrame, DEFAULT_SAMPLE_RATE, DEFAULT_TEMPO_BPM, NUM_STEREO_CHANNELS};
-
-const MAX_NUM_STEPS: usize = 32;
-type StepVals = [f32; MAX_NUM_STEPS];
+pub use declick::EdgeMode;
+pub use fade_curve::FadeCurve;
+pub use pattern_chain::ChainLengthUnit;
+
+use crate::math;
+use crate::phase::{Phase, SyncMode};
+use crate::state::{impl_state_enum, StateError, StateReader, StateWriter};
+use crate::{
+    AudioFrame, Sample, DEFAULT_SAMPLE_RATE, DEFAULT_TEMPO_BPM, NUM_CHANNELS, NUM_STEREO_CHANNELS,
+};
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+const DEFAULT_MAX_NUM_STEPS: usize = 32;
+const MAX_NUM_PATTERNS: usize = 8;
+type StepVals = Vec<f32>;
 type ChannelStepsList = [StepVals; NUM_STEREO_CHANNELS];
 
+/// Defines how the right channel derives its gate pattern.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StereoMode {
+    /// Both channels use the left pattern.
+    Mono,
+    /// Left and right channel use their own pattern.
+    Stereo,
+    /// Right channel uses the inverted left pattern.
+    Mirrored,
+    /// Right channel uses the left pattern shifted by the stereo offset.
+    Offset,
+    /// Right channel plays the left pattern backwards.
+    Reverse,
+    /// The left pattern gates the side signal only.
+    MidSide,
+}
+
+impl_state_enum!(
+    StereoMode,
+    [
+        StereoMode::Mono,
+        StereoMode::Stereo,
+        StereoMode::Mirrored,
+        StereoMode::Offset,
+        StereoMode::Reverse,
+        StereoMode::MidSide,
+    ]
+);
+
+/// Defines how the length of a step is specified.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepTiming {
+    /// Step length is a note length, synced to the host project time.
+    NoteLength,
+    /// Step length in milliseconds, free running.
+    Milliseconds,
+    /// Step rate in Hz, free running.
+    Hertz,
+}
+
+impl_state_enum!(
+    StepTiming,
+    [
+        StepTiming::NoteLength,
+        StepTiming::Milliseconds,
+        StepTiming::Hertz,
+    ]
+);
+
+/// Defines what the trance gate fades to on release.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReleaseTarget {
+    /// Fades the mix back to the dry signal.
+    Dry,
+    /// Fades the output to silence.
+    Silence,
+}
+
+impl_state_enum!(ReleaseTarget, [ReleaseTarget::Dry, ReleaseTarget::Silence]);
+
 #[derive(Debug, Clone)]
-pub struct TranceGate {
-    channel_steps_list: ChannelStepsList,
-    contour_filter: filtering::one_pole_simple::OnePoleSimpleMulti,
-    delay_phase: modulation::phase::Phase,
-    fade_in_phase: modulation::phase::Phase,
-    step_phase: modulation::phase::Phase,
+pub struct TranceGate<T: Sample = f32> {
+    patterns: Vec<ChannelStepsList>,
+    pattern_chain: pattern_chain::PatternChain,
+    humanize: humanize::Humanize,
+    contour_filter: contour::Contour<T>,
+    declick: declick::Declick<T>,
+    band_limited_edge: declick::BandLimitedEdge<T>,
+    delay_phase: Phase,
+    fade_in_phase: Phase,
+    fade_out_phase: Phase,
+    step_phase: Phase,
     delay_phase_val: f32,
     step_phase_val: f32,
     fade_in_phase_val: f32,
+    fade_out_phase_val: f32,
     step_val: step::Step,
+    abs_step: usize,
+    step_len: f32,
+    step_time_ms: f32,
+    step_rate_hz: f32,
+    step_timing: StepTiming,
+    delay_len: f32,
+    fade_in_len: f32,
+    fade_out_len: f32,
+    project_time_music: f64,
     mix: f32,
     width: f32,
     shuffle: f32,
-    contour: f32,
+    contour_attack: f32,
+    contour_release: f32,
+    declick_ms: f32,
+    edge_mode: EdgeMode,
+    tempo_bpm: f32,
     sample_rate: f32,
-    ch: usize,
+    stereo_mode: StereoMode,
+    stereo_offset: usize,
+    fade_curve: FadeCurve,
+    release_target: ReleaseTarget,
     is_delay_active: bool,
     is_fade_in_active: bool,
+    is_release_active: bool,
 }
 
 impl TranceGate {
+    pub fn new() -> Self {
+        Self::with_sample_type()
+    }
+
+    /// Creates a trance gate which can hold patterns of up to `max_num_steps`
+    /// steps. All step memory is allocated here, never on the audio thread.
+    pub fn with_max_steps(max_num_steps: usize) -> Self {
+        Self::with_sample_type_and_max_steps(max_num_steps)
+    }
+}
+
+impl Default for TranceGate {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Sample> TranceGate<T> {
     const LC: usize = 0;
     const RC: usize = 1;
     const MIN_NUM_STEPS: usize = 1;
     const ONE_SAMPLE: usize = 1;
+    const STATE_TAG: &'static [u8; 4] = b"TGAT";
 
-    pub fn new() -> Self {
-        use filtering::one_pole_simple::OnePoleSimpleMulti;
-        use modulation::phase::Phase;
-        use modulation::phase::SyncMode;
+    /// Creates a trance gate for the sample type `T`, for instance
+    /// `TranceGate::<f64>::with_sample_type()`.
+    pub fn with_sample_type() -> Self {
+        Self::with_sample_type_and_max_steps(DEFAULT_MAX_NUM_STEPS)
+    }
+
+    /// Like `with_max_steps`, for the sample type `T`.
+    pub fn with_sample_type_and_max_steps(max_num_steps: usize) -> Self {
+        let max_num_steps = max_num_steps.max(Self::MIN_NUM_STEPS);
+        let step_count = DEFAULT_MAX_NUM_STEPS.min(max_num_steps);
 
         let mut trance_gate = Self {
-            channel_steps_list: [[0.; MAX_NUM_STEPS]; NUM_STEREO_CHANNELS],
-            contour_filter: OnePoleSimpleMulti::new(0.),
-            delay_phase: modulation::phase::Phase::new(),
-            fade_in_phase: modulation::phase::Phase::new(),
-            step_phase: modulation::phase::Phase::new(),
+            patterns: vec![core::array::from_fn(|_| vec![0.; max_num_steps]); MAX_NUM_PATTERNS],
+            pattern_chain: pattern_chain::PatternChain::new(),
+            humanize: humanize::Humanize::new(),
+            contour_filter: contour::Contour::new(),
+            declick: declick::Declick::new(),
+            band_limited_edge: declick::BandLimitedEdge::new(),
+            delay_phase: Phase::new(),
+            fade_in_phase: Phase::new(),
+            fade_out_phase: Phase::new(),
+            step_phase: Phase::new(),
             delay_phase_val: 0.,
             fade_in_phase_val: 0.,
+            fade_out_phase_val: 0.,
             step_phase_val: 0.,
-            step_val: step::Step::new(0, 32, false),
+            step_val: step::Step::new(0, step_count, false),
+            abs_step: 0,
+            step_len: DEFAULT_NOTE_LEN,
+            step_time_ms: DEFAULT_STEP_TIME_MS,
+            step_rate_hz: 1000. / DEFAULT_STEP_TIME_MS,
+            step_timing: StepTiming::NoteLength,
+            delay_len: DEFAULT_NOTE_LEN,
+            fade_in_len: DEFAULT_NOTE_LEN,
+            fade_out_len: DEFAULT_NOTE_LEN,
+            project_time_music: 0.,
             mix: 0.5,
             width: 0.,
             shuffle: 0.,
-            contour: 0.01,
+            contour_attack: 0.01,
+            contour_release: 0.01,
+            declick_ms: 0.,
+            edge_mode: EdgeMode::Hard,
+            tempo_bpm: DEFAULT_TEMPO_BPM,
             sample_rate: DEFAULT_SAMPLE_RATE,
-            ch: 0,
+            stereo_mode: StereoMode::Mono,
+            stereo_offset: 0,
+            fade_curve: FadeCurve::Linear,
+            release_target: ReleaseTarget::Dry,
             is_delay_active: false,
             is_fade_in_active: false,
+            is_release_active: false,
         };
 
         const DEFAULT_NOTE_LEN: f32 = 1. / 32.;
+        // Matches a 1/32 note at the default tempo.
+        const DEFAULT_STEP_TIME_MS: f32 = 62.5;
 
         trance_gate
             .delay_phase
@@ -78,6 +229,13 @@ impl TranceGate {
             .fade_in_phase
             .set_sync_mode(SyncMode::ProjectSync);
 
+        trance_gate
+            .fade_out_phase
+            .set_rate(Phase::note_len_to_rate(DEFAULT_NOTE_LEN));
+        trance_gate
+            .fade_out_phase
+            .set_sync_mode(SyncMode::ProjectSync);
+
         trance_gate
             .step_phase
             .set_rate(Phase::note_len_to_rate(DEFAULT_NOTE_LEN));
@@ -89,8 +247,10 @@ impl TranceGate {
     }
 
     pub fn set_tempo(&mut self, tempo_bpm: f32) {
+        self.tempo_bpm = tempo_bpm;
         self.delay_phase.set_tempo(tempo_bpm);
         self.fade_in_phase.set_tempo(tempo_bpm);
+        self.fade_out_phase.set_tempo(tempo_bpm);
         self.step_phase.set_tempo(tempo_bpm);
     }
 
@@ -100,21 +260,44 @@ impl TranceGate {
 
         self.delay_phase_val = 0.;
         self.fade_in_phase_val = 0.;
+        self.fade_out_phase_val = 0.;
+        self.is_release_active = false;
         self.step_phase_val = 0.;
         self.step_val.set_pos(0);
+        self.abs_step = 0;
+        self.pattern_chain.reset();
+        self.humanize.set_step(self.abs_step);
+        self.update_filter_poles();
 
         if self.is_delay_active {
             self.reset();
         }
     }
 
+    /// Starts fading out the gate over `fade_out_len`, either back to the dry
+    /// signal or to silence, see `set_release_target`. A length of zero
+    /// releases instantly.
+    pub fn release(&mut self, fade_out_len: f32) {
+        self.is_release_active = true;
+        self.fade_out_phase_val = 0.;
+
+        if fade_out_len > 0. {
+            self.fade_out_len = fade_out_len;
+            self.fade_out_phase.set_note_len(fade_out_len);
+        } else {
+            self.fade_out_phase_val = 1.;
+        }
+    }
+
     pub fn reset(&mut self) {
         let reset_val = match self.is_delay_active {
-            true => 1.,
-            false => 0.,
+            true => T::ONE,
+            false => T::ZERO,
         };
 
         self.contour_filter.reset(reset_val);
+        self.declick.reset(reset_val);
+        self.band_limited_edge.reset(reset_val);
     }
 
     pub fn reset_step_pos(&mut self, step_pos: usize) {
@@ -125,27 +308,64 @@ impl TranceGate {
         self.step_val.pos()
     }
 
-    pub fn process(&mut self, inputs: &AudioFrame, outputs: &mut AudioFrame) {
+    pub fn max_num_steps(&self) -> usize {
+        self.patterns[0][Self::LC].len()
+    }
+
+    pub fn pattern_index(&self) -> usize {
+        self.pattern_chain.pattern().unwrap_or(0)
+    }
+
+    /// Processes one frame. The phases and step values stay `f32`, the gain
+    /// is smoothed, mixed and applied in the sample type `T`.
+    pub fn process(&mut self, inputs: &AudioFrame<T>, outputs: &mut AudioFrame<T>) {
         if self.is_delay_running() {
+            // The gate only opens after the delay, but a release already
+            // fades out during it.
             outputs.copy_from_slice(inputs);
+            self.apply_release_target(outputs);
+            self.advance_release();
             return;
         }
 
-        let pos = self.step_val.pos();
-        let mut left = self.channel_steps_list[Self::LC][pos];
-        let mut right = self.channel_steps_list[self.ch][pos];
+        let (pos, variation) = self.humanized_step();
+        let (left, right) = self.read_steps(pos);
+        let mut left = T::from_f32(self.humanize.apply_level(&variation, left));
+        let mut right = T::from_f32(self.humanize.apply_level(&variation, right));
 
         self.apply_effect(&mut left, &mut right);
 
-        outputs[Self::LC] = inputs[Self::LC] * left;
-        outputs[Self::RC] = inputs[Self::RC] * right;
+        match self.stereo_mode {
+            StereoMode::MidSide => Self::apply_gate_mid_side(inputs, outputs, left),
+            _ => {
+                outputs[Self::LC] = inputs[Self::LC] * left;
+                outputs[Self::RC] = inputs[Self::RC] * right;
+            }
+        }
 
+        self.apply_release_target(outputs);
         self.update_phases()
     }
 
+    fn apply_release_target(&self, outputs: &mut AudioFrame<T>) {
+        if self.release_target == ReleaseTarget::Silence {
+            let gain = T::from_f32(self.compute_release_gain());
+            outputs[Self::LC] *= gain;
+            outputs[Self::RC] *= gain;
+        }
+    }
+
+    fn advance_release(&mut self) {
+        if self.is_release_active {
+            self.fade_out_phase
+                .advance_one_shot(&mut self.fade_out_phase_val, Self::ONE_SAMPLE);
+        }
+    }
+
     fn update_phases(&mut self) {
         self.fade_in_phase
             .advance_one_shot(&mut self.fade_in_phase_val, Self::ONE_SAMPLE);
+        self.advance_release();
 
         let is_overflow = self
             .step_phase
@@ -155,7 +375,14 @@ impl TranceGate {
             return;
         }
         self.step_val.advance();
-        Self::set_shuffle(&mut self.step_val, self.step_phase.note_len());
+        self.advance_pattern_chain();
+        let note_len = match self.step_timing {
+            StepTiming::NoteLength => self.step_phase.note_len(),
+            _ => self.step_len,
+        };
+        Self::set_shuffle(&mut self.step_val, note_len);
+
+        self.update_humanize_step();
     }
 
     pub fn set_sample_rate(&mut self, sample_rate: f32) {
@@ -163,13 +390,50 @@ impl TranceGate {
 
         self.delay_phase.set_sample_rate(sample_rate);
         self.fade_in_phase.set_sample_rate(sample_rate);
+        self.fade_out_phase.set_sample_rate(sample_rate);
         self.step_phase.set_sample_rate(sample_rate);
 
         self.update_filter_poles();
     }
 
+    /// Sets a step of pattern 0. Steps from `max_num_steps` on, the size
+    /// chosen by `with_max_steps`, are ignored.
     pub fn set_step(&mut self, channel: usize, step: usize, value_normalized: f32) {
-        self.channel_steps_list[channel][step] = value_normalized;
+        self.set_pattern_step(0, channel, step, value_normalized);
+    }
+
+    /// Sets a step of one of the `MAX_NUM_PATTERNS` patterns. Out of range
+    /// patterns and channels as well as steps from `max_num_steps` on are
+    /// ignored.
+    pub fn set_pattern_step(
+        &mut self,
+        pattern: usize,
+        channel: usize,
+        step: usize,
+        value_normalized: f32,
+    ) {
+        let is_in_range = pattern < MAX_NUM_PATTERNS
+            && channel < NUM_STEREO_CHANNELS
+            && step < self.max_num_steps();
+        if is_in_range {
+            self.patterns[pattern][channel][step] = value_normalized;
+        }
+    }
+
+    /// Appends `pattern` to the pattern chain. It plays for `length` repeats
+    /// or bars, depending on the chain length unit. As long as the chain is
+    /// empty, pattern 0 plays all the time.
+    pub fn add_pattern_chain_entry(&mut self, pattern: usize, length: usize) {
+        self.pattern_chain
+            .push(pattern.min(MAX_NUM_PATTERNS - 1), length);
+    }
+
+    pub fn clear_pattern_chain(&mut self) {
+        self.pattern_chain.clear();
+    }
+
+    pub fn set_pattern_chain_unit(&mut self, unit: ChainLengthUnit) {
+        self.pattern_chain.set_unit(unit);
     }
 
     pub fn set_width(&mut self, width: f32) {
@@ -180,43 +444,113 @@ impl TranceGate {
         self.shuffle = shuffle;
     }
 
+    /// Switches between `StereoMode::Stereo` and `StereoMode::Mono`, see
+    /// `set_stereo_mode_ex` for the other modes.
     pub fn set_stereo_mode(&mut self, mode: bool) {
-        self.ch = match mode {
-            true => Self::RC,
-            false => Self::LC,
-        }
+        self.set_stereo_mode_ex(match mode {
+            true => StereoMode::Stereo,
+            false => StereoMode::Mono,
+        });
+    }
+
+    pub fn set_stereo_mode_ex(&mut self, mode: StereoMode) {
+        self.stereo_mode = mode;
+    }
+
+    pub fn set_stereo_offset(&mut self, offset_steps: usize) {
+        self.stereo_offset = offset_steps;
     }
 
     pub fn set_step_len(&mut self, step_len: f32) {
-        self.step_phase.set_note_len(step_len);
+        self.step_len = step_len;
+        self.update_step_timing();
+    }
+
+    /// Selects whether steps follow the host tempo or run freely at a time
+    /// in milliseconds or a rate in Hz. In the free running modes the delay
+    /// and the fades still use note lengths, but only follow the tempo set by
+    /// `set_tempo`, not the host transport.
+    pub fn set_step_timing(&mut self, step_timing: StepTiming) {
+        if self.step_timing == step_timing {
+            return;
+        }
+
+        self.step_timing = step_timing;
+        self.update_sync_modes();
+        self.update_step_timing();
+    }
+
+    pub fn set_step_time_ms(&mut self, step_time_ms: f32) {
+        self.step_time_ms = step_time_ms;
+        self.update_step_timing();
+    }
+
+    pub fn set_step_rate_hz(&mut self, step_rate_hz: f32) {
+        self.step_rate_hz = step_rate_hz;
+        self.update_step_timing();
     }
 
     pub fn update_project_time_music(&mut self, project_time_music: f64) {
-        self.delay_phase.set_project_time(project_time_music);
-        self.fade_in_phase.set_project_time(project_time_music);
-        self.step_phase.set_project_time(project_time_music);
+        self.project_time_music = project_time_music;
+        self.update_project_time();
+
+        self.locate_abs_step(project_time_music);
     }
 
     pub fn set_step_count(&mut self, step_count: usize) {
         self.step_val
-            .set_count(step_count.clamp(Self::MIN_NUM_STEPS, MAX_NUM_STEPS));
+            .set_count(step_count.clamp(Self::MIN_NUM_STEPS, self.max_num_steps()));
     }
 
+    /// Sets attack and release time of the contour in seconds.
     pub fn set_contour(&mut self, contour: f32) {
-        if self.contour == contour {
+        if self.contour_attack == contour && self.contour_release == contour {
+            return;
+        }
+
+        self.contour_attack = contour;
+        self.contour_release = contour;
+        self.update_filter_poles();
+    }
+
+    /// Sets the contour time in seconds for rising gain.
+    pub fn set_contour_attack(&mut self, attack: f32) {
+        if self.contour_attack == attack {
+            return;
+        }
+
+        self.contour_attack = attack;
+        self.update_filter_poles();
+    }
+
+    /// Sets the contour time in seconds for falling gain.
+    pub fn set_contour_release(&mut self, release: f32) {
+        if self.contour_release == release {
             return;
         }
 
-        self.contour = contour;
+        self.contour_release = release;
         self.update_filter_poles();
     }
 
+    /// Sets the minimum time in milliseconds a full gain step takes, applied
+    /// after the contour. Zero disables the ramp.
+    pub fn set_declick(&mut self, declick_ms: f32) {
+        self.declick_ms = declick_ms;
+        self.declick.set_ramp(self.declick_ms, self.sample_rate);
+    }
+
+    pub fn set_edge_mode(&mut self, edge_mode: EdgeMode) {
+        self.edge_mode = edge_mode;
+    }
+
     pub fn set_fade_in(&mut self, fade_in: f32) {
         self.is_fade_in_active = fade_in > 0.;
         if !self.is_fade_in_active {
             return;
         }
 
+        self.fade_in_len = fade_in;
         self.fade_in_phase.set_note_len(fade_in);
     }
 
@@ -226,6 +560,7 @@ impl TranceGate {
             return;
         }
 
+        self.delay_len = delay;
         self.delay_phase.set_note_len(delay);
     }
 
@@ -233,9 +568,278 @@ impl TranceGate {
         self.mix = mix;
     }
 
+    pub fn set_fade_curve(&mut self, fade_curve: FadeCurve) {
+        self.fade_curve = fade_curve;
+    }
+
+    pub fn set_release_target(&mut self, release_target: ReleaseTarget) {
+        self.release_target = release_target;
+    }
+
+    /// Sets the seed of the humanize section. The variation of a step only
+    /// depends on the seed and the step position since `trigger` or, with
+    /// note length steps, in the host project. So bounces are reproducible.
+    pub fn set_humanize_seed(&mut self, seed: u32) {
+        self.humanize.set_seed(seed);
+    }
+
+    /// Sets the amount of random level variation per step, 0 to 1.
+    pub fn set_humanize_level(&mut self, amount: f32) {
+        self.humanize.set_level_amount(amount);
+    }
+
+    /// Sets the maximum random timing offset per step in milliseconds.
+    pub fn set_humanize_timing(&mut self, timing_ms: f32) {
+        self.humanize.set_timing_ms(timing_ms);
+    }
+
+    /// Sets the amount of random contour variation per step, 0 to 1.
+    pub fn set_humanize_contour(&mut self, amount: f32) {
+        self.humanize.set_contour_amount(amount);
+        self.update_filter_poles();
+    }
+
+    /// Saves the complete runtime state in the versioned binary format.
+    pub fn save_state_binary(&self) -> Vec<u8> {
+        self.write_state().to_binary(Self::STATE_TAG)
+    }
+
+    /// Saves the complete runtime state in the versioned text format.
+    pub fn save_state_text(&self) -> String {
+        self.write_state().to_text(Self::STATE_TAG)
+    }
+
+    /// Restores a state saved by `save_state_binary`. Processing continues
+    /// bit-exactly where the saved instance left off. On error the gate
+    /// stays unchanged.
+    pub fn restore_state_binary(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut reader = StateReader::from_binary(data, Self::STATE_TAG)?;
+        self.restore_state(&mut reader)
+    }
+
+    /// Restores a state saved by `save_state_text`.
+    pub fn restore_state_text(&mut self, text: &str) -> Result<(), StateError> {
+        let mut reader = StateReader::from_text(text, Self::STATE_TAG)?;
+        self.restore_state(&mut reader)
+    }
+
     // private
+    fn write_state(&self) -> StateWriter {
+        let mut writer = StateWriter::new();
+
+        writer.usize("max_num_steps", self.max_num_steps());
+        for pattern in &self.patterns {
+            for steps in pattern {
+                writer.f32s("pattern", steps);
+            }
+        }
+
+        writer.f32("step_len", self.step_len);
+        writer.f32("step_time_ms", self.step_time_ms);
+        writer.f32("step_rate_hz", self.step_rate_hz);
+        writer.enumeration("step_timing", self.step_timing);
+        writer.f32("delay_len", self.delay_len);
+        writer.f32("fade_in_len", self.fade_in_len);
+        writer.f32("fade_out_len", self.fade_out_len);
+        writer.f64("project_time_music", self.project_time_music);
+        writer.f32("mix", self.mix);
+        writer.f32("width", self.width);
+        writer.f32("shuffle", self.shuffle);
+        writer.f32("contour_attack", self.contour_attack);
+        writer.f32("contour_release", self.contour_release);
+        writer.f32("declick_ms", self.declick_ms);
+        writer.enumeration("edge_mode", self.edge_mode);
+        writer.f32("tempo_bpm", self.tempo_bpm);
+        writer.f32("sample_rate", self.sample_rate);
+        writer.enumeration("stereo_mode", self.stereo_mode);
+        writer.usize("stereo_offset", self.stereo_offset);
+        writer.enumeration("fade_curve", self.fade_curve);
+        writer.enumeration("release_target", self.release_target);
+        for phase in [
+            &self.delay_phase,
+            &self.fade_in_phase,
+            &self.fade_out_phase,
+            &self.step_phase,
+        ] {
+            phase.write_state(&mut writer);
+        }
+
+        self.pattern_chain.write_state(&mut writer);
+        self.humanize.write_state(&mut writer);
+        self.contour_filter.write_state(&mut writer);
+        self.declick.write_state(&mut writer);
+        self.band_limited_edge.write_state(&mut writer);
+        self.step_val.write_state(&mut writer);
+
+        writer.usize("abs_step", self.abs_step);
+        writer.f32("delay_phase_val", self.delay_phase_val);
+        writer.f32("step_phase_val", self.step_phase_val);
+        writer.f32("fade_in_phase_val", self.fade_in_phase_val);
+        writer.f32("fade_out_phase_val", self.fade_out_phase_val);
+        writer.bool("is_delay_active", self.is_delay_active);
+        writer.bool("is_fade_in_active", self.is_fade_in_active);
+        writer.bool("is_release_active", self.is_release_active);
+
+        writer
+    }
+
+    fn restore_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        let mut restored = self.clone();
+        restored.read_state(reader)?;
+        *self = restored;
+        Ok(())
+    }
+
+    fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        let max_num_steps = reader.usize("max_num_steps")?;
+        if max_num_steps < Self::MIN_NUM_STEPS {
+            return Err(StateError::InvalidValue);
+        }
+
+        // The patterns keep their memory, it only grows for a state with more
+        // steps.
+        for pattern in self.patterns.iter_mut() {
+            for steps in pattern.iter_mut() {
+                steps.resize(max_num_steps, 0.);
+                reader.f32s_into("pattern", steps)?;
+            }
+        }
+
+        self.step_len = reader.f32("step_len")?;
+        self.step_time_ms = reader.f32("step_time_ms")?;
+        self.step_rate_hz = reader.f32("step_rate_hz")?;
+        self.step_timing = reader.enumeration("step_timing")?;
+        self.delay_len = reader.f32("delay_len")?;
+        self.fade_in_len = reader.f32("fade_in_len")?;
+        self.fade_out_len = reader.f32("fade_out_len")?;
+        self.project_time_music = reader.f64("project_time_music")?;
+        self.mix = reader.f32("mix")?;
+        self.width = reader.f32("width")?;
+        self.shuffle = reader.f32("shuffle")?;
+        self.contour_attack = reader.f32("contour_attack")?;
+        self.contour_release = reader.f32("contour_release")?;
+        self.declick_ms = reader.f32("declick_ms")?;
+        self.edge_mode = reader.enumeration("edge_mode")?;
+        self.tempo_bpm = reader.f32("tempo_bpm")?;
+        self.sample_rate = reader.f32("sample_rate")?;
+        self.stereo_mode = reader.enumeration("stereo_mode")?;
+        self.stereo_offset = reader.usize("stereo_offset")?;
+        self.fade_curve = reader.enumeration("fade_curve")?;
+        self.release_target = reader.enumeration("release_target")?;
+
+        for phase in [
+            &mut self.delay_phase,
+            &mut self.fade_in_phase,
+            &mut self.fade_out_phase,
+            &mut self.step_phase,
+        ] {
+            phase.read_state(reader)?;
+        }
+
+        self.pattern_chain.read_state(reader, MAX_NUM_PATTERNS)?;
+        self.humanize.read_state(reader)?;
+        self.contour_filter.read_state(reader)?;
+        self.declick.read_state(reader)?;
+        self.band_limited_edge.read_state(reader)?;
+        self.step_val.read_state(reader)?;
+        if self.step_val.count() > max_num_steps || self.step_val.pos() >= max_num_steps {
+            return Err(StateError::InvalidValue);
+        }
+
+        self.abs_step = reader.usize("abs_step")?;
+        self.delay_phase_val = reader.f32("delay_phase_val")?;
+        self.step_phase_val = reader.f32("step_phase_val")?;
+        self.fade_in_phase_val = reader.f32("fade_in_phase_val")?;
+        self.fade_out_phase_val = reader.f32("fade_out_phase_val")?;
+        self.is_delay_active = reader.bool("is_delay_active")?;
+        self.is_fade_in_active = reader.bool("is_fade_in_active")?;
+        self.is_release_active = reader.bool("is_release_active")?;
+
+        Ok(())
+    }
+
+    fn update_project_time(&mut self) {
+        self.delay_phase.set_project_time(self.project_time_music);
+        self.fade_in_phase.set_project_time(self.project_time_music);
+        self.fade_out_phase
+            .set_project_time(self.project_time_music);
+        self.step_phase.set_project_time(self.project_time_music);
+    }
+
     fn update_filter_poles(&mut self) {
-        self.contour_filter.set_tau(self.contour, self.sample_rate);
+        let factor = match self.humanize.is_contour_active() {
+            true => self.humanize.contour_factor(),
+            false => 1.,
+        };
+
+        self.contour_filter.set_tau(
+            self.contour_attack * factor,
+            self.contour_release * factor,
+            self.sample_rate,
+        );
+        self.declick.set_ramp(self.declick_ms, self.sample_rate);
+    }
+
+    fn update_sync_modes(&mut self) {
+        let (step_sync_mode, sync_mode) = match self.step_timing {
+            StepTiming::NoteLength => (SyncMode::ProjectSync, SyncMode::ProjectSync),
+            _ => (SyncMode::FreeRunning, SyncMode::TempoSync),
+        };
+
+        self.step_phase.set_sync_mode(step_sync_mode);
+        self.delay_phase.set_sync_mode(sync_mode);
+        self.fade_in_phase.set_sync_mode(sync_mode);
+        self.fade_out_phase.set_sync_mode(sync_mode);
+    }
+
+    fn update_step_timing(&mut self) {
+        const MS_PER_SEC: f32 = 1000.;
+        const MIN_VALUE: f32 = 0.001;
+
+        match self.step_timing {
+            StepTiming::NoteLength => self.step_phase.set_note_len(self.step_len),
+            StepTiming::Milliseconds => self
+                .step_phase
+                .set_rate(MS_PER_SEC / self.step_time_ms.max(MIN_VALUE)),
+            StepTiming::Hertz => self.step_phase.set_rate(self.step_rate_hz.max(MIN_VALUE)),
+        }
+    }
+
+    fn step_duration_secs(&self) -> f32 {
+        const QUARTERS_PER_NOTE: f32 = 4.;
+        const SECS_PER_MINUTE: f32 = 60.;
+        const MS_TO_SECS: f32 = 0.001;
+
+        match self.step_timing {
+            StepTiming::NoteLength => {
+                self.step_len * QUARTERS_PER_NOTE * SECS_PER_MINUTE / self.tempo_bpm
+            }
+            StepTiming::Milliseconds => self.step_time_ms * MS_TO_SECS,
+            StepTiming::Hertz => 1. / self.step_rate_hz,
+        }
+    }
+
+    fn humanized_step(&self) -> (usize, humanize::StepVariation) {
+        let pos = self.step_val.pos();
+        let humanize = &self.humanize;
+        if !humanize.is_timing_active() {
+            return (pos, humanize.current());
+        }
+
+        let count = self.step_val.count().max(Self::MIN_NUM_STEPS);
+        let step_duration_secs = self.step_duration_secs();
+
+        let late = humanize.timing_offset(&humanize.current(), step_duration_secs);
+        if self.step_phase_val < late {
+            return ((pos + count - 1) % count, humanize.prev());
+        }
+
+        let early = humanize.timing_offset(&humanize.next(), step_duration_secs);
+        if self.step_phase_val >= 1. + early {
+            return ((pos + 1) % count, humanize.next());
+        }
+
+        (pos, humanize.current())
     }
 
     fn is_delay_running(&mut self) -> bool {
@@ -246,57 +850,227 @@ impl TranceGate {
         !is_overflow && self.is_delay_active
     }
 
-    fn apply_effect(&mut self, left: &mut f32, right: &mut f32) {
+    fn read_steps(&self, pos: usize) -> (f32, f32) {
+        let count = self.step_val.count().max(Self::MIN_NUM_STEPS);
+        let channel_steps_list = &self.patterns[self.pattern_index()];
+        let left_steps = &channel_steps_list[Self::LC];
+        let left = left_steps[pos];
+
+        let right = match self.stereo_mode {
+            StereoMode::Mono | StereoMode::MidSide => left,
+            StereoMode::Stereo => channel_steps_list[Self::RC][pos],
+            StereoMode::Mirrored => 1. - left,
+            StereoMode::Offset => {
+                let offset = self.stereo_offset % count;
+                left_steps[(pos + count - offset) % count]
+            }
+            StereoMode::Reverse => left_steps[(count - 1).saturating_sub(pos)],
+        };
+
+        (left, right)
+    }
+
+    /// Steps per chain length unit. A bar holds a fractional number of
+    /// dotted or triplet steps, so it is not rounded.
+    fn chain_steps_per_unit(&self) -> f64 {
+        match self.pattern_chain.unit() {
+            ChainLengthUnit::Repeats => self.step_val.count() as f64,
+            ChainLengthUnit::Bars => {
+                let steps_per_bar = 1. / self.step_len as f64;
+                steps_per_bar.max(Self::MIN_NUM_STEPS as f64)
+            }
+        }
+    }
+
+    fn advance_pattern_chain(&mut self) {
+        self.abs_step += 1;
+
+        let steps_per_unit = self.chain_steps_per_unit();
+        if self.pattern_chain.advance(self.abs_step, steps_per_unit) {
+            self.step_val.set_pos(0);
+        }
+    }
+
+    fn update_humanize_step(&mut self) {
+        self.humanize.set_step(self.abs_step);
+        if self.humanize.is_contour_active() {
+            self.update_filter_poles();
+        }
+    }
+
+    /// Moves the absolute step, and with it the humanize variations and the
+    /// pattern chain, to the host time.
+    fn locate_abs_step(&mut self, project_time_music: f64) {
+        const QUARTERS_PER_NOTE: f64 = 4.;
+        // Tolerate one step of jitter between host time and the step phase.
+        const MAX_STEP_DEVIATION: usize = 1;
+
+        // Free running steps do not follow the host time.
+        if self.step_timing != StepTiming::NoteLength {
+            return;
+        }
+
+        let step_len_quarters = self.step_len as f64 * QUARTERS_PER_NOTE;
+        let abs_step = math::floor(project_time_music.max(0.) / step_len_quarters) as usize;
+        if abs_step.abs_diff(self.abs_step) <= MAX_STEP_DEVIATION {
+            return;
+        }
+
+        self.abs_step = abs_step;
+        self.update_humanize_step();
+        if !self.pattern_chain.is_active() {
+            return;
+        }
+
+        let steps_per_unit = self.chain_steps_per_unit();
+        let entry_step = self.pattern_chain.locate(abs_step, steps_per_unit);
+        self.step_val
+            .set_pos(entry_step % self.step_val.count().max(1));
+    }
+
+    fn apply_gate_mid_side(inputs: &AudioFrame<T>, outputs: &mut AudioFrame<T>, gate: T) {
+        const HALF: f32 = 0.5;
+        let half = T::from_f32(HALF);
+        let mid = (inputs[Self::LC] + inputs[Self::RC]) * half;
+        let side = (inputs[Self::LC] - inputs[Self::RC]) * half * gate;
+
+        outputs[Self::LC] = mid + side;
+        outputs[Self::RC] = mid - side;
+    }
+
+    fn apply_effect(&mut self, left: &mut T, right: &mut T) {
         self.apply_shuffle(left, right);
         self.apply_width(left, right);
+        self.apply_edge(left, right);
         self.apply_contour(left, right);
+        self.apply_declick(left, right);
         self.apply_mix_stereo(left, right);
     }
 
-    fn apply_shuffle(&mut self, left: &mut f32, right: &mut f32) {
+    fn apply_shuffle(&mut self, left: &mut T, right: &mut T) {
+        if self.step_val.is_shuffle() {
+            Self::apply_gate_delay(left, right, self.step_phase_val, self.shuffle_delay());
+        }
+    }
+
+    fn shuffle_delay(&self) -> f32 {
         // TODO: Is this a good value for a MAX_DELAY?
         const MAX_DELAY: f32 = 3. / 4.;
-        let delay = self.shuffle * MAX_DELAY;
+        self.shuffle * MAX_DELAY
+    }
 
-        if self.step_val.is_shuffle() {
-            Self::apply_gate_delay(left, right, self.step_phase_val, delay);
+    fn apply_width(&self, left: &mut T, right: &mut T) {
+        // The right channel depends on the widened left one, so it is taken
+        // from a second pass over the swapped result.
+        let values = [*left, *right, T::ZERO, T::ZERO];
+        let width = [T::from_f32(self.width); NUM_CHANNELS];
+        let first = T::max_frame(
+            &values,
+            &T::mul_frame(&T::swap_pairs_frame(&values), &width),
+        );
+        let second = T::max_frame(&values, &T::mul_frame(&T::swap_pairs_frame(&first), &width));
+
+        *left = first[Self::LC];
+        *right = second[Self::RC];
+    }
+
+    fn apply_edge(&mut self, left: &mut T, right: &mut T) {
+        if self.edge_mode == EdgeMode::Hard {
+            return;
         }
+
+        // Samples passed since the latest gain edge.
+        let step_samples = self.step_duration_secs() * self.sample_rate;
+        let frac = (self.step_phase_val - self.edge_phase()) * step_samples;
+
+        let mut values = [*left, *right];
+        self.band_limited_edge.process(&mut values, frac);
+        *left = values[Self::LC];
+        *right = values[Self::RC];
     }
 
-    fn apply_width(&self, left: &mut f32, right: &mut f32) {
-        *left = left.max(*right * self.width);
-        *right = right.max(*left * self.width);
+    /// Step phase of the latest gain edge. Steps start at the wrap, moved by
+    /// the humanize timing, and shuffled steps open after the shuffle delay.
+    fn edge_phase(&self) -> f32 {
+        let phase = self.step_phase_val;
+        let humanize = &self.humanize;
+        let step_start = match humanize.is_timing_active() {
+            true => {
+                let step_duration_secs = self.step_duration_secs();
+                let late = humanize.timing_offset(&humanize.current(), step_duration_secs);
+                let early = humanize.timing_offset(&humanize.next(), step_duration_secs);
+                if phase >= 1. + early {
+                    1. + early
+                } else if phase >= late {
+                    late
+                } else {
+                    humanize.timing_offset(&humanize.prev(), step_duration_secs) - 1.
+                }
+            }
+            false => 0.,
+        };
+
+        let shuffle_delay = self.shuffle_delay();
+        match self.step_val.is_shuffle() && phase > shuffle_delay && shuffle_delay > step_start {
+            true => shuffle_delay,
+            false => step_start,
+        }
     }
 
-    fn apply_contour(&mut self, left: &mut f32, right: &mut f32) {
-        let mut outputs: AudioFrame = [*left, *right, 0., 0.];
-        self.contour_filter.process(&mut outputs);
-        *left = outputs[Self::LC];
-        *right = outputs[Self::RC];
+    fn apply_declick(&mut self, left: &mut T, right: &mut T) {
+        let mut values = [*left, *right];
+        self.declick.process(&mut values);
+        *left = values[Self::LC];
+        *right = values[Self::RC];
+    }
+
+    fn apply_contour(&mut self, left: &mut T, right: &mut T) {
+        let mut values = [*left, *right];
+        self.contour_filter.process(&mut values);
+        *left = values[Self::LC];
+        *right = values[Self::RC];
     }
 
     fn compute_mix(&self) -> f32 {
-        match self.is_fade_in_active {
-            true => self.mix * self.fade_in_phase_val,
+        let mut mix = match self.is_fade_in_active {
+            true => self.mix * self.fade_curve.apply(self.fade_in_phase_val),
             false => self.mix,
+        };
+
+        if self.release_target == ReleaseTarget::Dry {
+            mix *= self.compute_release_gain();
         }
+
+        mix
     }
 
-    fn apply_mix(value: &mut f32, mix: f32) {
-        const MIX_MAX: f32 = 1.;
-        *value = (MIX_MAX - mix) + *value * mix;
+    fn compute_release_gain(&self) -> f32 {
+        match self.is_release_active {
+            true => self.fade_curve.apply(1. - self.fade_out_phase_val),
+            false => 1.,
+        }
     }
 
-    fn apply_mix_stereo(&self, left: &mut f32, right: &mut f32) {
+    fn apply_mix_stereo(&self, left: &mut T, right: &mut T) {
+        const MIX_MAX: f32 = 1.;
+
         let mix = self.compute_mix();
-        Self::apply_mix(left, mix);
-        Self::apply_mix(right, mix);
+        let values = [*left, *right, T::ZERO, T::ZERO];
+        let dry = [T::from_f32(MIX_MAX - mix); NUM_CHANNELS];
+        let mixed = T::add_frame(
+            &dry,
+            &T::mul_frame(&values, &[T::from_f32(mix); NUM_CHANNELS]),
+        );
+
+        let [mixed_left, mixed_right, _, _] = mixed;
+        *left = mixed_left;
+        *right = mixed_right;
     }
 
-    fn apply_gate_delay(left: &mut f32, right: &mut f32, phase_value: f32, delay: f32) {
+    fn apply_gate_delay(left: &mut T, right: &mut T, phase_value: f32, delay: f32) {
         let factor = match phase_value > delay {
-            true => 1.,
-            false => 0.,
+            true => T::ONE,
+            false => T::ZERO,
         };
 
         *left *= factor;
@@ -318,4 +1092,333 @@ mod tests {
         let trance_gate = TranceGate::new();
         println!("{:#?}", trance_gate);
     }
+
+    #[test]
+    fn test_trance_gate_max_num_steps() {
+        let mut trance_gate = TranceGate::new();
+        trance_gate.set_step_count(128);
+        assert_eq!(trance_gate.step_val.count(), DEFAULT_MAX_NUM_STEPS);
+
+        let mut trance_gate = TranceGate::with_max_steps(128);
+        assert_eq!(trance_gate.max_num_steps(), 128);
+        assert_eq!(trance_gate.step_val.count(), DEFAULT_MAX_NUM_STEPS);
+
+        trance_gate.set_step_count(128);
+        trance_gate.set_step(TranceGate::<f32>::RC, 127, 1.);
+        trance_gate.set_pattern_step(MAX_NUM_PATTERNS - 1, TranceGate::<f32>::RC, 127, 1.);
+        assert_eq!(trance_gate.step_val.count(), 128);
+
+        trance_gate.set_step_count(256);
+        assert_eq!(trance_gate.step_val.count(), 128);
+
+        // Steps past the maximum are ignored.
+        let patterns = trance_gate.patterns.clone();
+        trance_gate.set_step(TranceGate::<f32>::LC, 128, 1.);
+        assert_eq!(trance_gate.patterns, patterns);
+
+        // Restoring a state of the same size reuses the pattern memory.
+        let state = trance_gate.save_state_binary();
+        let steps = trance_gate.patterns[0][TranceGate::<f32>::LC].as_ptr();
+        trance_gate
+            .read_state(
+                &mut StateReader::from_binary(&state, TranceGate::<f32>::STATE_TAG).unwrap(),
+            )
+            .unwrap();
+        assert_eq!(
+            trance_gate.patterns[0][TranceGate::<f32>::LC].as_ptr(),
+            steps
+        );
+    }
+
+    #[test]
+    fn test_trance_gate_ignores_out_of_range_steps() {
+        let mut trance_gate = TranceGate::new();
+        let patterns = trance_gate.patterns.clone();
+        let max_num_steps = trance_gate.max_num_steps();
+
+        trance_gate.set_pattern_step(MAX_NUM_PATTERNS, TranceGate::<f32>::LC, 0, 1.);
+        trance_gate.set_pattern_step(0, NUM_STEREO_CHANNELS, 0, 1.);
+        trance_gate.set_pattern_step(0, TranceGate::<f32>::LC, max_num_steps, 1.);
+        assert_eq!(trance_gate.patterns, patterns);
+
+        trance_gate.set_pattern_step(1, TranceGate::<f32>::RC, max_num_steps - 1, 1.);
+        assert_eq!(
+            trance_gate.patterns[1][TranceGate::<f32>::RC][max_num_steps - 1],
+            1.
+        );
+    }
+
+    #[test]
+    fn test_trance_gate_stereo_modes() {
+        let mut trance_gate = TranceGate::new();
+        trance_gate.set_step_count(4);
+        for (step, value) in [1., 0.75, 0.5, 0.25].iter().enumerate() {
+            trance_gate.set_step(TranceGate::<f32>::LC, step, *value);
+        }
+
+        trance_gate.set_stereo_mode_ex(StereoMode::Mono);
+        assert_eq!(trance_gate.read_steps(1), (0.75, 0.75));
+
+        trance_gate.set_stereo_mode(true);
+        assert_eq!(trance_gate.stereo_mode, StereoMode::Stereo);
+        assert_eq!(trance_gate.read_steps(1), (0.75, 0.));
+
+        trance_gate.set_stereo_mode_ex(StereoMode::Mirrored);
+        assert_eq!(trance_gate.read_steps(1), (0.75, 0.25));
+
+        trance_gate.set_stereo_mode_ex(StereoMode::Offset);
+        trance_gate.set_stereo_offset(1);
+        assert_eq!(trance_gate.read_steps(1), (0.75, 1.));
+        assert_eq!(trance_gate.read_steps(0), (1., 0.25));
+
+        trance_gate.set_stereo_mode_ex(StereoMode::Reverse);
+        assert_eq!(trance_gate.read_steps(0), (1., 0.25));
+        assert_eq!(trance_gate.read_steps(3), (0.25, 1.));
+
+        trance_gate.set_stereo_mode(false);
+        assert_eq!(trance_gate.stereo_mode, StereoMode::Mono);
+    }
+
+    #[test]
+    fn test_trance_gate_mid_side() {
+        let inputs: AudioFrame = [1., 0.5, 0., 0.];
+        let mut outputs: AudioFrame = [0.; 4];
+
+        TranceGate::apply_gate_mid_side(&inputs, &mut outputs, 1.);
+        assert_eq!(outputs[..2], inputs[..2]);
+
+        TranceGate::apply_gate_mid_side(&inputs, &mut outputs, 0.);
+        assert_eq!(outputs[..2], [0.75, 0.75]);
+    }
+
+    #[test]
+    fn test_trance_gate_pattern_chain_follows_host_time() {
+        let mut trance_gate = TranceGate::new();
+        trance_gate.set_step_len(1. / 16.);
+        trance_gate.set_step_count(16);
+        trance_gate.set_pattern_step(1, TranceGate::<f32>::LC, 4, 1.);
+        trance_gate.add_pattern_chain_entry(0, 1);
+        trance_gate.add_pattern_chain_entry(1, 1);
+        assert_eq!(trance_gate.pattern_index(), 0);
+
+        // One pattern of 16 sixteenth notes lasts four quarter notes.
+        trance_gate.update_project_time_music(5.);
+        assert_eq!(trance_gate.pattern_index(), 1);
+        assert_eq!(trance_gate.step_pos(), 4);
+        assert_eq!(trance_gate.read_steps(trance_gate.step_pos()), (1., 1.));
+
+        trance_gate.update_project_time_music(8.);
+        assert_eq!(trance_gate.pattern_index(), 0);
+        assert_eq!(trance_gate.step_pos(), 0);
+    }
+
+    #[test]
+    fn test_trance_gate_humanize_timing() {
+        let mut trance_gate = TranceGate::new();
+        trance_gate.set_step_count(4);
+        trance_gate.reset_step_pos(1);
+        assert_eq!(trance_gate.humanized_step().0, 1);
+
+        // A 1/32 step lasts 62.5 ms at 120 bpm, so offsets stay below the
+        // maximum of half a step.
+        trance_gate.set_humanize_timing(20.);
+        trance_gate.set_humanize_seed(2);
+        let current = trance_gate.humanize.current();
+        let next = trance_gate.humanize.next();
+        let late = current.timing * 20. / 62.5;
+        let early = next.timing * 20. / 62.5;
+
+        trance_gate.step_phase_val = 0.5;
+        assert_eq!(trance_gate.humanized_step(), (1, current));
+
+        // Seed 2 starts with a late step followed by an early one.
+        assert!(late > 0. && early < 0.);
+
+        trance_gate.step_phase_val = late - 0.01;
+        assert_eq!(trance_gate.humanized_step().0, 0);
+
+        trance_gate.step_phase_val = 1. + early + 0.01;
+        assert_eq!(trance_gate.humanized_step(), (2, next));
+
+        // The variations follow the host time, a 1/32 step lasts 1/8 quarter.
+        let mut expected = humanize::Humanize::new();
+        expected.set_seed(2);
+        expected.set_step(16);
+        trance_gate.update_project_time_music(2.);
+        assert_eq!(trance_gate.humanize.current(), expected.current());
+    }
+
+    #[test]
+    fn test_trance_gate_edge_phase() {
+        let mut trance_gate = TranceGate::new();
+        trance_gate.step_phase_val = 0.2;
+        assert_eq!(trance_gate.edge_phase(), 0.);
+
+        // A shuffled step opens after the shuffle delay.
+        trance_gate.set_shuffle_amount(0.5);
+        trance_gate.step_val = step::Step::new(1, 4, true);
+        assert_eq!(trance_gate.edge_phase(), 0.);
+        trance_gate.step_phase_val = 0.4;
+        assert_eq!(trance_gate.edge_phase(), 0.375);
+
+        // Humanized steps start late or early. Seed 2 starts with a late step
+        // followed by an early one.
+        trance_gate.set_shuffle_amount(0.);
+        trance_gate.set_humanize_timing(20.);
+        trance_gate.set_humanize_seed(2);
+        let late = trance_gate.humanize.current().timing * 20. / 62.5;
+        let early = trance_gate.humanize.next().timing * 20. / 62.5;
+
+        trance_gate.step_phase_val = late + 0.01;
+        assert_eq!(trance_gate.edge_phase(), late);
+        trance_gate.step_phase_val = 1. + early + 0.01;
+        assert_eq!(trance_gate.edge_phase(), 1. + early);
+    }
+
+    #[test]
+    fn test_trance_gate_release() {
+        let mut trance_gate = TranceGate::new();
+        trance_gate.set_mix(1.);
+        assert_eq!(trance_gate.compute_mix(), 1.);
+
+        trance_gate.release(1. / 4.);
+        trance_gate.fade_out_phase_val = 0.5;
+        trance_gate.set_fade_curve(FadeCurve::SCurve);
+        assert_eq!(trance_gate.compute_mix(), 0.5);
+
+        trance_gate.release(0.);
+        assert_eq!(trance_gate.compute_mix(), 0.);
+
+        trance_gate.set_release_target(ReleaseTarget::Silence);
+        assert_eq!(trance_gate.compute_mix(), 1.);
+        assert_eq!(trance_gate.compute_release_gain(), 0.);
+
+        trance_gate.trigger(0., 0.);
+        assert_eq!(trance_gate.compute_release_gain(), 1.);
+
+        // A release during the trigger delay fades out right away. A 1/4
+        // delay lasts 22050 samples at 120 bpm.
+        let inputs: AudioFrame = [1., 1., 0., 0.];
+        let mut outputs: AudioFrame = [0.; 4];
+        trance_gate.trigger(1. / 4., 0.);
+        trance_gate.release(1. / 32.);
+        for _ in 0..10000 {
+            trance_gate.process(&inputs, &mut outputs);
+        }
+        assert!(trance_gate.is_delay_running());
+        assert_eq!(outputs[..2], [0., 0.]);
+    }
+
+    #[test]
+    fn test_trance_gate_step_timing() {
+        let mut trance_gate = TranceGate::new();
+        assert_eq!(trance_gate.step_duration_secs(), 0.0625);
+
+        trance_gate.set_step_timing(StepTiming::Milliseconds);
+        trance_gate.set_step_time_ms(250.);
+        assert_eq!(trance_gate.step_duration_secs(), 0.25);
+
+        trance_gate.set_step_timing(StepTiming::Hertz);
+        trance_gate.set_step_rate_hz(8.);
+        assert_eq!(trance_gate.step_duration_secs(), 0.125);
+
+        // Host time must not move free running steps.
+        trance_gate.add_pattern_chain_entry(0, 1);
+        trance_gate.add_pattern_chain_entry(1, 1);
+        trance_gate.update_project_time_music(5.);
+        assert_eq!(trance_gate.pattern_index(), 0);
+        assert_eq!(trance_gate.step_pos(), 0);
+    }
+
+    #[test]
+    fn test_trance_gate_state_restore_is_bit_exact() {
+        let process = |trance_gate: &mut TranceGate, num_samples: usize| {
+            let mut outputs = Vec::new();
+            for i in 0..num_samples {
+                let value = ((i * 7919) % 200) as f32 / 100. - 1.;
+                let inputs: AudioFrame = [value, -value, 0., 0.];
+                let mut frame: AudioFrame = [0.; 4];
+                trance_gate.process(&inputs, &mut frame);
+                outputs.push(frame.map(f32::to_bits));
+            }
+            outputs
+        };
+
+        // Free running steps as well as the default note length steps, which
+        // follow the project time.
+        for step_timing in [StepTiming::Milliseconds, StepTiming::NoteLength] {
+            let mut trance_gate = TranceGate::with_max_steps(64);
+            trance_gate.set_step_timing(step_timing);
+            trance_gate.set_step_time_ms(5.);
+            trance_gate.set_step_len(1. / 128.);
+            trance_gate.update_project_time_music(1.3);
+            trance_gate.set_step_count(48);
+            for step in 0..48 {
+                trance_gate.set_step(TranceGate::<f32>::LC, step, (step % 3) as f32 / 2.);
+            }
+            trance_gate.set_stereo_mode_ex(StereoMode::Reverse);
+            trance_gate.set_contour_attack(0.001);
+            trance_gate.set_contour_release(0.004);
+            trance_gate.set_humanize_seed(3);
+            trance_gate.set_humanize_level(0.3);
+            trance_gate.set_humanize_contour(0.5);
+            trance_gate.set_declick(0.5);
+            trance_gate.set_mix(1.);
+
+            process(&mut trance_gate, 1000);
+
+            let mut from_binary = TranceGate::new();
+            from_binary
+                .restore_state_binary(&trance_gate.save_state_binary())
+                .unwrap();
+            let mut from_text = TranceGate::new();
+            from_text
+                .restore_state_text(&trance_gate.save_state_text())
+                .unwrap();
+
+            let expected = process(&mut trance_gate, 2000);
+            assert_eq!(
+                process(&mut from_binary, 2000),
+                expected,
+                "{:?}",
+                step_timing
+            );
+            assert_eq!(process(&mut from_text, 2000), expected, "{:?}", step_timing);
+        }
+
+        let mut unchanged = TranceGate::new();
+        assert!(unchanged.restore_state_binary(b"HAFXTGAT").is_err());
+        assert_eq!(unchanged.max_num_steps(), DEFAULT_MAX_NUM_STEPS);
+    }
+
+    #[test]
+    fn test_trance_gate_process_f64() {
+        let mut single = TranceGate::new();
+        single.set_step(TranceGate::<f32>::LC, 0, 1.);
+        single.set_step_count(2);
+        single.set_contour(0.002);
+        single.set_mix(1.);
+        let mut double = TranceGate::<f64>::with_sample_type();
+        double.set_step(TranceGate::<f64>::LC, 0, 1.);
+        double.set_step_count(2);
+        double.set_contour(0.002);
+        double.set_mix(1.);
+
+        for i in 0..2000 {
+            let value = ((i * 7919) % 200) as f32 / 100. - 1.;
+            let inputs: AudioFrame = [value, -value, 0., 0.];
+            let inputs_f64: AudioFrame<f64> = inputs.map(f64::from);
+
+            let mut outputs: AudioFrame = [0.; 4];
+            let mut outputs_f64: AudioFrame<f64> = [0.; 4];
+            single.process(&inputs, &mut outputs);
+            double.process(&inputs_f64, &mut outputs_f64);
+
+            // The gain is smoothed in the sample type, so both only differ
+            // by the rounding of f32.
+            for (a, b) in outputs.iter().zip(outputs_f64.iter()) {
+                assert!((*a as f64 - *b).abs() < 1e-5);
+            }
+        }
+    }
 }
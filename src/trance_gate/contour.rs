@@ -0,0 +1,94 @@
+// Copyright(c) 2022 Hansen Audio.
+
+use crate::math;
+use crate::state::{StateError, StateReader, StateWriter};
+use crate::{Sample, NUM_STEREO_CHANNELS};
+
+/// One pole smoothing of the gate gain with separate time constants for
+/// rising (attack) and falling (release) gain.
+#[derive(Debug, Clone)]
+pub(super) struct Contour<T: Sample> {
+    attack_pole: f32,
+    release_pole: f32,
+    z: [T; NUM_STEREO_CHANNELS],
+}
+
+impl<T: Sample> Contour<T> {
+    pub fn new() -> Self {
+        Self {
+            attack_pole: 0.,
+            release_pole: 0.,
+            z: [T::ZERO; NUM_STEREO_CHANNELS],
+        }
+    }
+
+    pub fn set_tau(&mut self, attack_secs: f32, release_secs: f32, sample_rate: f32) {
+        self.attack_pole = Self::tau_to_pole(attack_secs, sample_rate);
+        self.release_pole = Self::tau_to_pole(release_secs, sample_rate);
+    }
+
+    pub fn process(&mut self, values: &mut [T; NUM_STEREO_CHANNELS]) {
+        let attack_pole = T::from_f32(self.attack_pole);
+        let release_pole = T::from_f32(self.release_pole);
+        for (value, z) in values.iter_mut().zip(self.z.iter_mut()) {
+            let pole = match *value > *z {
+                true => attack_pole,
+                false => release_pole,
+            };
+
+            *z = *value + pole * (*z - *value);
+            *value = *z;
+        }
+    }
+
+    pub fn reset(&mut self, value: T) {
+        self.z = [value; NUM_STEREO_CHANNELS];
+    }
+
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.f32("contour_attack_pole", self.attack_pole);
+        writer.f32("contour_release_pole", self.release_pole);
+        writer.samples("contour_z", &self.z);
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.attack_pole = reader.f32("contour_attack_pole")?;
+        self.release_pole = reader.f32("contour_release_pole")?;
+        reader.samples_into("contour_z", &mut self.z)
+    }
+
+    fn tau_to_pole(tau_secs: f32, sample_rate: f32) -> f32 {
+        let tau_samples = tau_secs * sample_rate;
+        match tau_samples > 0. {
+            true => math::expf(-1. / tau_samples),
+            false => 0.,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contour_attack_release() {
+        const SAMPLE_RATE: f32 = 1000.;
+        let mut contour = Contour::<f32>::new();
+        contour.set_tau(0., 0.01, SAMPLE_RATE);
+
+        let mut values = [1., 1.];
+        contour.process(&mut values);
+        assert_eq!(values, [1., 1.]);
+
+        values = [0., 1.];
+        contour.process(&mut values);
+        assert_eq!(values[1], 1.);
+        assert!(values[0] > 0.8 && values[0] < 1.);
+
+        contour.set_tau(0.01, 0., SAMPLE_RATE);
+        values = [1., 0.];
+        contour.process(&mut values);
+        assert_eq!(values[1], 0.);
+        assert!(values[0] < 1.);
+    }
+}
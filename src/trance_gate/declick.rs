@@ -0,0 +1,169 @@
+// Copyright(c) 2022 Hansen Audio.
+
+use crate::state::{impl_state_enum, StateError, StateReader, StateWriter};
+use crate::{Sample, NUM_STEREO_CHANNELS};
+
+/// Defines how hard gain steps of the gate are rendered.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeMode {
+    /// Gain steps happen from one sample to the next.
+    Hard,
+    /// Gain steps are smoothed with a polyBLEP residual. Adds one sample of
+    /// latency to the gain signal.
+    BandLimited,
+}
+
+impl_state_enum!(EdgeMode, [EdgeMode::Hard, EdgeMode::BandLimited]);
+
+/// Limits how fast the gain may change, independent of the contour.
+#[derive(Debug, Clone)]
+pub(super) struct Declick<T: Sample> {
+    values: [T; NUM_STEREO_CHANNELS],
+    max_delta: f32,
+}
+
+impl<T: Sample> Declick<T> {
+    pub fn new() -> Self {
+        Self {
+            values: [T::ZERO; NUM_STEREO_CHANNELS],
+            max_delta: f32::INFINITY,
+        }
+    }
+
+    pub fn set_ramp(&mut self, ramp_ms: f32, sample_rate: f32) {
+        const MS_TO_SECS: f32 = 0.001;
+
+        let ramp_samples = ramp_ms * MS_TO_SECS * sample_rate;
+        self.max_delta = match ramp_samples > 0. {
+            true => 1. / ramp_samples,
+            false => f32::INFINITY,
+        };
+    }
+
+    pub fn process(&mut self, values: &mut [T; NUM_STEREO_CHANNELS]) {
+        let is_limited = self.max_delta.is_finite();
+        let max_delta = T::from_f32(self.max_delta);
+        for (value, state) in values.iter_mut().zip(self.values.iter_mut()) {
+            *state = match is_limited {
+                true => *state + (*value - *state).max(-max_delta).min(max_delta),
+                false => *value,
+            };
+            *value = *state;
+        }
+    }
+
+    pub fn reset(&mut self, value: T) {
+        self.values = [value; NUM_STEREO_CHANNELS];
+    }
+
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.samples("declick_values", &self.values);
+        writer.f32("declick_max_delta", self.max_delta);
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.samples_into("declick_values", &mut self.values)?;
+        self.max_delta = reader.f32("declick_max_delta")?;
+        Ok(())
+    }
+}
+
+/// Band-limits the gain steps with a two sample polyBLEP residual.
+#[derive(Debug, Clone)]
+pub(super) struct BandLimitedEdge<T: Sample> {
+    prev_values: [T; NUM_STEREO_CHANNELS],
+    pending: [T; NUM_STEREO_CHANNELS],
+}
+
+impl<T: Sample> BandLimitedEdge<T> {
+    pub fn new() -> Self {
+        Self {
+            prev_values: [T::ZERO; NUM_STEREO_CHANNELS],
+            pending: [T::ZERO; NUM_STEREO_CHANNELS],
+        }
+    }
+
+    /// `frac` is the fraction of a sample which passed between the gain step
+    /// and the current sample.
+    pub fn process(&mut self, values: &mut [T; NUM_STEREO_CHANNELS], frac: f32) {
+        const HALF: f32 = 0.5;
+
+        let frac = frac.clamp(0., 1.);
+        let before = T::from_f32(frac * frac * HALF);
+        let after = T::from_f32((1. - frac) * (1. - frac) * HALF);
+
+        let states = self.prev_values.iter_mut().zip(self.pending.iter_mut());
+        for (value, (prev_value, pending)) in values.iter_mut().zip(states) {
+            let input = *value;
+            let delta = input - *prev_value;
+            *prev_value = input;
+
+            *value = *pending + delta * before;
+            *pending = input - delta * after;
+        }
+    }
+
+    pub fn reset(&mut self, value: T) {
+        self.prev_values = [value; NUM_STEREO_CHANNELS];
+        self.pending = [value; NUM_STEREO_CHANNELS];
+    }
+
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.samples("edge_prev_values", &self.prev_values);
+        writer.samples("edge_pending", &self.pending);
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.samples_into("edge_prev_values", &mut self.prev_values)?;
+        reader.samples_into("edge_pending", &mut self.pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_declick_ramp() {
+        let mut declick = Declick::<f32>::new();
+        declick.set_ramp(1., 4000.);
+
+        let mut values = [1., 0.];
+        declick.process(&mut values);
+        assert_eq!(values, [0.25, 0.]);
+
+        for _ in 0..3 {
+            values = [1., 0.];
+            declick.process(&mut values);
+        }
+        assert_eq!(values, [1., 0.]);
+
+        declick.set_ramp(0., 4000.);
+        values = [0., 1.];
+        declick.process(&mut values);
+        assert_eq!(values, [0., 1.]);
+    }
+
+    #[test]
+    fn test_band_limited_edge() {
+        let mut edge = BandLimitedEdge::<f32>::new();
+
+        let mut values = [0., 0.];
+        edge.process(&mut values, 0.5);
+        assert_eq!(values, [0., 0.]);
+
+        // The step is split symmetrically around the edge.
+        values = [1., 0.];
+        edge.process(&mut values, 0.5);
+        assert_eq!(values, [0.125, 0.]);
+
+        values = [1., 0.];
+        edge.process(&mut values, 0.5);
+        assert_eq!(values, [0.875, 0.]);
+
+        values = [1., 0.];
+        edge.process(&mut values, 0.5);
+        assert_eq!(values, [1., 0.]);
+    }
+}
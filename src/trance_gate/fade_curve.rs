@@ -0,0 +1,56 @@
+// Copyright(c) 2022 Hansen Audio.
+
+use crate::math;
+use crate::state::impl_state_enum;
+
+/// Shape of the fade-in and the fade-out of the trance gate.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FadeCurve {
+    Linear,
+    Exponential,
+    SCurve,
+}
+
+impl_state_enum!(
+    FadeCurve,
+    [FadeCurve::Linear, FadeCurve::Exponential, FadeCurve::SCurve]
+);
+
+impl FadeCurve {
+    /// Maps a linear fade position in [0, 1] onto the curve.
+    pub(super) fn apply(&self, x: f32) -> f32 {
+        // Spans roughly 60 dB from start to end of the fade.
+        const CURVATURE: f32 = 6.9;
+
+        let x = x.clamp(0., 1.);
+        match self {
+            FadeCurve::Linear => x,
+            FadeCurve::Exponential => math::expm1f(CURVATURE * x) / math::expm1f(CURVATURE),
+            FadeCurve::SCurve => x * x * (3. - 2. * x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fade_curves() {
+        for curve in [FadeCurve::Linear, FadeCurve::Exponential, FadeCurve::SCurve] {
+            assert_eq!(curve.apply(0.), 0.);
+            assert_eq!(curve.apply(1.), 1.);
+
+            let mut last = 0.;
+            for i in 1..=100 {
+                let value = curve.apply(i as f32 / 100.);
+                assert!(value >= last);
+                last = value;
+            }
+        }
+
+        assert_eq!(FadeCurve::SCurve.apply(0.5), 0.5);
+        assert!(FadeCurve::Exponential.apply(0.5) < 0.5);
+    }
+}
@@ -0,0 +1,229 @@
+// Copyright(c) 2022 Hansen Audio.
+
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// Random variation of a single step. All values are bipolar in [-1, 1).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(super) struct StepVariation {
+    pub level: f32,
+    pub timing: f32,
+    pub contour: f32,
+}
+
+/// Random step variations. Each step's variation is a hash of the seed and
+/// the absolute step position, so it does not depend on what played before.
+#[derive(Debug, Clone)]
+pub(super) struct Humanize {
+    seed: u32,
+    abs_step: usize,
+    level_amount: f32,
+    timing_ms: f32,
+    contour_amount: f32,
+    prev: StepVariation,
+    current: StepVariation,
+    next: StepVariation,
+}
+
+impl Humanize {
+    const NUM_COMPONENTS: u64 = 3;
+
+    pub fn new() -> Self {
+        let mut humanize = Self {
+            seed: 0,
+            abs_step: 0,
+            level_amount: 0.,
+            timing_ms: 0.,
+            contour_amount: 0.,
+            prev: StepVariation::default(),
+            current: StepVariation::default(),
+            next: StepVariation::default(),
+        };
+
+        humanize.set_step(0);
+        humanize
+    }
+
+    /// Moves to the absolute step position. The first step has no previous
+    /// variation.
+    pub fn set_step(&mut self, abs_step: usize) {
+        self.abs_step = abs_step;
+        self.prev = match abs_step.checked_sub(1) {
+            Some(prev_step) => self.variation(prev_step),
+            None => StepVariation::default(),
+        };
+        self.current = self.variation(abs_step);
+        self.next = self.variation(abs_step.wrapping_add(1));
+    }
+
+    pub fn set_seed(&mut self, seed: u32) {
+        self.seed = seed;
+        self.set_step(self.abs_step);
+    }
+
+    pub fn set_level_amount(&mut self, amount: f32) {
+        self.level_amount = amount.clamp(0., 1.);
+    }
+
+    pub fn set_timing_ms(&mut self, timing_ms: f32) {
+        self.timing_ms = timing_ms.max(0.);
+    }
+
+    pub fn set_contour_amount(&mut self, amount: f32) {
+        self.contour_amount = amount.clamp(0., 1.);
+    }
+
+    pub fn is_timing_active(&self) -> bool {
+        self.timing_ms > 0.
+    }
+
+    pub fn is_contour_active(&self) -> bool {
+        self.contour_amount > 0.
+    }
+
+    pub fn prev(&self) -> StepVariation {
+        self.prev
+    }
+
+    pub fn current(&self) -> StepVariation {
+        self.current
+    }
+
+    pub fn next(&self) -> StepVariation {
+        self.next
+    }
+
+    pub fn apply_level(&self, variation: &StepVariation, value: f32) -> f32 {
+        if self.level_amount <= 0. {
+            return value;
+        }
+
+        let factor = 1. + self.level_amount * variation.level;
+        (value * factor).clamp(0., 1.)
+    }
+
+    /// Returns the timing offset as a fraction of the step length. Positive
+    /// values are late, negative values are early.
+    pub fn timing_offset(&self, variation: &StepVariation, step_duration_secs: f32) -> f32 {
+        // Never move a step further than half way into its neighbour.
+        const MAX_OFFSET: f32 = 0.5;
+        const MS_TO_SECS: f32 = 0.001;
+
+        if step_duration_secs <= 0. {
+            return 0.;
+        }
+
+        let offset = variation.timing * self.timing_ms * MS_TO_SECS / step_duration_secs;
+        offset.clamp(-MAX_OFFSET, MAX_OFFSET)
+    }
+
+    pub fn contour_factor(&self) -> f32 {
+        1. + self.contour_amount * self.current.contour
+    }
+
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.u64("humanize_seed", self.seed as u64);
+        writer.usize("humanize_abs_step", self.abs_step);
+        writer.f32("humanize_level_amount", self.level_amount);
+        writer.f32("humanize_timing_ms", self.timing_ms);
+        writer.f32("humanize_contour_amount", self.contour_amount);
+        for variation in [self.prev, self.current, self.next] {
+            writer.f32s(
+                "humanize_variation",
+                &[variation.level, variation.timing, variation.contour],
+            );
+        }
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        let read_u32 = |reader: &mut StateReader, key| {
+            u32::try_from(reader.u64(key)?).map_err(|_| StateError::InvalidValue)
+        };
+
+        self.seed = read_u32(reader, "humanize_seed")?;
+        self.abs_step = reader.usize("humanize_abs_step")?;
+        self.level_amount = reader.f32("humanize_level_amount")?;
+        self.timing_ms = reader.f32("humanize_timing_ms")?;
+        self.contour_amount = reader.f32("humanize_contour_amount")?;
+        for variation in [&mut self.prev, &mut self.current, &mut self.next] {
+            let mut values = [0.; 3];
+            reader.f32s_into("humanize_variation", &mut values)?;
+            *variation = StepVariation {
+                level: values[0],
+                timing: values[1],
+                contour: values[2],
+            };
+        }
+
+        Ok(())
+    }
+
+    fn variation(&self, abs_step: usize) -> StepVariation {
+        StepVariation {
+            level: self.bipolar(abs_step, 0),
+            timing: self.bipolar(abs_step, 1),
+            contour: self.bipolar(abs_step, 2),
+        }
+    }
+
+    fn bipolar(&self, abs_step: usize, component: u64) -> f32 {
+        const MANTISSA_BITS: u32 = 24;
+        const SCALE: f32 = 1. / (1_u32 << MANTISSA_BITS) as f32;
+
+        let index = (abs_step as u64)
+            .wrapping_mul(Self::NUM_COMPONENTS)
+            .wrapping_add(component);
+        let x = Self::hash(((self.seed as u64) << 32) ^ index);
+
+        ((x >> (64 - MANTISSA_BITS)) as f32 * SCALE) * 2. - 1.
+    }
+
+    /// splitmix64 finalizer
+    fn hash(key: u64) -> u64 {
+        let mut x = key.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        x ^ (x >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sequence(
+        humanize: &mut Humanize,
+        abs_steps: impl Iterator<Item = usize>,
+    ) -> Vec<StepVariation> {
+        abs_steps
+            .map(|abs_step| {
+                humanize.set_step(abs_step);
+                humanize.current()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_humanize_is_deterministic_per_seed() {
+        let mut humanize = Humanize::new();
+        humanize.set_seed(42);
+        let first = sequence(&mut humanize, 0..16);
+
+        // Each step only depends on the seed and its position.
+        assert_eq!(first, sequence(&mut humanize, 0..16));
+        let backwards = sequence(&mut humanize, (0..16).rev());
+        assert_eq!(first, backwards.into_iter().rev().collect::<Vec<_>>());
+
+        humanize.set_step(5);
+        assert_eq!(humanize.prev(), first[4]);
+        assert_eq!(humanize.next(), first[6]);
+
+        humanize.set_seed(43);
+        assert_ne!(first, sequence(&mut humanize, 0..16));
+
+        for el in first {
+            assert!((-1. ..1.).contains(&el.level));
+            assert!((-1. ..1.).contains(&el.timing));
+            assert!((-1. ..1.).contains(&el.contour));
+        }
+    }
+}
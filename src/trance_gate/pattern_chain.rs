@@ -0,0 +1,215 @@
+// Copyright(c) 2022 Hansen Audio.
+
+use crate::math;
+use crate::state::{impl_state_enum, StateError, StateReader, StateWriter};
+use alloc::vec::Vec;
+
+const MAX_NUM_ENTRIES: usize = 64;
+
+/// Defines what the length of a pattern chain entry counts.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainLengthUnit {
+    /// Number of times the pattern is repeated, switching at pattern end.
+    Repeats,
+    /// Number of bars the pattern is played, switching at bar boundaries.
+    Bars,
+}
+
+impl_state_enum!(
+    ChainLengthUnit,
+    [ChainLengthUnit::Repeats, ChainLengthUnit::Bars]
+);
+
+#[derive(Debug, Clone, Copy)]
+struct ChainEntry {
+    pattern: usize,
+    length: usize,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct PatternChain {
+    entries: Vec<ChainEntry>,
+    unit: ChainLengthUnit,
+    entry_index: usize,
+    entry_step: usize,
+}
+
+impl PatternChain {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::with_capacity(MAX_NUM_ENTRIES),
+            unit: ChainLengthUnit::Repeats,
+            entry_index: 0,
+            entry_step: 0,
+        }
+    }
+
+    pub fn push(&mut self, pattern: usize, length: usize) {
+        if self.entries.len() >= MAX_NUM_ENTRIES {
+            return;
+        }
+
+        self.entries.push(ChainEntry {
+            pattern,
+            length: length.max(1),
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.entry_index = 0;
+        self.entry_step = 0;
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.entries.is_empty()
+    }
+
+    pub fn unit(&self) -> ChainLengthUnit {
+        self.unit
+    }
+
+    pub fn set_unit(&mut self, unit: ChainLengthUnit) {
+        self.unit = unit;
+    }
+
+    pub fn pattern(&self) -> Option<usize> {
+        self.entries.get(self.entry_index).map(|el| el.pattern)
+    }
+
+    /// Moves the chain on to `abs_step`, the number of steps since the chain
+    /// started. Returns true if an entry starts at this step.
+    pub fn advance(&mut self, abs_step: usize, steps_per_unit: f64) -> bool {
+        self.is_active() && self.locate(abs_step, steps_per_unit) == 0
+    }
+
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.enumeration("chain_unit", self.unit);
+        writer.usize("chain_num_entries", self.entries.len());
+        for entry in &self.entries {
+            writer.usize("chain_pattern", entry.pattern);
+            writer.usize("chain_length", entry.length);
+        }
+        writer.usize("chain_entry_index", self.entry_index);
+        writer.usize("chain_entry_step", self.entry_step);
+    }
+
+    pub fn read_state(
+        &mut self,
+        reader: &mut StateReader,
+        num_patterns: usize,
+    ) -> Result<(), StateError> {
+        self.unit = reader.enumeration("chain_unit")?;
+
+        let num_entries = reader.usize("chain_num_entries")?;
+        if num_entries > MAX_NUM_ENTRIES {
+            return Err(StateError::InvalidValue);
+        }
+
+        self.entries.clear();
+        for _ in 0..num_entries {
+            let pattern = reader.usize("chain_pattern")?;
+            let length = reader.usize("chain_length")?;
+            if pattern >= num_patterns || length == 0 {
+                return Err(StateError::InvalidValue);
+            }
+            self.entries.push(ChainEntry { pattern, length });
+        }
+
+        self.entry_index = reader.usize("chain_entry_index")?;
+        self.entry_step = reader.usize("chain_entry_step")?;
+        if self.entry_index > 0 && self.entry_index >= self.entries.len() {
+            return Err(StateError::InvalidValue);
+        }
+
+        Ok(())
+    }
+
+    /// Moves the chain to the entry which is playing at the absolute step
+    /// position. Returns the number of steps elapsed inside that entry.
+    ///
+    /// `steps_per_unit` may be fractional, e.g. for dotted steps counted in
+    /// bars. A step then belongs to the entry in which it starts, so the
+    /// entries follow the unit grid without accumulating rounding errors.
+    pub fn locate(&mut self, abs_step: usize, steps_per_unit: f64) -> usize {
+        // Absorbs the rounding of step lengths like 1/12 in f32.
+        const STEP_TOLERANCE: f64 = 1e-3;
+
+        let total_units: usize = self.entries.iter().map(|el| el.length).sum();
+        let total_steps = total_units as f64 * steps_per_unit;
+        if total_steps <= 0. {
+            return 0;
+        }
+
+        let abs_step_f = abs_step as f64 + STEP_TOLERANCE;
+        let cycle_start = math::floor(abs_step_f / total_steps) * total_steps;
+        let mut entry_start = cycle_start;
+        for (index, entry) in self.entries.iter().enumerate() {
+            let entry_end = entry_start + entry.length as f64 * steps_per_unit;
+            if abs_step_f < entry_end || index + 1 == self.entries.len() {
+                self.entry_index = index;
+                self.entry_step = math::floor(abs_step_f - entry_start) as usize;
+                break;
+            }
+            entry_start = entry_end;
+        }
+
+        self.entry_step
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pattern_chain_advance() {
+        const STEP_COUNT: usize = 4;
+        let mut chain = PatternChain::new();
+        chain.push(0, 2);
+        chain.push(1, 1);
+
+        let mut patterns = Vec::new();
+        for abs_step in 0..(STEP_COUNT * 4) {
+            patterns.push(chain.pattern().unwrap());
+            chain.advance(abs_step + 1, STEP_COUNT as f64);
+        }
+
+        assert_eq!(
+            patterns,
+            [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 0, 0, 0, 0].to_vec()
+        );
+    }
+
+    #[test]
+    fn test_pattern_chain_locate() {
+        const STEPS_PER_BAR: f64 = 8.;
+        let mut chain = PatternChain::new();
+        chain.set_unit(ChainLengthUnit::Bars);
+        chain.push(2, 1);
+        chain.push(3, 2);
+
+        assert_eq!(chain.locate(11, STEPS_PER_BAR), 3);
+        assert_eq!(chain.pattern(), Some(3));
+
+        assert_eq!(chain.locate(24 + 5, STEPS_PER_BAR), 5);
+        assert_eq!(chain.pattern(), Some(2));
+
+        // Dotted 1/16 steps, a bar lasts 10 2/3 steps. Step 11 is the first
+        // one starting in the second bar, step 32 starts the fourth bar.
+        const DOTTED_STEPS_PER_BAR: f64 = 32. / 3.;
+        assert_eq!(chain.locate(10, DOTTED_STEPS_PER_BAR), 10);
+        assert_eq!(chain.pattern(), Some(2));
+        assert_eq!(chain.locate(11, DOTTED_STEPS_PER_BAR), 0);
+        assert_eq!(chain.pattern(), Some(3));
+        assert_eq!(chain.locate(32, DOTTED_STEPS_PER_BAR), 0);
+        assert_eq!(chain.pattern(), Some(2));
+        assert_eq!(chain.locate(43, DOTTED_STEPS_PER_BAR), 0);
+        assert_eq!(chain.pattern(), Some(3));
+    }
+}
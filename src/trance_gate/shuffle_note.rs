@@ -2,20 +2,20 @@
 
 pub fn is_shuffle_note(note_index: usize, note_len: f32) -> bool {
     if note_len == 1. / 16. {
-        return is_even(note_index + 1, 2);
+        is_even(note_index + 1, 2)
     } else if note_len == 1. / 32. {
-        return is_even(note_index + 2, 4);
+        is_even(note_index + 2, 4)
     } else if note_len == 1. / 64. {
-        return is_even(note_index + 4, 8);
+        is_even(note_index + 4, 8)
     } else if note_len == 1. / 128. {
-        return is_even(note_index + 8, 16);
+        is_even(note_index + 8, 16)
     } else {
-        return false;
+        false
     }
 }
 
 fn is_odd(value: usize, divider: usize) -> bool {
-    value % divider != 0
+    !value.is_multiple_of(divider)
 }
 
 fn is_even(value: usize, divider: usize) -> bool {
@@ -28,26 +28,22 @@ mod tests {
 
     #[test]
     fn tests_is_shuffle_note_16() {
-        let mut step_index = 0;
         const NOTE_LEN: f32 = 1. / 16.;
         const TEST_RESULTS: [bool; 8] = [false, true, false, true, false, true, false, true];
-        for r in TEST_RESULTS {
+        for (step_index, r) in TEST_RESULTS.into_iter().enumerate() {
             assert_eq!(is_shuffle_note(step_index, NOTE_LEN), r);
-            step_index += 1;
         }
     }
 
     #[test]
     fn tests_is_shuffle_note_32() {
-        let mut step_index = 0;
         const NOTE_LEN: f32 = 1. / 32.;
         const TEST_RESULTS: [bool; 11] = [
             false, false, true, false, false, false, true, false, false, false, true,
         ];
 
-        for r in TEST_RESULTS {
+        for (step_index, r) in TEST_RESULTS.into_iter().enumerate() {
             assert_eq!(is_shuffle_note(step_index, NOTE_LEN), r);
-            step_index += 1;
         }
     }
 }
@@ -1,6 +1,7 @@
 // Copyright(c) 2022 Hansen Audio.
 
 use super::shuffle_note::is_shuffle_note;
+use crate::state::{StateError, StateReader, StateWriter};
 
 #[derive(Debug, Clone)]
 pub(super) struct Step {
@@ -29,6 +30,10 @@ impl Step {
         self.pos
     }
 
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
     pub fn is_shuffle(&self) -> bool {
         self.is_shuffle
     }
@@ -44,4 +49,17 @@ impl Step {
     pub fn set_note_len(&mut self, note_len: f32) {
         self.is_shuffle = is_shuffle_note(self.pos(), note_len);
     }
+
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.usize("step_pos", self.pos);
+        writer.usize("step_count", self.count);
+        writer.bool("step_is_shuffle", self.is_shuffle);
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.pos = reader.usize("step_pos")?;
+        self.count = reader.usize("step_count")?;
+        self.is_shuffle = reader.bool("step_is_shuffle")?;
+        Ok(())
+    }
 }